@@ -11,3 +11,4 @@ export * from "./cells/dff.mjs";
 export * from "./cells/memory.mjs";
 export * from "./cells/fsm.mjs";
 export * from "./cells/display7.mjs";
+export * from "./cells/yosys.mjs";
@@ -5,7 +5,64 @@ import _ from 'lodash';
 import * as joint from 'jointjs';
 import { Box, BoxView } from './base.mjs';
 import * as help from '../help.mjs';
-import { Vector3vl, Mem3vl } from '3vl';
+import { Vector3vl } from '3vl';
+
+// Memory contents keyed by address. Words never written read as `fill`, so only
+// the used part of a large address space takes up space.
+export class SparseMem3vl {
+    constructor(bits, words, fill) {
+        this.bits = bits;
+        this.words = words;
+        this.fill = fill;
+        this._data = new Map();
+    }
+    static fromJSON(bits, words, fill, data) {
+        const mem = new SparseMem3vl(bits, words, fill);
+        let addr = 0;
+        for (let n = 0; n < data.length; n++) {
+            if (typeof data[n] == 'string') {
+                mem.set(addr++, Vector3vl.fromBin(data[n], bits));
+                continue;
+            }
+            const count = data[n++];
+            const val = Vector3vl.fromBin(data[n], bits);
+            if (val.eq(fill)) {
+                addr += count;
+                continue;
+            }
+            for (let k = 0; k < count; k++)
+                mem.set(addr++, val);
+        }
+        return mem;
+    }
+    get(addr) {
+        return this._data.get(addr) || this.fill;
+    }
+    set(addr, val) {
+        if (addr < 0 || addr >= this.words) return;
+        if (val.eq(this.fill))
+            this._data.delete(addr);
+        else
+            this._data.set(addr, val);
+    }
+    toJSON() {
+        const out = [];
+        let next = 0;
+        for (const addr of Array.from(this._data.keys()).sort((a, b) => a - b)) {
+            if (addr > next)
+                out.push(addr - next, this.fill.toBin());
+            out.push(this._data.get(addr).toBin());
+            next = addr + 1;
+        }
+        return out;
+    }
+};
+
+function memoryFill(memfill, bits) {
+    if (memfill === undefined) return Vector3vl.zeros(bits);
+    if (memfill == 'x') return Vector3vl.xes(bits);
+    return Vector3vl.fromBin(memfill, bits);
+}
 
 // Memory cell
 export const Memory = Box.define('Memory', {
@@ -78,7 +135,7 @@ export const Memory = Box.define('Memory', {
             );
             if ('enable_polarity' in port) {
                 num++;
-                ports.push({ id: portname + 'en', group: 'in', dir: 'in', bits: port.no_bit_enable ? 1 : bits, portlabel: 'en', polarity: port.enable_polarity, labelled: true });
+                ports.push({ id: portname + 'en', group: 'in', dir: 'in', bits: port.no_bit_enable ? 1 : port.byte_enable ? Math.ceil(bits / 8) : bits, portlabel: 'en', polarity: port.enable_polarity, labelled: true });
             }
             if ('clock_polarity' in port) {
                 num++;
@@ -121,14 +178,14 @@ export const Memory = Box.define('Memory', {
         const memdata = this.get('memdata');
         
         if (!words) {
-            words = 1 << this.get('abits');
+            words = 2 ** this.get('abits');
             this.prop('words', words, { init: true });
         }
+        const fill = memoryFill(this.get('memfill'), bits);
         if (memdata)
-            this.memdata = Mem3vl.fromJSON(bits, memdata);
+            this.memdata = SparseMem3vl.fromJSON(bits, words, fill, memdata);
         else
-            this.memdata = new Mem3vl(bits, words);
-        console.assert(this.memdata.words == words);
+            this.memdata = new SparseMem3vl(bits, words, fill);
         
         this.last_clk = {};
         for (const [portname, port] of this._memports()) {
@@ -163,7 +220,9 @@ export const Memory = Box.define('Memory', {
         const write_value = (portname, port, oldval, val) => {
             if (port.no_bit_enable || !('enable_polarity' in port))
                 return val;
-            const mask = port.enable_polarity ? data[portname + 'en'] : data[portname + 'en'].not();
+            let mask = port.enable_polarity ? data[portname + 'en'] : data[portname + 'en'].not();
+            if (port.byte_enable)
+                mask = Vector3vl.fromArray(mask.toArray().flatMap(b => Array(8).fill(b)).slice(0, bits));
             return val.and(mask).or(oldval.and(mask.not()));
         };
         const do_read = (portname, port) => {
@@ -257,7 +316,7 @@ export const Memory = Box.define('Memory', {
         params.memdata = this.memdata.toJSON();
         return params;
     },
-    _gateParams: Box.prototype._gateParams.concat(['bits', 'abits', 'rdports', 'wrports', 'words', 'offset']),
+    _gateParams: Box.prototype._gateParams.concat(['bits', 'abits', 'rdports', 'wrports', 'words', 'offset', 'memfill']),
     _unsupportedPropChanges: Box.prototype._unsupportedPropChanges.concat(['bits', 'abits', 'rdports', 'wrports', 'words', 'offset']),
     _operationHelpers: Box.prototype._operationHelpers.concat(['_memrdports', '_memwrports', '_memports', '_calcaddr'])
 });
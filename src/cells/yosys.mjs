@@ -0,0 +1,263 @@
+"use strict";
+
+import { Box, BoxView } from './base.mjs';
+import { Vector3vl } from '3vl';
+
+// Yosys internal cells, with the ports (`A`, `B`, `S`, `Y`) and semantics of the Yosys
+// simulation library. Parameters are kept as in the netlist, as numbers or binary strings.
+
+function paramNum(value, deflt) {
+    if (typeof value == 'number') return value;
+    if (typeof value == 'string') {
+        const num = parseInt(value.slice(-32), 2);
+        return isNaN(num) ? deflt : num;
+    }
+    return deflt;
+}
+
+function paramBits(value, bits) {
+    if (typeof value == 'number') return Vector3vl.fromNumber(value, bits);
+    if (typeof value == 'string') return Vector3vl.fromBin(value, bits);
+    return Vector3vl.xes(bits);
+}
+
+// Bitwise merge of two candidate values: bits on which they agree are kept, others become x.
+function merge(l, r) {
+    const rs = r.toArray();
+    return Vector3vl.fromArray(l.toArray().map((v, n) => v == rs[n] ? v : 0));
+}
+
+// Entry of `table` at the index given by `sel`, for `2 ** sel.bits` entries. Select bits
+// are taken from the lowest: a defined bit keeps one of each pair of neighbouring entries
+// and an undefined one merges them, so undefined bits do not add to the cost.
+function select(sel, width, table) {
+    for (const bit of sel.toArray()) {
+        const next = [];
+        for (let i = 0; i < table.length; i += 2) {
+            const l = table[i], r = i + 1 < table.length ? table[i + 1] : l;
+            next.push(bit == 1 ? r : bit == -1 ? l : merge(l, r));
+        }
+        table = next;
+    }
+    return table.length ? table[0] : Vector3vl.xes(width);
+}
+
+// Signed shift amounts saturate well beyond any port width.
+function shiftAmount(b, signed) {
+    if (!b.isFullyDefined) return undefined;
+    const limit = 2 ** 40;
+    const bits = b.toArray();
+    let amount = signed && bits.length > 0 && bits[bits.length - 1] == 1 ? -1 : 0;
+    for (let n = bits.length - 1; n >= 0; n--)
+        amount = Math.min(limit, Math.max(-limit, amount * 2 + (bits[n] == 1 ? 1 : 0)));
+    return amount;
+}
+
+// `$shiftx` reads x outside of `A` and does not extend it.
+function shift(data, params, fillX) {
+    const aSigned = paramNum(params.A_SIGNED, 0) != 0;
+    const yWidth = paramNum(params.Y_WIDTH, 1);
+    const amount = shiftAmount(data.B, paramNum(params.B_SIGNED, 0) != 0);
+    if (amount === undefined) return { Y: Vector3vl.xes(yWidth) };
+
+    const a = data.A.toArray();
+    const [fill, width] = fillX ? [0, a.length] : [-1, Math.max(a.length, yWidth)];
+    const ext = n => n < 0 || n >= width ? fill
+        : n < a.length ? a[n]
+        : aSigned && a.length > 0 ? a[a.length - 1]
+        : -1;
+    return { Y: Vector3vl.fromArray(Array.from({ length: yWidth }, (_, n) => ext(n + amount))) };
+}
+
+// Base class for Yosys cells, with ports given by `yosysPorts`
+export const YosysCell = Box.define('YosysCell', {
+    /* default properties */
+    parameters: {},
+
+    ports: {
+        groups: {
+            'in': {
+                position: Box.prototype._getStackedPosition({ side: 'left' })
+            },
+            'out': {
+                position: Box.prototype._getStackedPosition({ side: 'right' })
+            }
+        }
+    }
+}, {
+    initialize() {
+        const ports = this.yosysPorts(this.get('parameters'));
+        const ins = ports.filter(port => port.dir == 'in').length;
+        this.get('size').height = Math.max(ins, ports.length - ins) * 16 + 8;
+        this.get('ports').items = ports.map(port => ({ ...port, group: port.dir, portlabel: port.id, labelled: true }));
+
+        Box.prototype.initialize.apply(this, arguments);
+    },
+    _gateParams: Box.prototype._gateParams.concat(['parameters']),
+    _unsupportedPropChanges: Box.prototype._unsupportedPropChanges.concat(['parameters'])
+});
+
+// Multiplexer with one-hot selection, `A` when no bit of `S` is set
+export const Pmux = YosysCell.define('Pmux', {}, {
+    yosysPorts(params) {
+        const width = paramNum(params.WIDTH, 1), swidth = paramNum(params.S_WIDTH, 1);
+        return [
+            { id: 'A', dir: 'in', bits: width },
+            { id: 'B', dir: 'in', bits: width * swidth },
+            { id: 'S', dir: 'in', bits: swidth },
+            { id: 'Y', dir: 'out', bits: width }
+        ];
+    },
+    operation(data) {
+        const width = data.A.bits;
+        if (!data.S.isFullyDefined) return { Y: Vector3vl.xes(width) };
+        const hot = data.S.toArray().flatMap((v, n) => v == 1 ? [n] : []);
+        if (hot.length == 0) return { Y: data.A };
+        if (hot.length == 1) return { Y: data.B.slice(hot[0] * width, (hot[0] + 1) * width) };
+        return { Y: Vector3vl.xes(width) };
+    }
+});
+export const PmuxView = BoxView;
+
+// Multiplexer selecting a slice of `A` by the binary value of `S`
+export const Bmux = YosysCell.define('Bmux', {}, {
+    yosysPorts(params) {
+        const width = paramNum(params.WIDTH, 1), swidth = paramNum(params.S_WIDTH, 1);
+        return [
+            { id: 'A', dir: 'in', bits: width * 2 ** swidth },
+            { id: 'S', dir: 'in', bits: swidth },
+            { id: 'Y', dir: 'out', bits: width }
+        ];
+    },
+    operation(data) {
+        const width = data.A.bits / 2 ** data.S.bits;
+        const slices = Array.from({ length: 2 ** data.S.bits }, (_, idx) => data.A.slice(idx * width, (idx + 1) * width));
+        return { Y: select(data.S, width, slices) };
+    }
+});
+export const BmuxView = BoxView;
+
+// Demultiplexer putting `A` in the slice of `Y` selected by `S`, other slices are zero
+export const Demux = YosysCell.define('Demux', {}, {
+    yosysPorts(params) {
+        const width = paramNum(params.WIDTH, 1), swidth = paramNum(params.S_WIDTH, 1);
+        return [
+            { id: 'A', dir: 'in', bits: width },
+            { id: 'S', dir: 'in', bits: swidth },
+            { id: 'Y', dir: 'out', bits: width * 2 ** swidth }
+        ];
+    },
+    operation(data) {
+        const width = data.A.bits;
+        const sel = data.S.toArray();
+        const value = data.S.isFullyDefined ? data.A : merge(data.A, Vector3vl.zeros(width));
+        // Slices whose index agrees with the defined bits of `S` are selected.
+        const slices = Array.from({ length: 2 ** sel.length }, (_, idx) =>
+            sel.every((v, n) => v == 0 || (Math.floor(idx / 2 ** n) % 2 == 1) == (v == 1))
+                ? value : Vector3vl.zeros(width));
+        return { Y: Vector3vl.concat(...slices) };
+    }
+});
+export const DemuxView = BoxView;
+
+// Shifts `A` right by `B`, extending it to `Y_WIDTH`
+export const Shift = YosysCell.define('Shift', {}, {
+    yosysPorts(params) {
+        return [
+            { id: 'A', dir: 'in', bits: paramNum(params.A_WIDTH, 1) },
+            { id: 'B', dir: 'in', bits: paramNum(params.B_WIDTH, 1) },
+            { id: 'Y', dir: 'out', bits: paramNum(params.Y_WIDTH, 1) }
+        ];
+    },
+    operation(data) {
+        return shift(data, this.get('parameters'), false);
+    }
+});
+export const ShiftView = BoxView;
+
+export const ShiftX = Shift.define('ShiftX', {}, {
+    operation(data) {
+        return shift(data, this.get('parameters'), true);
+    }
+});
+export const ShiftXView = BoxView;
+
+// Lookup table indexed by `A`
+export const Lut = YosysCell.define('Lut', {}, {
+    yosysPorts(params) {
+        return [
+            { id: 'A', dir: 'in', bits: paramNum(params.WIDTH, 1) },
+            { id: 'Y', dir: 'out', bits: 1 }
+        ];
+    },
+    operation(data) {
+        const table = paramBits(this.get('parameters').LUT, 2 ** data.A.bits).toArray();
+        return { Y: select(data.A, 1, table.map(v => Vector3vl.fromArray([v]))) };
+    }
+});
+export const LutView = BoxView;
+
+// Sum of products. `TABLE` holds two bits per input of each product term: bit 0
+// requires the input to be 0, bit 1 requires it to be 1.
+export const Sop = YosysCell.define('Sop', {}, {
+    yosysPorts(params) {
+        return [
+            { id: 'A', dir: 'in', bits: paramNum(params.WIDTH, 1) },
+            { id: 'Y', dir: 'out', bits: 1 }
+        ];
+    },
+    operation(data) {
+        const params = this.get('parameters');
+        const a = data.A.toArray();
+        const depth = paramNum(params.DEPTH, 1);
+        const table = paramBits(params.TABLE, 2 * a.length * depth).toArray();
+        let y = -1;
+        for (let term = 0; term < depth; term++) {
+            let product = 1;
+            for (let n = 0; n < a.length; n++) {
+                const base = 2 * (term * a.length + n);
+                if (table[base] == 1) product = Math.min(product, -a[n]);
+                if (table[base + 1] == 1) product = Math.min(product, a[n]);
+            }
+            y = Math.max(y, product);
+        }
+        return { Y: Vector3vl.fromArray([y]) };
+    }
+});
+export const SopView = BoxView;
+
+// Bitwise comparison treating x as a value
+export const BwEqx = YosysCell.define('BwEqx', {}, {
+    yosysPorts(params) {
+        const width = paramNum(params.WIDTH, 1);
+        return [
+            { id: 'A', dir: 'in', bits: width },
+            { id: 'B', dir: 'in', bits: width },
+            { id: 'Y', dir: 'out', bits: width }
+        ];
+    },
+    operation(data) {
+        const b = data.B.toArray();
+        return { Y: Vector3vl.fromArray(data.A.toArray().map((v, n) => v == b[n] ? 1 : -1)) };
+    }
+});
+export const BwEqxView = BoxView;
+
+// Bitwise multiplexer, `B` where `S` is set
+export const BwMux = YosysCell.define('BwMux', {}, {
+    yosysPorts(params) {
+        const width = paramNum(params.WIDTH, 1);
+        return [
+            { id: 'A', dir: 'in', bits: width },
+            { id: 'B', dir: 'in', bits: width },
+            { id: 'S', dir: 'in', bits: width },
+            { id: 'Y', dir: 'out', bits: width }
+        ];
+    },
+    operation(data) {
+        const b = data.B.toArray(), s = data.S.toArray();
+        return { Y: Vector3vl.fromArray(data.A.toArray().map((v, n) =>
+            s[n] == 1 ? b[n] : s[n] == -1 ? v : v == b[n] ? v : 0)) };
+    }
+});
+export const BwMuxView = BoxView;
@@ -50,6 +50,14 @@ export function getCellType(tp) {
         '$pow': cells.Power,
         '$mux': cells.Mux,
         '$pmux': cells.Mux1Hot,
+        '$bmux': cells.Bmux,
+        '$demux': cells.Demux,
+        '$shift': cells.Shift,
+        '$shiftx': cells.ShiftX,
+        '$lut': cells.Lut,
+        '$sop': cells.Sop,
+        '$bweqx': cells.BwEqx,
+        '$bwmux': cells.BwMux,
         '$dff': cells.Dff,
         '$mem': cells.Memory,
         '$fsm': cells.FSM,
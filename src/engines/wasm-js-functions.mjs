@@ -32,6 +32,10 @@ export function postMonitorValue(monitorId, tick, bits, avec, bvec, stopOnTrigge
     postMessage({ type: 'monitorValue', args: [monitorId, tick, { _bits: bits, _avec: avec, _bvec: bvec }, stopOnTrigger, oneShot] });
 }
 
+export function sendMonitorStopped(monitorId, tick) {
+    postMessage({ type: 'monitorStopped', args: [monitorId, tick] });
+}
+
 export function updater_stop() {
     postMessage({ type: "updater_stop" });
 }
@@ -42,4 +46,12 @@ export function sendAck(reqid, response) {
 
 export function sendAlarmReached(alarmId, tick, stopOnAlarm) {
     postMessage({ type: 'alarmReached', args: [alarmId, tick, stopOnAlarm] });
-}
\ No newline at end of file
+}
+
+export function sendBreakpointHit(breakpointId, tick) {
+    postMessage({ type: 'breakpointHit', args: [breakpointId, tick] });
+}
+
+export function mmioWrite(deviceId, offset, bits, avec, bvec) {
+    postMessage({ type: 'mmioWrite', args: [deviceId, offset, { _bits: bits, _avec: avec, _bvec: bvec }] });
+}
@@ -0,0 +1,9 @@
+import * as wasm from "../../pkg/digitaljs_wasm_worker.js"
+
+self.onmessage = (e) => {
+    const { module, memory, receiver } = e.data;
+    wasm.initSync({ module, memory });
+    postMessage({ type: 'ready' });
+    wasm.startRayonWorker(receiver);
+    close();
+}
@@ -8,7 +8,13 @@ let queue = [];
 self.onmessage = (e) => {
     let msg = e.data;
 
-    if (msg.type == "fetch") {
+    if (msg.type == "fetch" && msg.threads) {
+        startThreadPool(msg.data, msg.threads).then(() => {
+            engine = wasm.RustEngine.new();
+            queue.forEach((m) => applyMessage(m))
+            queue = [];
+        });
+    } else if (msg.type == "fetch") {
         wasm.initSync({ module: msg.data });
         engine = wasm.RustEngine.new();
         queue.forEach((m) => applyMessage(m))
@@ -26,6 +32,19 @@ self.onmessage = (e) => {
     }
 }
 
+// Requires a build with the `threads` feature (`npm run wasm-web-threads`).
+async function startThreadPool(bytes, numThreads) {
+    const module = new WebAssembly.Module(bytes);
+    const { memory } = wasm.initSync({ module });
+    const builder = new wasm.ThreadPoolBuilder(numThreads);
+    await Promise.all(Array.from({ length: numThreads }, () => new Promise((resolve) => {
+        const worker = new Worker(new URL('./wasm-thread-worker.mjs', import.meta.url), { type: 'module' });
+        worker.onmessage = () => resolve();
+        worker.postMessage({ module, memory, receiver: builder.receiver() });
+    })));
+    builder.build();
+}
+
 function applyMessage(msg) {
     //console.log(msg);
     if (msg.type == 'start') {
@@ -40,6 +59,8 @@ function applyMessage(msg) {
         _stop();  
     } else if (msg.type == "stop") {
         stop(...msg.args);
+    } else if (msg.type == "query") {
+        query(...msg.args);
     } else if ('arg' in msg)
         engine[msg.type](msg.arg);
     else if ('args' in msg)
@@ -54,6 +75,20 @@ function stop(reqid, sendUpdates) {
     engine._sendAck(reqid);
 }
 
+function query(reqid, name, args) {
+    let response;
+    try {
+        response = engine[name].apply(engine, args);
+    } catch (e) {
+        postMessage({ type: 'ack', args: [reqid, undefined, e instanceof Error ? e.message : String(e)] });
+        return;
+    }
+    const transfer = response && typeof response == 'object'
+        ? Object.values(response).filter(v => ArrayBuffer.isView(v)).map(v => v.buffer)
+        : [];
+    postMessage({ type: 'ack', args: [reqid, response] }, transfer);
+}
+
 function _stop() {
     if (updater) {
         clearInterval(updater);
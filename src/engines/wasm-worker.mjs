@@ -5,7 +5,7 @@ import * as cells from '../cells.mjs';
 import Worker from 'web-worker';
 
 export class WasmWorkerEngine extends BaseEngine {
-    constructor(graph, { workerURL, nodeJs }) {
+    constructor(graph, { workerURL, nodeJs, threads }) {
         super(graph);
         this._running = false;
         this._tickCache = 0;
@@ -15,7 +15,12 @@ export class WasmWorkerEngine extends BaseEngine {
         this._monitors = Object.create(null);
         this._promises = Object.create(null);
         this._alarms = Object.create(null);
+        this._breakpoints = Object.create(null);
+        this._mmioDevices = Object.create(null);
+        this._modules = Object.create(null);
+        this._instances = new Set();
         this._uniqueCounter = 0;
+        this._threads = threads;
         this._worker = workerURL ? new Worker(workerURL) : new Worker(new URL('./wasm-worker-worker.mjs', import.meta.url));
         this._worker.onmessage = (e) => this._handleMessage(e.data);
         
@@ -24,13 +29,13 @@ export class WasmWorkerEngine extends BaseEngine {
         } else {
             fetch(new URL("../../pkg/digitaljs_wasm_worker_bg.wasm", import.meta.url))
                 .then((response) => response.arrayBuffer())
-                .then((bytes) => this._worker.postMessage({ type: "fetch", data: bytes }));
+                .then((bytes) => this._worker.postMessage({ type: "fetch", data: bytes, threads: this._threads }));
         }
         
         this.interval = 10;
         this._addGraph(this._graph);
     }
-    _addGate(graph, gate) {
+    _postGate(graphId, gate) {
         const params = gate.getGateParams();
 
         const inputs = gate.get('inputSignals');
@@ -43,16 +48,54 @@ export class WasmWorkerEngine extends BaseEngine {
                 return { id, dir, bits, avec: outputs[id]._avec, bvec: outputs[id]._bvec }
         });
 
-        this._worker.postMessage({ type: 'addGate', args: [graph.cid, gate.id, params, ports] });
-        
+        this._worker.postMessage({ type: 'addGate', args: [graphId, gate.id, params, ports] });
+    }
+    _postLink(graphId, link) {
+        if (!link.get('warning') && link.get('source').id && link.get('target').id)
+            this._worker.postMessage({ type: 'addLink', args: [graphId, link.id, link.get('source'), link.get('target')] });
+    }
+    // Subcircuits without nested subcircuits are copied in the engine from a module
+    // definition built once per cell type.
+    _isModuleInstance(gate) {
+        return gate instanceof cells.Subcircuit &&
+            !gate.get('graph').getElements().some(elem => elem instanceof cells.Subcircuit);
+    }
+    _moduleFor(gate) {
+        const celltype = gate.get('celltype');
+        if (!(celltype in this._modules)) {
+            const moduleId = 'module:' + celltype;
+            const subgraph = gate.get('graph');
+            this._worker.postMessage({ type: 'addModule', arg: moduleId });
+            for (const elem of subgraph.getElements())
+                this._postGate(moduleId, elem);
+            for (const link of subgraph.getLinks())
+                this._postLink(moduleId, link);
+            this._modules[celltype] = moduleId;
+        }
+        return this._modules[celltype];
+    }
+    _addGate(graph, gate) {
+        if (!this._instances.has(graph.cid))
+            this._postGate(graph.cid, gate);
+
+        const instance = this._isModuleInstance(gate);
+        if (instance) this._instances.add(gate.get('graph').cid);
         super._addGate(graph, gate);
+        if (instance) this._instances.delete(gate.get('graph').cid);
+
         if (gate instanceof cells.Subcircuit) {
             const ioMap = Object.entries(gate.get('circuitIOmap')).map(([port, io_id]) => { return { port, io_id }})
 
-            this._worker.postMessage({
-                type: 'addSubcircuit',
-                args: [graph.cid, gate.id, gate.get('graph').cid, ioMap]
-            });
+            if (instance)
+                this._worker.postMessage({
+                    type: 'instantiateSubcircuit',
+                    args: [graph.cid, gate.id, this._moduleFor(gate), gate.get('graph').cid, ioMap]
+                });
+            else
+                this._worker.postMessage({
+                    type: 'addSubcircuit',
+                    args: [graph.cid, gate.id, gate.get('graph').cid, ioMap]
+                });
         }
         if (gate instanceof cells.Input && gate.get('mode') != 0) {
             this.listenTo(gate, 'change:outputSignals', (gate, sigs) => {
@@ -84,16 +127,29 @@ export class WasmWorkerEngine extends BaseEngine {
         }
     }
     _addLink(graph, link) {
-        if (!link.get('warning') && link.get('source').id && link.get('target').id)
-            this._worker.postMessage({ type: 'addLink', args: [graph.cid, link.id, link.get('source'), link.get('target')] });
+        if (!this._instances.has(graph.cid))
+            this._postLink(graph.cid, link);
         super._addLink(graph, link);
     }
     _addGraph(graph) {
         this._observers[graph.cid] = 0;
         this._graphs[graph.cid] = graph;
-        this._worker.postMessage({ type: 'addGraph', args: [graph.cid] });
+        if (!this._instances.has(graph.cid))
+            this._worker.postMessage({ type: 'addGraph', args: [graph.cid] });
         super._addGraph(graph);
     }
+    _dropGraphs(rootId, graphIds) {
+        this._worker.postMessage({ type: 'removeGraph', arg: rootId });
+        for (const graphId of graphIds) {
+            const graph = this._graphs[graphId];
+            for (const gate of graph.getElements())
+                this.stopListening(gate);
+            this.stopListening(graph);
+            this._instances.delete(graphId);
+            delete this._graphs[graphId];
+            delete this._observers[graphId];
+        }
+    }
     _removeGate(graph, gate) {
         this._worker.postMessage({ type: 'removeGate', args: [graph.cid, gate.id] });
         super._removeGate(graph, link);
@@ -201,9 +257,45 @@ export class WasmWorkerEngine extends BaseEngine {
     monitor(gate, port, callback, options) {
         const monitorId = this._generateUniqueId();
         this._monitors[monitorId] = callback;
-        this._worker.postMessage({ type: 'monitor', args: [gate.graph.cid, gate.id, port, monitorId, options] });
+        this._worker.postMessage({ type: 'monitor', args: [gate.graph.cid, gate.id, port, monitorId, this._monitorOptions(options)] });
         return monitorId;
     }
+    monitorPath(path, callback, options) {
+        const monitorId = this._generateUniqueId();
+        this._monitors[monitorId] = callback;
+        this._worker.postMessage({ type: 'monitorPath', args: [this._graph.cid + '.' + path, monitorId, this._monitorOptions(options)] });
+        return monitorId;
+    }
+    _monitorOptions(options) {
+        if (!options || !options.trigger) return options;
+        return { ...options, trigger: this._prepareTrigger(options.trigger) };
+    }
+    _prepareTrigger(trigger) {
+        const ret = { ...trigger };
+        if (trigger.path !== undefined)
+            ret.path = this._graph.cid + '.' + trigger.path;
+        if (trigger.gate && typeof trigger.gate == 'object') {
+            ret.graph = trigger.gate.graph.cid;
+            ret.gate = trigger.gate.id;
+        }
+        if (trigger.operands)
+            ret.operands = trigger.operands.map(t => this._prepareTrigger(t));
+        return ret;
+    }
+    changeInputPath(path, sig) {
+        this._worker.postMessage({ type: 'changeInputPath', args: [this._graph.cid + '.' + path, sig] });
+    }
+    resolvePath(path) {
+        return this._query('resolvePath', this._graph.cid + '.' + path);
+    }
+    async listHierarchy() {
+        const prefix = this._graph.cid + '.';
+        const paths = await this._query('listHierarchy', this._graph.cid);
+        return paths.map(path => path.slice(prefix.length));
+    }
+    drainMonitor(monitorId) {
+        return this._query('drainMonitor', monitorId);
+    }
     unmonitor(monitorId) {
         if (!(monitorId in this._monitors)) return;
         this._worker.postMessage({ type: 'unmonitor', arg: monitorId });
@@ -221,13 +313,155 @@ export class WasmWorkerEngine extends BaseEngine {
         this._worker.postMessage({ type: 'unalarm', arg: alarmId });
         delete this._alarms[alarmId];
     }
+    startCoverage() {
+        this._worker.postMessage({ type: 'startCoverage' });
+    }
+    stopCoverage() {
+        this._worker.postMessage({ type: 'stopCoverage' });
+    }
+    coverageReport(gate) {
+        if (gate) return this._query('coverageReport', gate.graph.cid, gate.id);
+        return this._query('coverageReport', this._graph.cid);
+    }
+    startPowerEstimate() {
+        this._worker.postMessage({ type: 'startPowerEstimate' });
+    }
+    stopPowerEstimate() {
+        this._worker.postMessage({ type: 'stopPowerEstimate' });
+    }
+    setCapacitance(cellType, weight) {
+        this._worker.postMessage({ type: 'setCapacitance', args: [cellType, weight] });
+    }
+    powerReport() {
+        return this._query('powerReport', this._graph.cid);
+    }
+    staticTiming() {
+        return this._query('staticTiming', this._graph.cid);
+    }
+    addFault(gate, port, options) {
+        const faultId = this._generateUniqueId();
+        this._worker.postMessage({ type: 'addFault', args: [gate.graph.cid, gate.id, port, faultId, options] });
+        return faultId;
+    }
+    addMemoryFault(gate, address, options) {
+        const faultId = this._generateUniqueId();
+        this._worker.postMessage({ type: 'addMemoryFault', args: [gate.graph.cid, gate.id, address, faultId, options] });
+        return faultId;
+    }
+    faultCampaign(options) {
+        return this._query('faultCampaign', this._graph.cid, options);
+    }
+    async checkEquivalence(reference, options = {}) {
+        const added = reference.cid in this._graphs ? null : new Set(Object.keys(this._graphs));
+        if (added) this._addGraph(reference);
+        let result;
+        try {
+            result = await this._query('checkEquivalence', this._graph.cid, reference.cid, options);
+        } finally {
+            // The reference is only needed for the comparison, so it leaves the live simulation.
+            if (added) this._dropGraphs(reference.cid, Object.keys(this._graphs).filter(cid => !added.has(cid)));
+        }
+        if (result.mismatch) {
+            const m = result.mismatch;
+            m.expected = Vector3vl.fromClonable(m.expected);
+            m.actual = Vector3vl.fromClonable(m.actual);
+            m.sequence = m.sequence.map(vector => vector.map(sig => Vector3vl.fromClonable(sig)));
+        }
+        return result;
+    }
+    removeFault(faultId) {
+        this._worker.postMessage({ type: 'removeFault', arg: faultId });
+    }
+    setGateDelay(gate, rise, fall) {
+        this._worker.postMessage({ type: 'setGateDelay', args: [gate.graph.cid, gate.id, rise, fall] });
+    }
+    setPortDelay(gate, port, rise, fall) {
+        this._worker.postMessage({ type: 'setPortDelay', args: [gate.graph.cid, gate.id, port, rise, fall] });
+    }
+    setDelayModel(gate, model) {
+        this._worker.postMessage({ type: 'setDelayModel', args: [gate.graph.cid, gate.id, model] });
+    }
+    startGlitchDetection(maxWidth) {
+        this._worker.postMessage({ type: 'startGlitchDetection', arg: maxWidth });
+    }
+    stopGlitchDetection() {
+        this._worker.postMessage({ type: 'stopGlitchDetection' });
+    }
+    async drainGlitches() {
+        const { glitches, dropped } = await this._query('drainGlitches');
+        return {
+            glitches: glitches.map(({ graphId, gateId, port, tick, width }) =>
+                ({ gate: this._findGateByIds(graphId, gateId), port, tick, width })),
+            dropped
+        };
+    }
+    fsmCoverage(gate) {
+        return this._query('fsmCoverage', gate.graph.cid, gate.id);
+    }
+    resetFsmCoverage(gate) {
+        this._worker.postMessage({ type: 'resetFsmCoverage', args: [gate.graph.cid, gate.id] });
+    }
+    loadMemoryImage(gate, format, data) {
+        this._worker.postMessage({ type: 'loadMemoryImage', args: [gate.graph.cid, gate.id, format, data] });
+    }
+    readMemory(gate, start = 0, count) {
+        return this._query('readMemory', gate.graph.cid, gate.id, start, count);
+    }
+    dumpMemoryImage(gate, format) {
+        return this._query('dumpMemoryImage', gate.graph.cid, gate.id, format);
+    }
+    addMmioRegion(gate, start, count, onWrite) {
+        const deviceId = this._generateUniqueId();
+        this._mmioDevices[deviceId] = onWrite;
+        this._worker.postMessage({ type: 'addMmioRegion', args: [gate.graph.cid, gate.id, deviceId, start, count] });
+        return deviceId;
+    }
+    addMmioConsole(gate, address) {
+        const deviceId = this._generateUniqueId();
+        this._worker.postMessage({ type: 'addMmioConsole', args: [gate.graph.cid, gate.id, deviceId, address] });
+        return deviceId;
+    }
+    setMmioValue(gate, deviceId, offset, sig) {
+        this._worker.postMessage({ type: 'setMmioValue', args: [gate.graph.cid, gate.id, deviceId, offset, sig] });
+    }
+    drainMmio(gate, deviceId) {
+        return this._query('drainMmio', gate.graph.cid, gate.id, deviceId);
+    }
+    removeMmioRegion(gate, deviceId) {
+        this._worker.postMessage({ type: 'removeMmioRegion', args: [gate.graph.cid, gate.id, deviceId] });
+        delete this._mmioDevices[deviceId];
+    }
+    breakpoint(expr, callback) {
+        const breakpointId = this._generateUniqueId();
+        this._breakpoints[breakpointId] = callback;
+        this._worker.postMessage({ type: 'breakpoint', args: [this._graph.cid, breakpointId, expr] });
+        return breakpointId;
+    }
+    unbreakpoint(breakpointId) {
+        if (!(breakpointId in this._breakpoints)) return;
+        this._worker.postMessage({ type: 'unbreakpoint', arg: breakpointId });
+        delete this._breakpoints[breakpointId];
+    }
+    instantiateGraph(graph, count) {
+        return this._query('instantiateGraph', graph.cid, count);
+    }
+    removeInstance(graphId) {
+        this._worker.postMessage({ type: 'removeGraph', arg: graphId });
+    }
+    changeInputs(graphIds, gateId, sigs) {
+        this._worker.postMessage({ type: 'changeInputs', args: [graphIds, gateId, sigs] });
+    }
+    async getOutputs(graphIds, gateId, port = 'in') {
+        const sigs = await this._query('getOutputs', graphIds, gateId, port);
+        return sigs.map(sig => Vector3vl.fromClonable(sig));
+    }
     _handleMessage(msg) {
         switch (msg.type) {
             case "fetchWasm": {
                 fetch(new URL("../../pkg/digitaljs_wasm_worker_bg.wasm", import.meta.url))
                 .then((response) => response.arrayBuffer())
                 .then((bytes) => {
-                    this._worker.postMessage({ type: "fetch", data: bytes });
+                    this._worker.postMessage({ type: "fetch", data: bytes, threads: this._threads });
                 });
                 break;
             }
@@ -293,6 +527,11 @@ export class WasmWorkerEngine extends BaseEngine {
                 this._worker.postMessage({ type: this._running == 'fast' ? 'startFast' : 'start' });
         }
     }
+    _handle_monitorStopped(monitorId, tick) {
+        if (!(monitorId in this._monitors)) return;
+        this._running = false;
+        this.trigger('changeRunning');
+    }
     _handle_alarmReached(alarmId, tick, stopped) {
         const callback = this._alarms[alarmId];
         if (callback == undefined) return;
@@ -306,27 +545,51 @@ export class WasmWorkerEngine extends BaseEngine {
                 this._worker.postMessage({ type: this._running == 'fast' ? 'startFast' : 'start' });
         }
     }
-    _handle_ack(reqid, response) {
-        this._resolvePromise(reqid, response);
+    _handle_breakpointHit(breakpointId, tick) {
+        const callback = this._breakpoints[breakpointId];
+        if (callback == undefined) return;
+        // The engine has already stopped; returning false from the callback resumes it.
+        const ret = callback(tick);
+        if (ret === false && this._running)
+            this._worker.postMessage({ type: this._running == 'fast' ? 'startFast' : 'start' });
+        else if (this._running) {
+            this._running = false;
+            this.trigger('changeRunning');
+        }
+    }
+    _handle_mmioWrite(deviceId, offset, sig) {
+        const callback = this._mmioDevices[deviceId];
+        if (callback) callback(offset, Vector3vl.fromClonable(sig));
+    }
+    _handle_ack(reqid, response, error) {
+        this._resolvePromise(reqid, response, error);
     }
     _findGateByIds(graphId, gateId) {
         const graph = this._graphs[graphId];
         if (graph === undefined) return undefined;
         return graph.getCell(gateId);
     }
+    _query(name, ...args) {
+        const [reqid, promise] = this._generatePromise();
+        this._worker.postMessage({ type: 'query', args: [reqid, name, args] });
+        return promise;
+    }
     _generateUniqueId() {
         return this._uniqueCounter++;
     }
     _generatePromise() {
         const reqid = this._generateUniqueId();
-        return [reqid, new Promise((resolve) => { this._promises[reqid] = resolve; })];
+        return [reqid, new Promise((resolve, reject) => { this._promises[reqid] = { resolve, reject }; })];
     }
-    _resolvePromise(reqid, value) {
+    _resolvePromise(reqid, value, error) {
         if (!this._promises[reqid]) {
             console.warn("Missing promise", reqid);
             return;
         }
-        this._promises[reqid](value);
+        if (error !== undefined)
+            this._promises[reqid].reject(new Error(error));
+        else
+            this._promises[reqid].resolve(value);
         delete this._promises[reqid];
     }
 }
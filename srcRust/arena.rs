@@ -0,0 +1,165 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+use crate::gate::Gate;
+use crate::graph::Graph;
+
+pub type GateId = Id<Gate>;
+pub type GraphId = Id<Graph>;
+
+pub struct Id<T> {
+    index: u32,
+    generation: u32,
+    _marker: PhantomData<fn() -> T>
+}
+
+impl<T> Clone for Id<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Id<T> { }
+
+#[cfg(test)]
+impl<T> Id<T> {
+    pub fn from_index(index: u32) -> Id<T> {
+        Id { index, generation: 0, _marker: PhantomData }
+    }
+}
+
+impl<T> PartialEq for Id<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Id<T> { }
+
+impl<T> Hash for Id<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Id<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}v{}", self.index, self.generation)
+    }
+}
+
+#[derive(Clone)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>
+}
+
+#[derive(Clone)]
+pub struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Arena<T> {
+        Arena { slots: Vec::new(), free: Vec::new() }
+    }
+
+    pub fn insert(&mut self, value: T) -> Id<T> {
+        let index = match self.free.pop() {
+            Some(i) => {
+                self.slots[i as usize].value = Some(value);
+                i
+            },
+            None => {
+                self.slots.push(Slot { generation: 0, value: Some(value) });
+                (self.slots.len() - 1) as u32
+            }
+        };
+
+        Id { index, generation: self.slots[index as usize].generation, _marker: PhantomData }
+    }
+
+    pub fn remove(&mut self, id: Id<T>) -> Option<T> {
+        let slot = self.slots.get_mut(id.index as usize)?;
+        if slot.generation != id.generation { return None; }
+
+        let value = slot.value.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(id.index);
+        Some(value)
+    }
+
+    pub fn get(&self, id: Id<T>) -> Option<&T> {
+        match self.slots.get(id.index as usize) {
+            Some(s) if s.generation == id.generation => s.value.as_ref(),
+            _ => None
+        }
+    }
+
+    pub fn get_mut(&mut self, id: Id<T>) -> Option<&mut T> {
+        match self.slots.get_mut(id.index as usize) {
+            Some(s) if s.generation == id.generation => s.value.as_mut(),
+            _ => None
+        }
+    }
+
+    pub fn get_disjoint_mut(&mut self, ids: &[Id<T>]) -> Vec<Option<&mut T>> {
+        let mut order = (0..ids.len()).collect::<Vec<usize>>();
+        order.sort_by_key(|i| ids[*i].index);
+
+        let mut result = ids.iter().map(|_| None).collect::<Vec<Option<&mut T>>>();
+        let mut rest = &mut self.slots[..];
+        let mut offset = 0usize;
+
+        for i in order {
+            let id = ids[i];
+            let index = id.index as usize;
+            if index < offset || index >= offset + rest.len() { continue; }
+
+            let (_, tail) = std::mem::take(&mut rest).split_at_mut(index - offset);
+            let (slot, tail) = tail.split_first_mut().unwrap();
+            rest = tail;
+            offset = index + 1;
+
+            if slot.generation == id.generation {
+                result[i] = slot.value.as_mut();
+            }
+        }
+
+        result
+    }
+
+    pub fn contains(&self, id: Id<T>) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Id<T>, &T)> {
+        self.slots.iter().enumerate().filter_map(|(i, s)| {
+            s.value.as_ref().map(|v| (Id { index: i as u32, generation: s.generation, _marker: PhantomData }, v))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Id<T>, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(i, s)| {
+            let generation = s.generation;
+            s.value.as_mut().map(|v| (Id { index: i as u32, generation, _marker: PhantomData }, v))
+        })
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Arena::new()
+    }
+}
@@ -0,0 +1,550 @@
+use crate::cell_arith::BigUInt;
+use crate::monitor::PortRef;
+use crate::vector3vl::Vec3vl;
+
+// Breakpoint expressions, e.g. `cpu.pc == 0x40 && mem.out[7:4] != 4'b1010`.
+// Signals are hierarchical paths, resolved once when the breakpoint is set.
+// Operators follow Verilog: arithmetic and bitwise results take the width of
+// the wider operand, comparisons and logical operators are one bit wide,
+// and any undefined bit makes an arithmetic or comparison result x.
+pub struct Breakpoint {
+    expr: Expr,
+    ports: Vec<PortRef>,
+    last: bool
+}
+
+struct Expr {
+    kind: ExprKind,
+    bits: u32
+}
+
+enum ExprKind {
+    Signal(PortRef),
+    Const(Vec3vl),
+    Slice(Box<Expr>, u32),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>)
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(String),
+    Op(&'static str)
+}
+
+const OPERATORS: [&str; 25] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>",
+    "<", ">", "+", "-", "*", "/", "%", "&", "|", "^", "!", "~",
+    "(", ")", "[", "]", ":"
+];
+
+const PRECEDENCE: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"]
+];
+
+pub type Resolver<'a> = dyn Fn(&str) -> Result<(PortRef, u32), String> + 'a;
+
+impl Breakpoint {
+    pub fn compile(source: &str, resolve: &Resolver) -> Result<Breakpoint, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0, resolve, ports: Vec::new() };
+        let expr = parser.expr(0)?;
+        if let Some(t) = parser.peek() {
+            return Err(format!("Unexpected {} in breakpoint expression", describe(t)));
+        }
+
+        Ok(Breakpoint { expr, ports: parser.ports, last: false })
+    }
+
+    pub fn ports(&self) -> &Vec<PortRef> {
+        &self.ports
+    }
+
+    pub fn eval(&self, value: &dyn Fn(&PortRef) -> Option<Vec3vl>) -> Result<Vec3vl, String> {
+        self.expr.eval(value)
+    }
+
+    // Returns true only when the expression goes from false (or x) to true.
+    pub fn update(&mut self, value: &dyn Fn(&PortRef) -> Option<Vec3vl>) -> bool {
+        let now = match self.eval(value) {
+            Ok(v) => v.reduce_or().lsb() == 1,
+            Err(_) => false
+        };
+        let hit = now && !self.last;
+        self.last = now;
+        hit
+    }
+}
+
+impl Expr {
+    fn eval(&self, value: &dyn Fn(&PortRef) -> Option<Vec3vl>) -> Result<Vec3vl, String> {
+        match &self.kind {
+            ExprKind::Signal(p) => Ok(value(p).unwrap_or_else(|| Vec3vl::xes(self.bits))),
+            ExprKind::Const(v) => Ok(v.clone()),
+            ExprKind::Slice(e, low) => {
+                let mut v = e.eval(value)?.slice(*low, low + self.bits)?;
+                v.normalize();
+                Ok(v)
+            },
+            ExprKind::Unary(op, e) => {
+                let v = e.eval(value)?;
+                Ok(match *op {
+                    "!" => v.reduce_or().not(),
+                    "~" => v.not(),
+                    _ => arith("-", &Vec3vl::zeros(v.bits), &v, v.bits)
+                })
+            },
+            ExprKind::Binary(op, l, r) => {
+                let l = l.eval(value)?;
+                let r = r.eval(value)?;
+                binary(op, l, r, self.bits)
+            }
+        }
+    }
+}
+
+fn binary(op: &str, l: Vec3vl, r: Vec3vl, bits: u32) -> Result<Vec3vl, String> {
+    match op {
+        "&&" => l.reduce_or().and(&r.reduce_or()),
+        "||" => l.reduce_or().or(&r.reduce_or()),
+        "&" | "|" | "^" => {
+            let (l, r) = (extend(&l, bits)?, extend(&r, bits)?);
+            match op {
+                "&" => l.and(&r),
+                "|" => l.or(&r),
+                _ => l.xor(&r)
+            }
+        },
+        "<<" | ">>" => Ok(shift(op, &l, &r)),
+        "==" | "!=" | "<" | "<=" | ">" | ">=" => Ok(compare(op, &l, &r)),
+        _ => Ok(arith(op, &extend(&l, bits)?, &extend(&r, bits)?, bits))
+    }
+}
+
+fn arith(op: &str, l: &Vec3vl, r: &Vec3vl, bits: u32) -> Vec3vl {
+    let (l, r) = match (l.to_biguint(), r.to_biguint()) {
+        (Ok(l), Ok(r)) => (l, r),
+        _ => return Vec3vl::xes(bits)
+    };
+
+    let result = match op {
+        "+" => Some(l.wrapping_add(r)),
+        "-" => Some(l.wrapping_sub(r)),
+        "*" => Some(l.wrapping_mul(r)),
+        "/" => l.checked_div(r),
+        _ => l.checked_rem(r)
+    };
+
+    match result {
+        Some(n) => {
+            let mut v = Vec3vl::from_biguint(&n, bits);
+            v.normalize();
+            v
+        },
+        None => Vec3vl::xes(bits)
+    }
+}
+
+fn shift(op: &str, l: &Vec3vl, r: &Vec3vl) -> Vec3vl {
+    let (n, amount) = match (l.to_biguint(), r.to_biguint()) {
+        (Ok(n), Ok(a)) => (n, a),
+        _ => return Vec3vl::xes(l.bits)
+    };
+
+    let amount = if amount.bits() > 32 { u32::MAX } else { amount.digits()[0] };
+    let result = if op == "<<" { n.checked_shl(amount) } else { n.checked_shr(amount) };
+    let mut v = Vec3vl::from_biguint(&result.unwrap_or(BigUInt::ZERO), l.bits);
+    v.normalize();
+    v
+}
+
+fn compare(op: &str, l: &Vec3vl, r: &Vec3vl) -> Vec3vl {
+    let (l, r) = match (l.to_biguint(), r.to_biguint()) {
+        (Ok(l), Ok(r)) => (l, r),
+        _ => return Vec3vl::xes(1)
+    };
+
+    let result = match op {
+        "==" => l == r,
+        "!=" => l != r,
+        "<" => l < r,
+        "<=" => l <= r,
+        ">" => l > r,
+        _ => l >= r
+    };
+    Vec3vl::make_bool(1, result)
+}
+
+fn extend(v: &Vec3vl, bits: u32) -> Result<Vec3vl, String> {
+    if v.bits >= bits {
+        let mut v = v.slice(0, bits)?;
+        v.normalize();
+        Ok(v)
+    } else {
+        Vec3vl::concat(&mut vec![v.clone(), Vec3vl::zeros(bits - v.bits)])
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    resolve: &'a Resolver<'a>,
+    ports: Vec<PortRef>
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        match self.tokens.get(self.pos) {
+            Some(t) => {
+                self.pos += 1;
+                Ok(t.clone())
+            },
+            None => Err("Unexpected end of breakpoint expression".to_string())
+        }
+    }
+
+    fn expect(&mut self, op: &'static str) -> Result<(), String> {
+        match self.next()? {
+            Token::Op(o) if o == op => Ok(()),
+            t => Err(format!("Expected {op}, found {}", describe(&t)))
+        }
+    }
+
+    fn binary_op(&self, level: usize) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(o)) if PRECEDENCE[level].contains(o) => Some(*o),
+            _ => None
+        }
+    }
+
+    fn expr(&mut self, level: usize) -> Result<Expr, String> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+
+        let mut left = self.expr(level + 1)?;
+        while let Some(op) = self.binary_op(level) {
+            self.pos += 1;
+            let right = self.expr(level + 1)?;
+            let bits = match op {
+                "||" | "&&" | "==" | "!=" | "<" | "<=" | ">" | ">=" => 1,
+                "<<" | ">>" => left.bits,
+                _ => left.bits.max(right.bits)
+            };
+            left = Expr { kind: ExprKind::Binary(op, Box::new(left), Box::new(right)), bits };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some(Token::Op(op @ ("!" | "~" | "-"))) => {
+                let op = *op;
+                self.pos += 1;
+                let e = self.unary()?;
+                let bits = if op == "!" { 1 } else { e.bits };
+                Ok(Expr { kind: ExprKind::Unary(op, Box::new(e)), bits })
+            },
+            _ => self.postfix()
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut e = self.primary()?;
+        while self.peek() == Some(&Token::Op("[")) {
+            self.pos += 1;
+            let high = self.index()?;
+            let low = if self.peek() == Some(&Token::Op(":")) {
+                self.pos += 1;
+                self.index()?
+            } else {
+                high
+            };
+            self.expect("]")?;
+
+            if high < low || high >= e.bits {
+                return Err(format!("Slice [{high}:{low}] out of range of a {} bit value", e.bits));
+            }
+            e = Expr { kind: ExprKind::Slice(Box::new(e), low), bits: high - low + 1 };
+        }
+        Ok(e)
+    }
+
+    fn index(&mut self) -> Result<u32, String> {
+        match self.next()? {
+            Token::Number(n) => match parse_number(&n)?.to_biguint() {
+                Ok(v) if v.bits() <= 32 => Ok(v.digits()[0]),
+                _ => Err(format!("Invalid slice index {n}"))
+            },
+            t => Err(format!("Expected slice index, found {}", describe(&t)))
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next()? {
+            Token::Op("(") => {
+                let e = self.expr(0)?;
+                self.expect(")")?;
+                Ok(e)
+            },
+            Token::Number(n) => {
+                let v = parse_number(&n)?;
+                Ok(Expr { bits: v.bits, kind: ExprKind::Const(v) })
+            },
+            Token::Ident(name) => {
+                let (port, bits) = (self.resolve)(&name)?;
+                if !self.ports.contains(&port) { self.ports.push(port.clone()); }
+                Ok(Expr { kind: ExprKind::Signal(port), bits })
+            },
+            t => Err(format!("Unexpected {} in breakpoint expression", describe(&t)))
+        }
+    }
+}
+
+fn describe(t: &Token) -> String {
+    match t {
+        Token::Ident(s) | Token::Number(s) => s.clone(),
+        Token::Op(o) => o.to_string()
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars = source.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let take = |i: usize, f: &dyn Fn(char) -> bool| {
+            let mut j = i;
+            while j < chars.len() && f(chars[j]) { j += 1; }
+            (chars[i..j].iter().collect::<String>(), j)
+        };
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '\'' {
+            let (s, j) = take(i, &|c| c.is_ascii_alphanumeric() || c == '_' || c == '\'' || c == '?');
+            tokens.push(Token::Number(s));
+            i = j;
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let (s, j) = take(i, &|c| c.is_alphanumeric() || "_$.@".contains(c));
+            tokens.push(Token::Ident(s));
+            i = j;
+        } else {
+            let rest = chars[i..].iter().take(2).collect::<String>();
+            match OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+                Some(op) => {
+                    tokens.push(Token::Op(op));
+                    i += op.len();
+                },
+                None => return Err(format!("Unexpected character {c} in breakpoint expression"))
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Accepts decimal, 0x/0b prefixed and Verilog sized (8'hff, 4'b1x0x) literals.
+fn parse_number(literal: &str) -> Result<Vec3vl, String> {
+    let s = literal.replace('_', "").to_lowercase();
+    let invalid = || format!("Invalid number {literal}");
+
+    let (size, base, digits) = if let Some((size, rest)) = s.split_once('\'') {
+        let size = if size.is_empty() { None } else { Some(size.parse::<u32>().map_err(|_| invalid())?) };
+        let mut rest = rest.chars();
+        (size, rest.next().ok_or_else(invalid)?, rest.as_str().to_string())
+    } else if let Some(d) = s.strip_prefix("0x") {
+        (None, 'h', d.to_string())
+    } else if let Some(d) = s.strip_prefix("0b") {
+        (None, 'b', d.to_string())
+    } else {
+        (None, 'd', s.clone())
+    };
+
+    let v = match base {
+        'b' => from_bit_string(&digits).ok_or_else(invalid)?,
+        'h' => {
+            let bin = digits.chars().map(|c| match c {
+                'x' | 'z' | '?' => Some("xxxx".to_string()),
+                _ => c.to_digit(16).map(|d| format!("{d:04b}"))
+            }).collect::<Option<String>>().ok_or_else(invalid)?;
+            from_bit_string(&bin).ok_or_else(invalid)?
+        },
+        'd' => {
+            let n = BigUInt::from_str_radix(&digits, 10).map_err(|_| invalid())?;
+            let mut v = Vec3vl::from_biguint(&n, n.bits().max(1));
+            v.normalize();
+            v
+        },
+        _ => return Err(invalid())
+    };
+
+    match size {
+        Some(0) => Err(invalid()),
+        Some(bits) => extend(&v, bits),
+        None => Ok(v)
+    }
+}
+
+fn from_bit_string(s: &str) -> Option<Vec3vl> {
+    if s.is_empty() { return None; }
+
+    let mut v = Vec3vl::zeros(s.len() as u32);
+    for (i, c) in s.chars().rev().enumerate() {
+        let (a, b) = match c {
+            '0' => (0, 0),
+            '1' => (1, 1),
+            'x' | 'z' | '?' => (0, 1),
+            _ => return None
+        };
+        v.avec[i / 32] |= a << (i % 32);
+        v.bvec[i / 32] |= b << (i % 32);
+    }
+    Some(v)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::arena::GateId;
+
+    struct Signals(HashMap<String, (PortRef, Vec3vl)>);
+
+    impl Signals {
+        fn new(values: &[(&str, &str)]) -> Signals {
+            Signals(values.iter().enumerate().map(|(n, (name, bits))| {
+                let port = (GateId::from_index(n as u32), "out".to_string());
+                (name.to_string(), (port, Vec3vl::from_binary(bits.to_string(), None)))
+            }).collect())
+        }
+
+        fn set(&mut self, name: &str, bits: &str) {
+            self.0.get_mut(name).unwrap().1 = Vec3vl::from_binary(bits.to_string(), None);
+        }
+
+        fn compile(&self, source: &str) -> Result<Breakpoint, String> {
+            Breakpoint::compile(source, &|name: &str| match self.0.get(name) {
+                Some((port, value)) => Ok((port.clone(), value.bits)),
+                None => Err(format!("Unknown signal {name}"))
+            })
+        }
+
+        fn value(&self) -> impl Fn(&PortRef) -> Option<Vec3vl> + '_ {
+            |port| self.0.values().find(|(p, _)| p == port).map(|(_, v)| v.clone())
+        }
+
+        fn eval(&self, source: &str) -> String {
+            let value = self.compile(source).unwrap().eval(&self.value()).unwrap();
+            (0..value.bits).rev().map(|n| match value.get(n) { 1 => '1', -1 => '0', _ => 'x' }).collect()
+        }
+    }
+
+    #[test]
+    fn parses_literals() {
+        let signals = Signals::new(&[]);
+        assert_eq!(signals.eval("8'hf0"), "11110000");
+        assert_eq!(signals.eval("4'b1x0x"), "1x0x");
+        assert_eq!(signals.eval("0x1f"), "00011111");
+        assert_eq!(signals.eval("0b101"), "101");
+        assert_eq!(signals.eval("6"), "110");
+        assert_eq!(signals.eval("6'd5"), "000101");
+        assert_eq!(signals.eval("8'h_x_1"), "xxxx0001");
+        assert!(signals.compile("0'b1").is_err());
+        assert!(signals.compile("4'q1").is_err());
+        assert!(signals.compile("8'b102").is_err());
+    }
+
+    #[test]
+    fn follows_operator_precedence() {
+        let signals = Signals::new(&[("a", "0010"), ("b", "0011")]);
+        assert_eq!(signals.eval("a + b * 2"), "1000");
+        assert_eq!(signals.eval("(a + b) * 2"), "1010");
+        assert_eq!(signals.eval("a | b & 1"), "0011");
+        assert_eq!(signals.eval("a + 1 == b && !(a > b)"), "1");
+        assert_eq!(signals.eval("a << 1 == 4 || 0"), "1");
+        assert_eq!(signals.eval("-a"), "1110");
+        assert_eq!(signals.eval("~a ^ b"), "1110");
+        assert_eq!(signals.eval("b % a + b / a"), "0010");
+    }
+
+    #[test]
+    fn evaluates_with_verilog_widths() {
+        let signals = Signals::new(&[("a", "11111111"), ("b", "0001")]);
+        assert_eq!(signals.eval("a + b"), "00000000");
+        assert_eq!(signals.eval("b - 2"), "1111");
+        assert_eq!(signals.eval("a[7:4]"), "1111");
+        assert_eq!(signals.eval("a[3:0] + b"), "0000");
+        assert_eq!(signals.eval("a[0]"), "1");
+        assert_eq!(signals.eval("a == 255"), "1");
+        assert_eq!(signals.eval("b != 1"), "0");
+        assert_eq!(signals.eval("b >> 4"), "0000");
+        assert_eq!(signals.eval("b / 0"), "xxxx");
+    }
+
+    #[test]
+    fn propagates_undefined_bits() {
+        let signals = Signals::new(&[("a", "1x10"), ("b", "0000")]);
+        assert_eq!(signals.eval("a == 4'b1010"), "x");
+        assert_eq!(signals.eval("a != 4'b1x10"), "x");
+        assert_eq!(signals.eval("a + 1"), "xxxx");
+        assert_eq!(signals.eval("a & b"), "0000");
+        assert_eq!(signals.eval("a | b"), "1x10");
+        assert_eq!(signals.eval("a[1:0] == 2"), "1");
+        assert_eq!(signals.eval("b == 0 || a == 1"), "1");
+        assert_eq!(signals.eval("b != 0 && a == 1"), "0");
+    }
+
+    #[test]
+    fn reports_errors() {
+        let signals = Signals::new(&[("a", "0010")]);
+        assert!(signals.compile("a +").is_err());
+        assert!(signals.compile("(a").is_err());
+        assert!(signals.compile("a )").is_err());
+        assert!(signals.compile("a[4]").is_err());
+        assert!(signals.compile("a[1:2]").is_err());
+        assert!(signals.compile("a # 1").is_err());
+        assert!(signals.compile("missing == 1").is_err());
+        assert!(signals.compile("").is_err());
+    }
+
+    #[test]
+    fn collects_signal_ports() {
+        let signals = Signals::new(&[("a", "0010"), ("b", "0011")]);
+        let breakpoint = signals.compile("a == 1 || b == a").unwrap();
+        assert_eq!(breakpoint.ports().len(), 2);
+    }
+
+    #[test]
+    fn hits_only_on_becoming_true() {
+        let mut signals = Signals::new(&[("a", "0000")]);
+        let mut breakpoint = signals.compile("a == 3").unwrap();
+        assert!(!breakpoint.update(&signals.value()));
+
+        signals.set("a", "0011");
+        assert!(breakpoint.update(&signals.value()));
+        assert!(!breakpoint.update(&signals.value()));
+
+        signals.set("a", "00x1");
+        assert!(!breakpoint.update(&signals.value()));
+        signals.set("a", "0011");
+        assert!(breakpoint.update(&signals.value()));
+    }
+}
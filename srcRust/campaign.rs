@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::arena::{GateId, GraphId};
+use crate::fault::{Fault, FaultKind, FaultSite};
+use crate::gate::Gate;
+use crate::hierarchy::{find_gate, gate_path};
+use crate::js_types::{JsFaultCampaign, JsVec3vl};
+use crate::monitor::PortRef;
+use crate::vector3vl::Vec3vl;
+use crate::RustEngine;
+
+pub const DEFAULT_SETTLE: u32 = 1000;
+const DEFAULT_LANES: u32 = 64;
+
+// Output values after every test vector.
+type Trace = Vec<Vec<Vec3vl>>;
+
+struct StuckFault {
+    name: String,
+    port: PortRef,
+    bit: u32,
+    kind: FaultKind
+}
+
+struct Stimulus {
+    inputs: Vec<GateId>,
+    outputs: Vec<GateId>,
+    vectors: Vec<Vec<Vec3vl>>,
+    settle: u32
+}
+
+// Single stuck-at fault simulation of `graph`. The campaign runs on a scratch copy
+// of the engine, so the live simulation is not disturbed. Faults are simulated in
+// batches of `lanes` instances of the graph sharing one event queue, each with one
+// fault injected, and a fault counts as detected by the first vector after which
+// some output is defined in both the good and the faulty circuit and differs.
+pub fn run(engine: &RustEngine, graph: GraphId, params: &JsFaultCampaign) -> Result<JsValue, String> {
+    let stimulus = Stimulus::new(engine, graph, params)?;
+    let lanes = params.get_lanes().unwrap_or(DEFAULT_LANES).max(1) as usize;
+
+    let mut faults = Vec::new();
+    enumerate_faults(engine, graph, graph, &mut faults)?;
+    faults.sort_by(|a, b| (&a.name, &a.port.1, a.bit, a.kind as u8).cmp(&(&b.name, &b.port.1, b.bit, b.kind as u8)));
+
+    let mut sim = engine.scratch_priv();
+    let golden = simulate(&mut sim, graph, &stimulus, &[None], None)?.1;
+
+    let mut detected = Vec::new();
+    for batch in faults.chunks(lanes) {
+        let lane_faults = batch.iter().map(Some).collect::<Vec<Option<&StuckFault>>>();
+        detected.extend(simulate(&mut sim, graph, &stimulus, &lane_faults, Some(&golden))?.0);
+    }
+
+    let list = Array::new();
+    let mut count = 0;
+    for (fault, vector) in faults.iter().zip(&detected) {
+        if vector.is_some() { count += 1; }
+        let obj = Object::new();
+        let _ = Reflect::set(&obj, &"gate".into(), &fault.name.as_str().into());
+        let _ = Reflect::set(&obj, &"port".into(), &fault.port.1.as_str().into());
+        let _ = Reflect::set(&obj, &"bit".into(), &fault.bit.into());
+        let _ = Reflect::set(&obj, &"stuckAt".into(), &(if fault.kind == FaultKind::StuckAt1 { 1 } else { 0 }).into());
+        let _ = Reflect::set(&obj, &"detected".into(), &vector.is_some().into());
+        let _ = Reflect::set(&obj, &"vector".into(), &vector.map_or(JsValue::NULL, |v| (v as u32).into()));
+        list.push(&obj);
+    }
+
+    let obj = Object::new();
+    let _ = Reflect::set(&obj, &"total".into(), &(faults.len() as u32).into());
+    let _ = Reflect::set(&obj, &"detected".into(), &count.into());
+    let _ = Reflect::set(&obj, &"coverage".into(), &(if faults.is_empty() { 1.0 } else { count as f64 / faults.len() as f64 }).into());
+    let _ = Reflect::set(&obj, &"faults".into(), &list);
+    Ok(obj.into())
+}
+
+impl Stimulus {
+    fn new(engine: &RustEngine, graph: GraphId, params: &JsFaultCampaign) -> Result<Stimulus, String> {
+        let find = |name: &String| find_gate(&engine.gates, &engine.graphs, graph, name);
+        let inputs = params.get_inputs().iter().map(find).collect::<Result<Vec<GateId>, String>>()?;
+        let outputs = match params.get_outputs() {
+            Some(names) => names.iter().map(find).collect::<Result<Vec<GateId>, String>>()?,
+            None => engine.graph(graph)?.gate_iter()
+                .filter(|g| engine.gates.get(**g).is_some_and(Gate::is_io_output))
+                .copied()
+                .collect()
+        };
+
+        let mut vectors = Vec::new();
+        for (i, vector) in params.get_vectors().iter().enumerate() {
+            let values = vector.dyn_into::<Array>()
+                .map_err(|_| format!("Test vector {i} is not an array"))?
+                .iter()
+                .map(|v| Vec3vl::from_clonable(v.unchecked_into::<JsVec3vl>()))
+                .collect::<Vec<Vec3vl>>();
+            if values.len() != inputs.len() {
+                return Err(format!("Test vector {i} has {} values for {} inputs", values.len(), inputs.len()));
+            }
+            vectors.push(values);
+        }
+
+        Ok(Stimulus { inputs, outputs, vectors, settle: params.get_settle().unwrap_or(DEFAULT_SETTLE) })
+    }
+}
+
+fn enumerate_faults(engine: &RustEngine, root: GraphId, graph: GraphId, out: &mut Vec<StuckFault>) -> Result<(), String> {
+    for gate_id in engine.graph(graph)?.gate_iter() {
+        let gate = engine.gate(*gate_id)?;
+        if let Ok(sub) = gate.get_subgraph() {
+            enumerate_faults(engine, root, sub, out)?;
+            continue;
+        }
+
+        let name = gate_path(&engine.gates, &engine.graphs, *gate_id, root);
+        for (port, _) in gate.iodirs_iter() {
+            for bit in 0..gate.get_port_value(port)?.bits {
+                for kind in [FaultKind::StuckAt0, FaultKind::StuckAt1] {
+                    out.push(StuckFault { name: name.clone(), port: (*gate_id, port.clone()), bit, kind });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Runs the stimulus on one instance of `graph` per lane and removes the instances
+// afterwards. Returns the first detecting vector of every lane and, for the
+// golden run, the outputs after every vector.
+fn simulate(sim: &mut RustEngine, graph: GraphId, stimulus: &Stimulus, lanes: &[Option<&StuckFault>], golden: Option<&Trace>) -> Result<(Vec<Option<usize>>, Trace), String> {
+    let mut instances = Vec::new();
+    for (lane, fault) in lanes.iter().enumerate() {
+        let suffix = format!("@{}", sim.instance_counter);
+        sim.instance_counter += 1;
+
+        let mut copies = HashMap::new();
+        let name = format!("{}{suffix}", sim.graph(graph)?.get_id());
+        let instance = sim.clone_graph_priv(graph, name, &suffix, &mut copies)?;
+
+        if let Some(f) = fault {
+            let site = FaultSite::Port((copies[&f.port.0], f.port.1.clone()));
+            sim.add_fault_priv(lane as u32, Fault { site, kind: f.kind, bit: Some(f.bit), tick: None })?;
+        }
+        // The scratch engine has none of the live pending events, so the copies are
+        // evaluated once to settle from the current values.
+        for copy in copies.values() {
+            let gate = sim.gate(*copy)?;
+            if !gate.is_io_input() && gate.get_subgraph().is_err() {
+                sim.enqueue(*copy)?;
+            }
+        }
+        instances.push((instance, copies));
+    }
+    sim.settle_priv(stimulus.settle)?;
+
+    let mut detected = vec![None; lanes.len()];
+    let mut outputs = Vec::new();
+    for (v, vector) in stimulus.vectors.iter().enumerate() {
+        for (_, copies) in &instances {
+            for (input, value) in stimulus.inputs.iter().zip(vector) {
+                sim.set_gate_output_signal_priv(copies[input], "out".to_string(), value.clone())?;
+            }
+        }
+        sim.settle_priv(stimulus.settle)?;
+
+        for (lane, (_, copies)) in instances.iter().enumerate() {
+            let values = stimulus.outputs.iter()
+                .map(|o| sim.gate(copies[o])?.get_input(&"in".to_string()))
+                .collect::<Result<Vec<Vec3vl>, String>>()?;
+
+            if let Some(golden) = golden {
+                let differs = values.iter().zip(&golden[v])
+                    .any(|(a, b)| a.is_fully_defined() && b.is_fully_defined() && a != b);
+                if differs && detected[lane].is_none() { detected[lane] = Some(v); }
+            } else {
+                outputs.push(values);
+            }
+        }
+        if golden.is_some() && detected.iter().all(Option::is_some) { break; }
+    }
+
+    for lane in 0..lanes.len() {
+        sim.remove_fault(lane as u32)?;
+    }
+    for (instance, _) in instances {
+        sim.remove_graph_priv(instance)?;
+    }
+    sim.queue.clear();
+    sim.output_queue.clear();
+    sim.output_ticks.clear();
+    sim.pq.clear();
+    Ok((detected, outputs))
+}
@@ -5,6 +5,7 @@ use crate::js_types::JsGateParams;
 use crate::vector3vl::Vec3vl;
 use crate::operations::ReturnValue;
 
+#[derive(Clone)]
 pub struct DffState {
   arst_value: Option<String>,
   bits: u32,
@@ -1,12 +1,16 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::vec;
 
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::JsValue;
+
 use crate::gate::PolarityOptions;
 use crate::js_types::JsGateParams;
 use crate::operations::ReturnValue;
 use crate::{triggerFSMCurrentStateChange, triggerFSMNextTransChange};
 use crate::vector3vl::Vec3vl;
 
+#[derive(Clone)]
 pub struct FsmState {
   graph_id: String,
   gate_id: String,
@@ -15,7 +19,12 @@ pub struct FsmState {
   transitions: HashMap<u32, Vec<FsmTransition>>,
   init_state: u32,
   current_state: u32,
-  last_clk: i32
+  last_clk: i32,
+  last_arst: i32,
+  // Set in engines the host does not know about, which must not notify it.
+  detached: bool,
+  state_visits: HashMap<u32, u32>,
+  transitions_taken: HashMap<String, u32>
 }
 
 #[derive(Clone)]
@@ -112,39 +121,106 @@ pub fn fsm(args: &HashMap<String, Vec3vl>, state: &mut FsmState) -> Result<Retur
   };
 
   if arst.lsb() == pol(arst_pol) {
+    // Holding the reset keeps the machine in the initial state without entering it again.
+    if state.current_state != state.init_state || state.last_arst == -pol(arst_pol) {
+      state.visit(state.init_state, None);
+    }
     state.current_state = state.init_state;
-    triggerFSMCurrentStateChange(
-      state.graph_id.clone(), 
-      state.gate_id.clone(), 
-      state.current_state
-    );
-  } else {
-    let last_clk = state.last_clk;
-    if clk.lsb() == pol(clk_pol) && last_clk == -pol(clk_pol) {
-      state.current_state = if let Some(trans) = next_trans(state.current_state, &data_in, &state.transitions)? {
-        trans.state_out
-      } else {
-        state.init_state
-      };
+    if !state.detached {
       triggerFSMCurrentStateChange(
         state.graph_id.clone(), 
         state.gate_id.clone(), 
         state.current_state
       );
     }
+  } else {
+    let last_clk = state.last_clk;
+    if clk.lsb() == pol(clk_pol) && last_clk == -pol(clk_pol) {
+      let trans = next_trans(state.current_state, &data_in, &state.transitions)?;
+      state.current_state = match &trans {
+        Some(t) => t.state_out,
+        None => state.init_state
+      };
+      state.visit(state.current_state, trans.map(|t| t.id));
+      if !state.detached {
+        triggerFSMCurrentStateChange(
+          state.graph_id.clone(), 
+          state.gate_id.clone(), 
+          state.current_state
+        );
+      }
+    }
   }
 
   state.last_clk = clk.lsb();
-  if let Some(t) = next_trans(state.current_state, &data_in, &state.transitions)? {
-    triggerFSMNextTransChange(state.graph_id.clone(), state.gate_id.clone(), Some(t.id));
-  } else {
-    triggerFSMNextTransChange(state.graph_id.clone(), state.gate_id.clone(), None);
+  state.last_arst = arst.lsb();
+  let next = next_trans(state.current_state, &data_in, &state.transitions)?;
+  if !state.detached {
+    triggerFSMNextTransChange(state.graph_id.clone(), state.gate_id.clone(), next.map(|t| t.id));
   }
 
   ReturnValue::out(next_output(state.current_state, data_in, state.bits_out, &state.transitions)?)
 }
 
 impl FsmState {
+  pub fn detach(&mut self) {
+    self.detached = true;
+  }
+
+  pub fn set_graph_id(&mut self, graph_id: String) {
+    self.graph_id = graph_id;
+  }
+
+  fn visit(&mut self, state: u32, transition: Option<String>) {
+    *self.state_visits.entry(state).or_default() += 1;
+    if let Some(t) = transition {
+      *self.transitions_taken.entry(t).or_default() += 1;
+    }
+  }
+
+  pub fn reset_coverage(&mut self) {
+    self.state_visits = HashMap::from([(self.current_state, 1)]);
+    self.transitions_taken = HashMap::new();
+  }
+
+  // Returns `{ states, transitions, unvisitedStates, untakenTransitions }`, where the
+  // first two map state numbers and transition ids (`tr{n}`) to how often they were entered.
+  pub fn coverage(&self) -> JsValue {
+    let mut states = BTreeSet::from([self.init_state]);
+    let mut transitions = Vec::new();
+    for (state_in, trans) in &self.transitions {
+      states.insert(*state_in);
+      for t in trans {
+        states.insert(t.state_out);
+        transitions.push(t.id.clone());
+      }
+    }
+    transitions.sort_by_key(|id| id[2..].parse::<u32>().unwrap_or(0));
+
+    let state_counts = Object::new();
+    let unvisited = Array::new();
+    for state in states {
+      let count = self.state_visits.get(&state).copied().unwrap_or(0);
+      let _ = Reflect::set(&state_counts, &state.into(), &count.into());
+      if count == 0 { unvisited.push(&state.into()); }
+    }
+
+    let trans_counts = Object::new();
+    let untaken = Array::new();
+    for id in transitions {
+      let count = self.transitions_taken.get(&id).copied().unwrap_or(0);
+      let _ = Reflect::set(&trans_counts, &id.as_str().into(), &count.into());
+      if count == 0 { untaken.push(&id.as_str().into()); }
+    }
+
+    let obj = Object::new();
+    let _ = Reflect::set(&obj, &"states".into(), &state_counts);
+    let _ = Reflect::set(&obj, &"transitions".into(), &trans_counts);
+    let _ = Reflect::set(&obj, &"unvisitedStates".into(), &unvisited);
+    let _ = Reflect::set(&obj, &"untakenTransitions".into(), &untaken);
+    obj.into()
+  }
+
   pub fn new(params: JsGateParams, graph_id: String, gate_id: String) -> FsmState {
     let (bits_in, bits_out) = match params.get_bits_struct() {
       Some(b) => (b.get_bits_in(), b.get_bits_out()),
@@ -184,6 +260,10 @@ impl FsmState {
       init_state,
       current_state: init_state,
       last_clk: 0,
+      last_arst: 0,
+      detached: false,
+      state_visits: HashMap::from([(init_state, 1)]),
+      transitions_taken: HashMap::new()
     }
   }
 }
\ No newline at end of file
@@ -1,24 +1,34 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use wasm_bindgen::JsValue;
 
+use crate::cell_rom::RomState;
 use crate::js_types::{JsGateParams, MemoryPolarityStruct};
+use crate::mmio::{mmio_read, mmio_write, MmioRegion};
 use crate::operations::ReturnValue;
+use crate::paged_memory::PagedMemory;
 use crate::triggerMemoryUpdate;
 use crate::vector3vl::Vec3vl;
 
+#[derive(Clone)]
 pub struct MemoryState {
   pub gate_id: String,
   pub graph_id: String,
   pub abits: u32,
   pub bits: u32,
-  pub memory: Vec<Vec3vl>,
+  pub memory: PagedMemory,
+  pub mmio: Vec<MmioRegion>,
   pub offset: u32,
-  pub words: u32,
+  pub words: u64,
   pub rdports: Vec<(String, MemoryPortPolarity)>,
   pub wrports: Vec<(String, MemoryPortPolarity)>,
   pub outputs: HashMap<String, Vec3vl>,
-  pub last_clk: HashMap<String, i32>
+  pub last_clk: HashMap<String, i32>,
+  // Word last read by each combinational read port, cleared when the memory is changed
+  // from outside of `memory_op`.
+  pub last_read: HashMap<String, Option<u32>>,
+  // Set in engines the host does not know about, which must not notify it.
+  pub detached: bool
 }
 
 pub fn memory_op(args: &HashMap<String, Vec3vl>, state: &mut MemoryState) -> Result<ReturnValue, String> {
@@ -27,7 +37,7 @@ pub fn memory_op(args: &HashMap<String, Vec3vl>, state: &mut MemoryState) -> Res
   let is_enabled = |portname: &String, port: &MemoryPortPolarity| -> Result<bool, String> {
     if let Some(p) = port.enable {
       return match args.get(&format!("{portname}en")).cloned() {
-        Some(mut v) => Ok(v.to_array().iter().all(|x| *x == pol(p))),
+        Some(v) => Ok((0..v.bits).any(|n| v.get(n) == pol(p))),
         None => Err(format!("No port named {portname}en"))
       }
     }
@@ -47,26 +57,20 @@ pub fn memory_op(args: &HashMap<String, Vec3vl>, state: &mut MemoryState) -> Res
     Ok(true)
   };
 
-  let valid_addr = |n: i32| -> bool {
-    n >= 0 && n < state.words as i32
-  };
-
-  let do_comb_read = |portname: &String, memory: &mut Vec<Vec3vl>, outputs: &mut HashMap<String, Vec3vl>| -> Result<(), String> {
+  let read_addr = |portname: &String| -> Result<Option<u32>, String> {
     let port = format!("{portname}addr");
-    let sig = match args.get(&port) {
-      Some(v) => v,
-      None => return Err(format!("No port {port}"))
-    };
-    let addr = calc_addr(&mut sig.clone(), state.offset)?;
+    match args.get(&port) {
+      Some(sig) => calc_addr(&mut sig.clone(), state.offset, state.words),
+      None => Err(format!("No port {port}"))
+    }
+  };
 
-    outputs.insert(format!("{portname}data"), if valid_addr(addr) {
-        memory[addr as usize].clone()
-      } else {
-        Vec3vl::xes(state.bits)
+  let do_comb_read = |portname: &String, addr: Option<u32>, memory: &PagedMemory, mmio: &[MmioRegion], outputs: &mut HashMap<String, Vec3vl>| {
+    outputs.insert(format!("{portname}data"), match addr {
+        Some(addr) => mmio_read(mmio, addr).unwrap_or_else(|| memory.get(addr)),
+        None => Vec3vl::xes(state.bits)
       }
     );
-
-    Ok(())
   };
 
   let write_value = |portname: &String, port: &MemoryPortPolarity, oldval: Vec3vl, val: Vec3vl| -> Result<Vec3vl, String> {
@@ -77,6 +81,7 @@ pub fn memory_op(args: &HashMap<String, Vec3vl>, state: &mut MemoryState) -> Res
           None => return Err(format!("Memoty cell has no enable port for port {portname}"))
         };
         if !p { mask = mask.not(); }
+        let mask = expand_enable(&mask, port.enable_lane(state.bits), state.bits);
 
         Ok(val.and(&mask)?.or(&oldval.and(&mask.not())?)?)
       },
@@ -84,8 +89,8 @@ pub fn memory_op(args: &HashMap<String, Vec3vl>, state: &mut MemoryState) -> Res
     }
   };
 
-  let mut do_read = |portname: &String, port: &MemoryPortPolarity, outputs: &mut HashMap<String, Vec3vl>| -> Result<(), String> {
-    do_comb_read(portname, &mut state.memory, outputs)?;
+  let do_read = |portname: &String, port: &MemoryPortPolarity, outputs: &mut HashMap<String, Vec3vl>| -> Result<(), String> {
+    do_comb_read(portname, read_addr(portname)?, &state.memory, &state.mmio, outputs);
 
     for (wrportname, wrport) in state.wrports.iter() {
       if let Some(t) = port.transparent {
@@ -105,39 +110,44 @@ pub fn memory_op(args: &HashMap<String, Vec3vl>, state: &mut MemoryState) -> Res
     Ok(())
   };
 
-  let do_write = |portname: &String, port: &MemoryPortPolarity, memory: &mut Vec<Vec3vl>| -> Result<(), String> {
+  // Returns the index of the written memory word.
+  let do_write = |portname: &String, port: &MemoryPortPolarity, memory: &mut PagedMemory, mmio: &mut [MmioRegion]| -> Result<Option<u32>, String> {
     let addrport = format!("{portname}addr");
     let sig = match args.get(&addrport) {
       Some(v) => v,
       None => return Err(format!("No port {addrport}"))
     };
-    let addr = calc_addr(&mut sig.clone(), state.offset)?;
+    let addr = calc_addr(&mut sig.clone(), state.offset, state.words)?;
 
-    if valid_addr(addr) {
-      let oldval = memory[addr as usize].clone();
-      
+    if let Some(addr) = addr {
       let val = match args.get(&format!("{portname}data")) {
         Some(v) => v.clone(),
         None => return Err(format!("Memory cell has no data port for {portname}"))
       };
-      
-      let newval = write_value(portname, port, oldval.clone(), val.clone())?;
+
+      if let Some(oldval) = mmio_read(mmio, addr) {
+        mmio_write(mmio, addr, &write_value(portname, port, oldval, val)?);
+        return Ok(Some(addr));
+      }
+
+      let oldval = memory.get(addr);
+      let newval = write_value(portname, port, oldval.clone(), val)?;
       let changed = oldval != newval; 
-      memory[addr as usize] = newval;
+      memory.set(addr, &newval);
 
-      if changed {
+      if changed && !state.detached {
         triggerMemoryUpdate(
           state.graph_id.clone(),
           state.gate_id.clone(),
           addr,
-          val.bits,
-          val.avec.clone(),
-          val.bvec.clone()
+          newval.bits,
+          newval.avec,
+          newval.bvec
         );
       }
     }
 
-    Ok(())
+    Ok(addr)
   };
 
   // do_srst
@@ -150,21 +160,32 @@ pub fn memory_op(args: &HashMap<String, Vec3vl>, state: &mut MemoryState) -> Res
     }
   };
 
+  let mut changed = HashSet::new();
+
   for (portname, port) in state.rdports.iter() {
     if port.clock.is_some() && is_enabled(portname, port)? && port_active(portname, port, *state.last_clk.get(&format!("{portname}clk")).unwrap())? {
       do_read(portname, port, &mut state.outputs)?;
+      changed.insert(format!("{portname}data"));
     }
   }
 
   for (portname, port) in state.wrports.iter() {
     if is_enabled(portname, port)? && port_active(portname, port, *state.last_clk.get(&format!("{portname}clk")).unwrap())? {
-      do_write(portname, port, &mut state.memory)?;
+      if let Some(addr) = do_write(portname, port, &mut state.memory, &mut state.mmio)? {
+        state.last_read.retain(|_, a| *a != Some(addr));
+      }
     }
   }
 
+  // Only ports whose address changed, or whose word was just written, are read again.
   for (portname, port) in state.rdports.iter() {
     if port.clock.is_none() && is_enabled(portname, port)? {
-      do_comb_read(portname, &mut state.memory, &mut state.outputs)?;
+      let addr = read_addr(portname)?;
+      if state.last_read.get(portname) != Some(&addr) {
+        do_comb_read(portname, addr, &state.memory, &state.mmio, &mut state.outputs);
+        state.last_read.insert(portname.clone(), addr);
+        changed.insert(format!("{portname}data"));
+      }
     }
   }
 
@@ -179,14 +200,19 @@ pub fn memory_op(args: &HashMap<String, Vec3vl>, state: &mut MemoryState) -> Res
     update_last_clk(portname, port, &mut state.last_clk);
   }
 
-  ReturnValue::values(None, state.outputs.clone())
+  let outputs = changed.into_iter()
+    .filter_map(|port| state.outputs.get(&port).cloned().map(|v| (port, v)))
+    .collect();
+  ReturnValue::values(None, outputs)
 }
 
-fn calc_addr(sig: &mut Vec3vl, offset: u32) -> Result<i32, String> {
+// Index of the addressed word, `None` when the address is undefined or out of range.
+pub fn calc_addr(sig: &mut Vec3vl, offset: u32, words: u64) -> Result<Option<u32>, String> {
   if !sig.is_fully_defined() {
-    Ok(-1)
+    Ok(None)
   } else {
-    Ok(sig.get_number()? as i32 - offset as i32)
+    let addr = (sig.get_number()? as u64).checked_sub(offset as u64);
+    Ok(addr.filter(|a| *a < words).map(|a| a as u32))
   }
 }
 
@@ -194,9 +220,11 @@ impl MemoryState {
   pub fn new(params: JsGateParams, graph_id: String, gate_id: String) -> MemoryState {
     let bits_in = params.get_bits().unwrap_or(1);
     let abits = params.get_abits().unwrap_or(1);
-    let memory = load_memory(params.get_memdata(), bits_in).unwrap_or_default();
     let offset = params.get_offset().unwrap_or(0);
-    let words = params.get_words().unwrap_or(1 << abits);
+    let words = params.get_words().map_or(1u64 << abits, |w| w as u64);
+    let fill = Vec3vl::from_binary(params.get_memfill().unwrap_or("x".to_string()), Some(bits_in as usize));
+    let mut memory = PagedMemory::new(bits_in, words, fill);
+    load_memory(params.get_memdata(), bits_in, |addr, val| memory.set(addr, &val));
 
     let rdports: Vec<(String, MemoryPortPolarity)> = params.get_rdports().map(|mem_vec|
       mem_vec.iter()
@@ -234,43 +262,106 @@ impl MemoryState {
       abits, 
       bits: bits_in, 
       memory, 
+      mmio: Vec::new(),
       offset, 
       words, 
       rdports, 
       wrports, 
       outputs: HashMap::new(),
-      last_clk
+      last_clk,
+      last_read: HashMap::new(),
+      detached: false
+    }
+  }
+
+  // Writable memory with the contents and asynchronous read ports of a ROM.
+  pub fn from_rom(rom: &RomState, graph_id: String, gate_id: String) -> MemoryState {
+    let rdports = rom.rdports().iter().map(|name| (name.clone(), MemoryPortPolarity::default())).collect();
+    MemoryState {
+      gate_id,
+      graph_id,
+      abits: (64 - rom.words.saturating_sub(1).leading_zeros()).max(1),
+      bits: rom.bits,
+      memory: rom.to_memory(),
+      mmio: Vec::new(),
+      offset: rom.offset,
+      words: rom.words,
+      rdports,
+      wrports: Vec::new(),
+      outputs: HashMap::new(),
+      last_clk: HashMap::new(),
+      last_read: HashMap::new(),
+      detached: false
+    }
+  }
+
+  pub fn detach(&mut self) {
+    self.detached = true;
+    for region in self.mmio.iter_mut() {
+      region.detach();
     }
   }
+
+  pub fn read_latencies(&self) -> Vec<(String, u32)> {
+    self.rdports.iter()
+      .filter_map(|(portname, port)| port.latency.map(|l| (format!("{portname}data"), l)))
+      .collect()
+  }
+
+  // Data outputs of the read ports without a clock that `port` is an input of.
+  pub fn async_read_outputs(&self, port: &str) -> Vec<String> {
+    self.rdports.iter()
+      .filter(|(portname, rdport)| rdport.clock.is_none() &&
+        (port == format!("{portname}addr") || port == format!("{portname}en")))
+      .map(|(portname, _)| format!("{portname}data"))
+      .collect()
+  }
 }
 
-fn load_memory(memory: Option<Vec<JsValue>>, size: u32) -> Option<Vec<Vec3vl>> {
-  memory.map(|v| {
-    let mut mem = Vec::new();
+// Decodes `memdata`, a list of binary words optionally preceded by a repeat count.
+pub fn load_memory(memory: Option<Vec<JsValue>>, size: u32, mut set: impl FnMut(u32, Vec3vl)) {
+  if let Some(v) = memory {
+    let mut addr = 0u32;
 
     let mut n = 0usize;
     while n < v.len() {
       if let Some(s) = v[n].as_string() {
-        mem.push(Vec3vl::from_binary(s, Some(size as usize)));
+        set(addr, Vec3vl::from_binary(s, Some(size as usize)));
+        addr = addr.wrapping_add(1);
       } else if let Some(f) = v[n].as_f64() {
         n += 1;
         let val = Vec3vl::from_binary(v[n].as_string().unwrap(), Some(size as usize));
         let count = f as u32;
         for _ in 0..count {
-          mem.push(val.clone());
+          set(addr, val.clone());
+          addr = addr.wrapping_add(1);
         }
       }
         n += 1;
     }
-          
-    mem
-  })
+  }
 }
 
-#[derive(Clone)]
+// Widens a write enable to one bit per data bit, each enable bit covering `lane` bits.
+fn expand_enable(en: &Vec3vl, lane: u32, bits: u32) -> Vec3vl {
+  if lane == 1 || en.bits == 0 { return en.clone(); }
+
+  let mut mask = Vec3vl::zeros(bits);
+  for n in 0..bits {
+    let m = (n / lane).min(en.bits - 1);
+    mask.avec[(n / 32) as usize] |= ((en.avec[(m / 32) as usize] >> (m % 32)) & 1) << (n % 32);
+    mask.bvec[(n / 32) as usize] |= ((en.bvec[(m / 32) as usize] >> (m % 32)) & 1) << (n % 32);
+  }
+  mask
+}
+
+#[derive(Clone, Default)]
 pub struct MemoryPortPolarity {
   enable:       Option<bool>,
+  no_bit_enable: bool,
+  byte_enable:  bool,
   clock:        Option<bool>,
+  latency:      Option<u32>,
   transparent:  Option<bool>,
   collision:    Option<bool>,
   srst:         Option<bool>,
@@ -284,7 +375,10 @@ impl MemoryPortPolarity {
   pub fn new(options: &MemoryPolarityStruct) -> MemoryPortPolarity {
     MemoryPortPolarity { 
       enable:       options.get_enable(), 
+      no_bit_enable: options.get_no_bit_enable().unwrap_or(false),
+      byte_enable:  options.get_byte_enable().unwrap_or(false),
       clock:        options.get_clock(),
+      latency:      options.get_latency(),
       transparent:  options.get_transparent(),
       collision:    options.get_collision(),
       srst:         options.get_srst(),
@@ -294,4 +388,9 @@ impl MemoryPortPolarity {
       arst_value:   options.get_arst_value()
     }
   }
+
+  // Number of data bits controlled by each bit of the enable port.
+  fn enable_lane(&self, bits: u32) -> u32 {
+    if self.no_bit_enable { bits.max(1) } else if self.byte_enable { 8 } else { 1 }
+  }
 }
\ No newline at end of file
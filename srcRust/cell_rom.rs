@@ -0,0 +1,142 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+
+use crate::cell_memory::{calc_addr, load_memory};
+use crate::js_types::JsGateParams;
+use crate::operations::ReturnValue;
+use crate::paged_memory::PagedMemory;
+use crate::vector3vl::Vec3vl;
+
+#[derive(Clone, PartialEq)]
+pub struct RomTable {
+  bits: u32,
+  fill: Vec3vl,
+  data: Vec<Vec3vl>
+}
+
+// Read-only memory evaluated by indexing a table shared between instances with equal contents.
+#[derive(Clone)]
+pub struct RomState {
+  pub bits: u32,
+  pub offset: u32,
+  pub words: u64,
+  rdports: Vec<String>,
+  table: Arc<RomTable>
+}
+
+// Tables of the live ROMs, keyed by a hash of their contents.
+static TABLES: OnceLock<Mutex<HashMap<u64, Weak<RomTable>>>> = OnceLock::new();
+
+pub fn rom_op(args: &HashMap<String, Vec3vl>, state: &RomState) -> Result<ReturnValue, String> {
+  let mut outputs = HashMap::new();
+  for portname in state.rdports.iter() {
+    let port = format!("{portname}addr");
+    let addr = match args.get(&port) {
+      Some(sig) => calc_addr(&mut sig.clone(), state.offset, state.words)?,
+      None => return Err(format!("No port {port}"))
+    };
+    let val = match addr {
+      Some(addr) => state.get(addr),
+      None => Vec3vl::xes(state.bits)
+    };
+    outputs.insert(format!("{portname}data"), val);
+  }
+  ReturnValue::values(None, outputs)
+}
+
+impl RomTable {
+  fn content_hash(&self) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    self.bits.hash(&mut hasher);
+    for word in std::iter::once(&self.fill).chain(self.data.iter()) {
+      word.avec.hash(&mut hasher);
+      word.bvec.hash(&mut hasher);
+    }
+    hasher.finish()
+  }
+}
+
+impl RomState {
+  // A memory can be evaluated as a ROM when it has no write ports and only
+  // asynchronous read ports without enables, resets or latency.
+  pub fn is_rom(params: &JsGateParams) -> bool {
+    let no_wrports = params.get_wrports().is_none_or(|v| v.is_empty());
+    let rdports = params.get_rdports().unwrap_or_default();
+    no_wrports && !rdports.is_empty() && rdports.iter().all(|port|
+      port.get_clock().is_none() && port.get_enable().is_none() && port.get_latency().is_none() &&
+      port.get_srst().is_none() && port.get_arst().is_none()
+    )
+  }
+
+  pub fn new(params: JsGateParams) -> RomState {
+    let bits = params.get_bits().unwrap_or(1);
+    let abits = params.get_abits().unwrap_or(1);
+    let offset = params.get_offset().unwrap_or(0);
+    let words = params.get_words().map_or(1u64 << abits, |w| w as u64);
+    let fill = Vec3vl::from_binary(params.get_memfill().unwrap_or("x".to_string()), Some(bits as usize));
+    let rdports = (0..params.get_rdports().map_or(0, |v| v.len())).map(|n| format!("rd{n}")).collect();
+
+    let mut data = Vec::new();
+    load_memory(params.get_memdata(), bits, |addr, val| {
+      if (addr as u64) < words {
+        if data.len() <= addr as usize {
+          data.resize(addr as usize + 1, fill.clone());
+        }
+        data[addr as usize] = val;
+      }
+    });
+
+    RomState { bits, offset, words, rdports, table: shared_table(RomTable { bits, fill, data }) }
+  }
+
+  pub fn get(&self, addr: u32) -> Vec3vl {
+    self.table.data.get(addr as usize).unwrap_or(&self.table.fill).clone()
+  }
+
+  pub fn rdports(&self) -> &[String] {
+    &self.rdports
+  }
+
+  pub fn to_memory(&self) -> PagedMemory {
+    let mut memory = PagedMemory::new(self.bits, self.words, self.table.fill.clone());
+    for (addr, word) in self.table.data.iter().enumerate() {
+      memory.set(addr as u32, word);
+    }
+    memory
+  }
+
+  // Replaces the whole contents, returning the previous ones.
+  pub fn replace(&mut self, memory: &PagedMemory) -> PagedMemory {
+    let old = self.to_memory();
+    let data = (0..memory.allocated_end()).map(|addr| memory.get(addr as u32)).collect();
+    self.table = shared_table(RomTable { bits: self.bits, fill: memory.fill().clone(), data });
+    old
+  }
+
+  // Changes one word of this instance only, copying the table if it is shared.
+  pub fn set(&mut self, addr: u32, val: Vec3vl) {
+    let table = Arc::make_mut(&mut self.table);
+    if table.data.len() <= addr as usize {
+      table.data.resize(addr as usize + 1, table.fill.clone());
+    }
+    table.data[addr as usize] = val;
+  }
+}
+
+fn shared_table(table: RomTable) -> Arc<RomTable> {
+  let hash = table.content_hash();
+  let mut tables = TABLES.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+
+  if let Some(existing) = tables.get(&hash).and_then(Weak::upgrade) {
+    if *existing == table {
+      return existing;
+    }
+  }
+
+  tables.retain(|_, t| t.strong_count() > 0);
+  let table = Arc::new(table);
+  tables.insert(hash, Arc::downgrade(&table));
+  table
+}
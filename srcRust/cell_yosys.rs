@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::JsValue;
+
+use crate::operations::ReturnValue;
+use crate::vector3vl::Vec3vl;
+
+// Yosys internal cells, evaluated with the ports (`A`, `B`, `S`, `Y`) and semantics of
+// the Yosys simulation library.
+
+#[derive(Clone, Copy)]
+pub struct ShiftParams {
+    pub a_signed: bool,
+    pub b_signed: bool,
+    pub y_width: u32,
+    // `$shiftx` reads x outside of `A` and does not extend it
+    pub fill_x: bool
+}
+
+fn input<'a>(args: &'a HashMap<String, Vec3vl>, port: &str) -> Result<&'a Vec3vl, String> {
+    args.get(port).ok_or_else(|| format!("No input {port}"))
+}
+
+fn output(val: Vec3vl) -> Result<ReturnValue, String> {
+    ReturnValue::values(None, HashMap::from([("Y".to_string(), val)]))
+}
+
+// Bitwise merge of two candidate values: bits on which they agree are kept, others become x.
+fn merge(l: &Vec3vl, r: &Vec3vl) -> Vec3vl {
+    Vec3vl::new(
+        l.bits,
+        l.avec.iter().zip(r.avec.iter()).map(|(a, b)| a & b).collect(),
+        l.bvec.iter().zip(r.bvec.iter()).map(|(a, b)| a | b).collect()
+    )
+}
+
+fn set_bit(v: &mut Vec3vl, n: u32, val: i32) {
+    let (w, b) = ((n / 32) as usize, n % 32);
+    v.avec[w] = (v.avec[w] & !(1 << b)) | (((val == 1) as u32) << b);
+    v.bvec[w] = (v.bvec[w] & !(1 << b)) | (((val != -1) as u32) << b);
+}
+
+// Entry of `table` at the index given by `sel`, for `1 << sel.bits` entries. Select bits
+// are taken from the lowest: a defined bit keeps one of each pair of neighbouring entries
+// and an undefined one merges them, so undefined bits do not add to the cost.
+fn select(sel: &Vec3vl, width: u32, mut table: Vec<Vec3vl>) -> Vec3vl {
+    for n in 0..sel.bits {
+        let bit = sel.get(n);
+        table = table.chunks(2).map(|pair| match (bit, pair) {
+            (1, [_, r]) => r.clone(),
+            (0, [l, r]) => merge(l, r),
+            _ => pair[0].clone()
+        }).collect();
+    }
+    table.into_iter().next().unwrap_or_else(|| Vec3vl::xes(width))
+}
+
+pub fn pmux(args: &HashMap<String, Vec3vl>, width: u32, s_width: u32) -> Result<ReturnValue, String> {
+    let (a, b, s) = (input(args, "A")?, input(args, "B")?, input(args, "S")?);
+    if !s.is_fully_defined() {
+        return output(Vec3vl::xes(width));
+    }
+
+    let hot = (0..s_width).filter(|n| s.get(*n) == 1).collect::<Vec<u32>>();
+    output(match hot[..] {
+        [] => a.clone(),
+        [n] => b.slice(n * width, (n + 1) * width)?,
+        _ => Vec3vl::xes(width)
+    })
+}
+
+pub fn bmux(args: &HashMap<String, Vec3vl>, width: u32) -> Result<ReturnValue, String> {
+    let (a, s) = (input(args, "A")?, input(args, "S")?);
+    let slices = (0..a.bits / width.max(1))
+        .map(|idx| a.slice(idx * width, (idx + 1) * width))
+        .collect::<Result<Vec<Vec3vl>, String>>()?;
+    output(select(s, width, slices))
+}
+
+pub fn demux(args: &HashMap<String, Vec3vl>, width: u32, s_width: u32) -> Result<ReturnValue, String> {
+    let (a, s) = (input(args, "A")?, input(args, "S")?);
+    let mut slices = vec![Vec3vl::zeros(width); 1 << s_width];
+
+    // Slices whose index agrees with the defined bits of `S` are selected.
+    let (mut mask, mut selected) = (0usize, 0usize);
+    for n in 0..s.bits.min(s_width) {
+        match s.get(n) {
+            1 => { mask |= 1 << n; selected |= 1 << n; },
+            -1 => mask |= 1 << n,
+            _ => {}
+        }
+    }
+    let value = if s.is_fully_defined() { a.clone() } else { merge(a, &Vec3vl::zeros(width)) };
+    for (idx, slice) in slices.iter_mut().enumerate() {
+        if idx & mask == selected {
+            *slice = value.clone();
+        }
+    }
+    output(Vec3vl::concat(&mut slices)?)
+}
+
+// Signed shift amounts saturate well beyond any port width.
+fn shift_amount(b: &Vec3vl, signed: bool) -> Option<i64> {
+    if !b.is_fully_defined() { return None; }
+    let limit = 1i64 << 40;
+    let mut amount = if signed && b.bits > 0 && b.msb() == 1 { -1i64 } else { 0 };
+    for n in (0..b.bits).rev() {
+        amount = (amount * 2 + (b.get(n) == 1) as i64).clamp(-limit, limit);
+    }
+    Some(amount)
+}
+
+pub fn shift(args: &HashMap<String, Vec3vl>, params: &ShiftParams) -> Result<ReturnValue, String> {
+    let (a, b) = (input(args, "A")?, input(args, "B")?);
+    let amount = match shift_amount(b, params.b_signed) {
+        Some(amount) => amount,
+        None => return output(Vec3vl::xes(params.y_width))
+    };
+
+    let (fill, width) = if params.fill_x { (0, a.bits) } else { (-1, a.bits.max(params.y_width)) };
+    let ext = |n: i64| -> i32 {
+        if n < 0 || n >= width as i64 { fill }
+        else if n < a.bits as i64 { a.get(n as u32) }
+        else if params.a_signed && a.bits > 0 { a.msb() }
+        else { -1 }
+    };
+
+    let mut y = Vec3vl::zeros(params.y_width);
+    for n in 0..params.y_width {
+        set_bit(&mut y, n, ext(n as i64 + amount));
+    }
+    output(y)
+}
+
+pub fn lut(args: &HashMap<String, Vec3vl>, table: &Vec3vl) -> Result<ReturnValue, String> {
+    let a = input(args, "A")?;
+    let entries = (0..table.bits)
+        .map(|idx| Vec3vl::make_int(1, table.get(idx)))
+        .collect::<Result<Vec<Vec3vl>, String>>()?;
+    output(select(a, 1, entries))
+}
+
+// `table` holds two bits per input of each product term: bit 0 requires the input
+// to be 0, bit 1 requires it to be 1.
+pub fn sop(args: &HashMap<String, Vec3vl>, width: u32, depth: u32, table: &Vec3vl) -> Result<ReturnValue, String> {
+    let a = input(args, "A")?;
+    let and = |l: i32, r: i32| l.min(r);
+    let or = |l: i32, r: i32| l.max(r);
+
+    let y = (0..depth).fold(-1, |acc, term| {
+        let product = (0..width).fold(1, |prod, n| {
+            let base = 2 * (term * width + n);
+            let mut lit = 1;
+            if table.get(base) == 1 { lit = and(lit, -a.get(n)); }
+            if table.get(base + 1) == 1 { lit = and(lit, a.get(n)); }
+            and(prod, lit)
+        });
+        or(acc, product)
+    });
+    output(Vec3vl::make_int(1, y)?)
+}
+
+pub fn bweqx(args: &HashMap<String, Vec3vl>) -> Result<ReturnValue, String> {
+    let (a, b) = (input(args, "A")?, input(args, "B")?);
+    let eq = a.avec.iter().zip(b.avec.iter()).zip(a.bvec.iter().zip(b.bvec.iter()))
+        .map(|((aa, ba), (ab, bb))| !((aa ^ ba) | (ab ^ bb)))
+        .collect::<Vec<u32>>();
+    let mut y = Vec3vl::new(a.bits, eq.clone(), eq);
+    y.normalize();
+    output(y)
+}
+
+pub fn bwmux(args: &HashMap<String, Vec3vl>) -> Result<ReturnValue, String> {
+    let (a, b, s) = (input(args, "A")?, input(args, "B")?, input(args, "S")?);
+    let mut y = merge(a, b);
+    for w in 0..y.avec.len() {
+        let one = s.avec[w] & s.bvec[w];
+        let zero = !s.avec[w] & !s.bvec[w];
+        let keep = !(one | zero);
+        y.avec[w] = (y.avec[w] & keep) | (b.avec[w] & one) | (a.avec[w] & zero);
+        y.bvec[w] = (y.bvec[w] & keep) | (b.bvec[w] & one) | (a.bvec[w] & zero);
+    }
+    y.normalize();
+    output(y)
+}
+
+// Yosys writes integer parameters either as numbers or as binary strings.
+pub fn param_num(value: Option<JsValue>, default: u32) -> u32 {
+    match value {
+        Some(v) if v.as_f64().is_some() => v.as_f64().unwrap() as u32,
+        Some(v) => v.as_string()
+            .and_then(|s| u32::from_str_radix(&s[s.len().saturating_sub(32)..], 2).ok())
+            .unwrap_or(default),
+        None => default
+    }
+}
+
+pub fn param_bits(value: Option<JsValue>, bits: u32) -> Vec3vl {
+    match value {
+        Some(v) if v.as_f64().is_some() => Vec3vl::from_number(v.as_f64().unwrap() as u32, bits),
+        Some(v) => Vec3vl::from_binary(v.as_string().unwrap_or("x".to_string()), Some(bits as usize)),
+        None => Vec3vl::xes(bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vec(data: &str) -> Vec3vl {
+        Vec3vl::from_binary(data.to_string(), None)
+    }
+
+    fn binary(v: &Vec3vl) -> String {
+        (0..v.bits).rev()
+            .map(|n| match v.get(n) {
+                1 => '1',
+                -1 => '0',
+                _ => 'x'
+            })
+            .collect()
+    }
+
+    fn eval(result: Result<ReturnValue, String>) -> String {
+        let result = result.unwrap();
+        let (_, y) = result.signals_iter().find(|(port, _)| *port == "Y").unwrap();
+        binary(y)
+    }
+
+    fn args(ports: &[(&str, Vec3vl)]) -> HashMap<String, Vec3vl> {
+        ports.iter().map(|(port, v)| (port.to_string(), v.clone())).collect()
+    }
+
+    #[test]
+    fn bmux_merges_selected_slices() {
+        let a = vec("00110101");
+        assert_eq!(eval(bmux(&args(&[("A", a.clone()), ("S", vec("10"))]), 2)), "11");
+        assert_eq!(eval(bmux(&args(&[("A", a.clone()), ("S", vec("x0"))]), 2)), "x1");
+        assert_eq!(eval(bmux(&args(&[("A", a), ("S", vec("xx"))]), 2)), "xx");
+    }
+
+    #[test]
+    fn bmux_with_wide_undefined_select() {
+        let s = Vec3vl::xes(20);
+        let mut a = Vec3vl::ones(1 << 20);
+        assert_eq!(eval(bmux(&args(&[("A", a.clone()), ("S", s.clone())]), 1)), "1");
+        a.avec[1000] = 0;
+        a.bvec[1000] = 0;
+        assert_eq!(eval(bmux(&args(&[("A", a), ("S", s)]), 1)), "x");
+    }
+
+    #[test]
+    fn demux_merges_input_with_zero() {
+        let a = vec("10");
+        assert_eq!(eval(demux(&args(&[("A", a.clone()), ("S", vec("1"))]), 2, 1)), "1000");
+        assert_eq!(eval(demux(&args(&[("A", a.clone()), ("S", vec("x"))]), 2, 1)), "x0x0");
+        assert_eq!(eval(demux(&args(&[("A", a), ("S", vec("1x"))]), 2, 2)), "x0x00000");
+    }
+
+    #[test]
+    fn demux_with_wide_undefined_select() {
+        let y = eval(demux(&args(&[("A", vec("1")), ("S", Vec3vl::xes(20))]), 1, 20));
+        assert_eq!(y.len(), 1 << 20);
+        assert!(y.chars().all(|c| c == 'x'));
+    }
+
+    #[test]
+    fn lut_merges_table_entries() {
+        let table = vec("1100");
+        assert_eq!(eval(lut(&args(&[("A", vec("10"))]), &table)), "1");
+        assert_eq!(eval(lut(&args(&[("A", vec("1x"))]), &table)), "1");
+        assert_eq!(eval(lut(&args(&[("A", vec("x0"))]), &table)), "x");
+    }
+}
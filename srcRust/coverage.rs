@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::JsValue;
+
+use crate::monitor::PortRef;
+use crate::vector3vl::Vec3vl;
+
+// Toggle coverage of gate outputs, collected between startCoverage and stopCoverage.
+// Counters are kept per bit; ports that never changed are reported from their
+// current value.
+pub struct Coverage {
+    start: u32,
+    ports: HashMap<PortRef, PortStats>
+}
+
+#[derive(Clone)]
+struct PortStats {
+    rise: Vec<u32>,
+    fall: Vec<u32>,
+    to_x: Vec<u32>,
+    from_x: Vec<u32>,
+    // Ticks spent at 0, x and 1, indexed by `Vec3vl::get(bit) + 1`.
+    time: [Vec<u32>; 3],
+    last: Vec3vl,
+    since: u32
+}
+
+impl Coverage {
+    pub fn new(tick: u32) -> Coverage {
+        Coverage { start: tick, ports: HashMap::new() }
+    }
+
+    pub fn record(&mut self, port: PortRef, old: &Vec3vl, new: &Vec3vl, tick: u32) {
+        let start = self.start;
+        let stats = self.ports.entry(port).or_insert_with(|| PortStats::new(old, start));
+        if stats.last.bits != new.bits { return; }
+
+        stats.settle(tick);
+        for bit in 0..new.bits {
+            let (o, n) = (stats.last.get(bit), new.get(bit));
+            let b = bit as usize;
+            match (o, n) {
+                (-1, 1) => stats.rise[b] += 1,
+                (1, -1) => stats.fall[b] += 1,
+                (0, 0) => { },
+                (0, _) => stats.from_x[b] += 1,
+                (_, 0) => stats.to_x[b] += 1,
+                _ => { }
+            }
+        }
+        stats.last = new.clone();
+    }
+
+    pub fn report(&self, port: &PortRef, current: &Vec3vl, tick: u32) -> JsValue {
+        let mut stats = match self.ports.get(port) {
+            Some(s) => s.clone(),
+            None => PortStats::new(current, self.start)
+        };
+        stats.settle(tick);
+        stats.to_js()
+    }
+}
+
+impl PortStats {
+    fn new(value: &Vec3vl, since: u32) -> PortStats {
+        let zeros = vec![0; value.bits as usize];
+        PortStats {
+            rise: zeros.clone(),
+            fall: zeros.clone(),
+            to_x: zeros.clone(),
+            from_x: zeros.clone(),
+            time: [zeros.clone(), zeros.clone(), zeros],
+            last: value.clone(),
+            since
+        }
+    }
+
+    fn settle(&mut self, tick: u32) {
+        let elapsed = tick.wrapping_sub(self.since);
+        for bit in 0..self.last.bits {
+            self.time[(self.last.get(bit) + 1) as usize][bit as usize] += elapsed;
+        }
+        self.since = tick;
+    }
+
+    fn to_js(&self) -> JsValue {
+        let array = |v: &Vec<u32>| v.iter().map(|n| JsValue::from(*n)).collect::<Array>();
+        let toggled = (0..self.rise.len()).filter(|b| self.rise[*b] > 0 && self.fall[*b] > 0).count() as u32;
+
+        let obj = Object::new();
+        let _ = Reflect::set(&obj, &"bits".into(), &self.last.bits.into());
+        let _ = Reflect::set(&obj, &"rise".into(), &array(&self.rise));
+        let _ = Reflect::set(&obj, &"fall".into(), &array(&self.fall));
+        let _ = Reflect::set(&obj, &"toX".into(), &array(&self.to_x));
+        let _ = Reflect::set(&obj, &"fromX".into(), &array(&self.from_x));
+        let _ = Reflect::set(&obj, &"time0".into(), &array(&self.time[0]));
+        let _ = Reflect::set(&obj, &"timeX".into(), &array(&self.time[1]));
+        let _ = Reflect::set(&obj, &"time1".into(), &array(&self.time[2]));
+        let _ = Reflect::set(&obj, &"toggled".into(), &toggled.into());
+        obj.into()
+    }
+}
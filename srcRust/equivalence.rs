@@ -0,0 +1,171 @@
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::JsValue;
+
+use crate::arena::{GateId, GraphId};
+use crate::campaign::DEFAULT_SETTLE;
+use crate::gate::Gate;
+use crate::js_types::JsEquivalenceParams;
+use crate::vector3vl::Vec3vl;
+use crate::RustEngine;
+
+const DEFAULT_CYCLES: u32 = 1000;
+const DEFAULT_EXHAUSTIVE_BITS: u32 = 16;
+
+struct Ports {
+    inputs: Vec<(String, GateId, GateId)>,
+    outputs: Vec<(String, GateId, GateId)>,
+    clocks: Vec<GateId>
+}
+
+// Drives two graphs with the same input sequence and compares their outputs after
+// every cycle. Inputs and outputs are paired by name. When the inputs are at most
+// `exhaustiveBits` wide, every input combination is applied once, otherwise
+// `cycles` random vectors are. In every cycle the clocks are held low while the
+// inputs change and then raised, and the outputs are compared after both settle.
+pub fn run(engine: &RustEngine, graph: GraphId, reference: GraphId, params: &JsEquivalenceParams) -> Result<JsValue, String> {
+    let mut sim = engine.scratch_priv();
+    let ports = Ports::new(&sim, graph, reference)?;
+    let settle = params.get_settle().unwrap_or(DEFAULT_SETTLE);
+
+    let widths = ports.inputs.iter()
+        .map(|(_, g, _)| sim.gate(*g)?.get_port_value(&"out".to_string()).map(|v| v.bits))
+        .collect::<Result<Vec<u32>, String>>()?;
+    let total = widths.iter().sum::<u32>();
+    let exhaustive = total <= params.get_exhaustive_bits().unwrap_or(DEFAULT_EXHAUSTIVE_BITS).min(31);
+    let cycles = if exhaustive { 1u32 << total } else { params.get_cycles().unwrap_or(DEFAULT_CYCLES) };
+    let mut rng = XorShift::new(params.get_seed().unwrap_or(1));
+
+    let mut sequence = Vec::new();
+    let mut mismatch = None;
+    for cycle in 0..cycles {
+        let mut offset = 0;
+        let vector = widths.iter().map(|bits| {
+            let v = if exhaustive {
+                Vec3vl::from_number((cycle >> offset) & ((1u32 << bits) - 1), *bits)
+            } else {
+                rng.vector(*bits)
+            };
+            offset += bits;
+            v
+        }).collect::<Vec<Vec3vl>>();
+
+        for clock in &ports.clocks {
+            sim.set_gate_output_signal_priv(*clock, "out".to_string(), Vec3vl::zeros(1))?;
+        }
+        for ((_, a, b), value) in ports.inputs.iter().zip(&vector) {
+            sim.set_gate_output_signal_priv(*a, "out".to_string(), value.clone())?;
+            sim.set_gate_output_signal_priv(*b, "out".to_string(), value.clone())?;
+        }
+        sim.settle_priv(settle)?;
+        for clock in &ports.clocks {
+            sim.set_gate_output_signal_priv(*clock, "out".to_string(), Vec3vl::ones(1))?;
+        }
+        sim.settle_priv(settle)?;
+        sequence.push(vector);
+
+        for (name, a, b) in &ports.outputs {
+            let actual = sim.gate(*a)?.get_input(&"in".to_string())?;
+            let expected = sim.gate(*b)?.get_input(&"in".to_string())?;
+            if actual != expected {
+                mismatch = Some((cycle, name.clone(), expected, actual));
+                break;
+            }
+        }
+        if mismatch.is_some() { break; }
+    }
+
+    let names = |list: &Vec<(String, GateId, GateId)>| list.iter()
+        .map(|(name, _, _)| JsValue::from(name.as_str()))
+        .collect::<Array>();
+
+    let obj = Object::new();
+    let _ = Reflect::set(&obj, &"equivalent".into(), &mismatch.is_none().into());
+    let _ = Reflect::set(&obj, &"mode".into(), &(if exhaustive { "exhaustive" } else { "random" }).into());
+    let _ = Reflect::set(&obj, &"cycles".into(), &(sequence.len() as u32).into());
+    let _ = Reflect::set(&obj, &"inputs".into(), &names(&ports.inputs));
+    let _ = Reflect::set(&obj, &"outputs".into(), &names(&ports.outputs));
+    let _ = Reflect::set(&obj, &"mismatch".into(), &match mismatch {
+        None => JsValue::NULL,
+        Some((cycle, output, expected, actual)) => {
+            let m = Object::new();
+            let _ = Reflect::set(&m, &"cycle".into(), &cycle.into());
+            let _ = Reflect::set(&m, &"output".into(), &output.as_str().into());
+            let _ = Reflect::set(&m, &"expected".into(), &expected.to_clonable());
+            let _ = Reflect::set(&m, &"actual".into(), &actual.to_clonable());
+            let _ = Reflect::set(&m, &"sequence".into(), &sequence.iter()
+                .map(|vector| vector.iter().map(Vec3vl::to_clonable).collect::<Array>())
+                .collect::<Array>());
+            m.into()
+        }
+    });
+    Ok(obj.into())
+}
+
+impl Ports {
+    fn new(sim: &RustEngine, graph: GraphId, reference: GraphId) -> Result<Ports, String> {
+        let list = |graph: GraphId, select: fn(&Gate) -> bool| -> Result<Vec<(String, GateId)>, String> {
+            let mut found = Vec::new();
+            for id in sim.graph(graph)?.gate_iter() {
+                let gate = sim.gate(*id)?;
+                if select(gate) { found.push((gate.get_name(), *id)); }
+            }
+            found.sort_by(|a, b| a.0.cmp(&b.0));
+            Ok(found)
+        };
+        let pair = |select: fn(&Gate) -> bool, port: &str| -> Result<Vec<(String, GateId, GateId)>, String> {
+            let ours = list(graph, select)?;
+            let theirs = list(reference, select)?;
+            let names = |l: &Vec<(String, GateId)>| l.iter().map(|(n, _)| n.clone()).collect::<Vec<String>>();
+            if names(&ours) != names(&theirs) {
+                return Err(format!("Circuits have different {port}s: {:?} and {:?}", names(&ours), names(&theirs)));
+            }
+
+            let port_name = if port == "input" { "out" } else { "in" }.to_string();
+            let mut pairs = Vec::new();
+            for ((name, a), (_, b)) in ours.into_iter().zip(theirs) {
+                let (wa, wb) = (sim.gate(a)?.get_port_value(&port_name)?.bits, sim.gate(b)?.get_port_value(&port_name)?.bits);
+                if wa != wb {
+                    return Err(format!("The {port} {name} is {wa} bits wide in one circuit and {wb} in the other"));
+                }
+                pairs.push((name, a, b));
+            }
+            Ok(pairs)
+        };
+
+        let clocks = [graph, reference].iter()
+            .map(|g| list(*g, |gate| gate.get_cell_type() == "Clock"))
+            .collect::<Result<Vec<Vec<(String, GateId)>>, String>>()?
+            .into_iter()
+            .flatten()
+            .map(|(_, id)| id)
+            .collect();
+
+        Ok(Ports {
+            inputs: pair(Gate::is_io_input, "input")?,
+            outputs: pair(Gate::is_io_output, "output")?,
+            clocks
+        })
+    }
+}
+
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u32) -> XorShift {
+        XorShift(0x9e3779b97f4a7c15 ^ seed as u64)
+    }
+
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 32) as u32
+    }
+
+    fn vector(&mut self, bits: u32) -> Vec3vl {
+        let words = (0..bits.div_ceil(32)).map(|_| self.next()).collect::<Vec<u32>>();
+        let mut v = Vec3vl::new(bits, words.clone(), words);
+        v.normalize();
+        v
+    }
+}
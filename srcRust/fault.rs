@@ -0,0 +1,77 @@
+use crate::arena::GateId;
+use crate::js_types::JsFaultParams;
+use crate::monitor::PortRef;
+use crate::vector3vl::Vec3vl;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FaultKind {
+    StuckAt0,
+    StuckAt1,
+    StuckAtX,
+    BitFlip
+}
+
+#[derive(Clone)]
+pub enum FaultSite {
+    Port(PortRef),
+    Memory(GateId, u32)
+}
+
+// A fault on one port or memory word, limited to a single bit when `bit` is given.
+// Stuck-at faults stay until removed, starting at `tick` when given; bit flips happen once at `tick`.
+#[derive(Clone)]
+pub struct Fault {
+    pub site: FaultSite,
+    pub kind: FaultKind,
+    pub bit: Option<u32>,
+    pub tick: Option<u32>
+}
+
+impl Fault {
+    pub fn new(site: FaultSite, params: &JsFaultParams) -> Result<Fault, String> {
+        let kind = match params.get_type().as_str() {
+            "stuck0" => FaultKind::StuckAt0,
+            "stuck1" => FaultKind::StuckAt1,
+            "stuckX" => FaultKind::StuckAtX,
+            "flip"   => FaultKind::BitFlip,
+            t => return Err(format!("Unknown fault type {t}"))
+        };
+
+        let tick = params.get_tick();
+        if kind == FaultKind::BitFlip && tick.is_none() {
+            return Err("Bit flip fault needs a tick".to_string());
+        }
+        if matches!(site, FaultSite::Memory(_, _)) && kind != FaultKind::BitFlip {
+            return Err("Memory faults can only be bit flips".to_string());
+        }
+
+        Ok(Fault { site, kind, bit: params.get_bit(), tick })
+    }
+
+    pub fn is_stuck(&self) -> bool {
+        self.kind != FaultKind::BitFlip
+    }
+
+    pub fn apply(&self, sig: &Vec3vl) -> Vec3vl {
+        let mut v = sig.clone();
+        for (i, (a, b)) in v.avec.iter_mut().zip(v.bvec.iter_mut()).enumerate() {
+            let m = match self.bit {
+                Some(bit) if bit / 32 == i as u32 => 1u32 << (bit % 32),
+                Some(_) => 0,
+                None => !0
+            };
+            match self.kind {
+                FaultKind::StuckAt0 => { *a &= !m; *b &= !m; },
+                FaultKind::StuckAt1 => { *a |= m; *b |= m; },
+                FaultKind::StuckAtX => { *a &= !m; *b |= m; },
+                FaultKind::BitFlip => {
+                    let defined = !(*a ^ *b) & m;
+                    *a ^= defined;
+                    *b ^= defined;
+                }
+            }
+        }
+        v.normalize();
+        v
+    }
+}
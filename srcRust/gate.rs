@@ -1,30 +1,44 @@
-use std::cell::RefCell;
+use std::borrow::Cow;
 use std::collections::{hash_map::Iter, HashMap, HashSet};
-use std::rc::Rc;
+use std::sync::Arc;
 
-use crate::graph::GraphPtr;
+use wasm_bindgen::JsValue;
+
+use crate::arena::GraphId;
+use crate::cell_memory::MemoryState;
 use crate::js_types::{DffPolarityStruct, JsGateParams, PortParams};
 use crate::link::LinkTarget;
 use crate::operations::{Operation, ReturnValue};
+use crate::paged_memory::PagedMemory;
 use crate::vector3vl::Vec3vl;
 
-pub type GatePtr = Rc<RefCell<Gate>>;
-
+#[derive(Clone)]
 pub struct Gate {
-    id: String,
-    graph: GraphPtr,
+    def: Arc<GateDefinition>,
+    graph: GraphId,
     in_vals: HashMap<String, Vec3vl>,
     out_vals: HashMap<String, Vec3vl>,
+    subgraph: Option<GraphId>,
+    subgraph_io_map: Option<Arc<HashMap<String, String>>>,
+    operation: Operation,
+    monitors: HashMap<String, Vec<u32>>
+}
+
+// Part of a gate that does not change during simulation, shared between
+// all instances of a module until one of them gets relinked.
+#[derive(Clone)]
+struct GateDefinition {
+    id: String,
+    label: Option<String>,
     links: HashSet<String>,
     linked_to: HashMap<String, Vec<LinkTarget>>,
     propagation: u32,
+    delay: Option<PortDelay>,
+    port_delays: HashMap<String, PortDelay>,
+    delay_model: DelayModel,
     gate_type: String,
-    subgraph: Option<GraphPtr>,
-    subgraph_io_map: Option<HashMap<String, String>>,
     subgraph_net: Option<String>,
     io_dirs: HashMap<String, IoDir>,
-    operation: Operation,
-    monitors: HashMap<String, Vec<u32>>
 }
 
 #[derive(Clone, PartialEq)]
@@ -32,94 +46,267 @@ pub enum IoDir {
     In, Out
 }
 
+// Transport delay passes every input pulse to the output. Inertial delay
+// drops pulses shorter than the propagation, as a pending output change is
+// cancelled when the gate settles on a different value before it comes out.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DelayModel {
+    Transport, Inertial
+}
+
+#[derive(Clone, Copy)]
+pub struct PortDelay {
+    pub rise: u32,
+    pub fall: u32
+}
+
+impl PortDelay {
+    // Rise delay when bits only go to 1, fall delay when they only go to 0,
+    // and the slower of the two for mixed changes and changes into x.
+    fn for_change(&self, old: &Vec3vl, new: &Vec3vl) -> u32 {
+        let (mut old, mut new) = (old.clone(), new.clone());
+        old.normalize();
+        new.normalize();
+
+        let words = new.avec.len().min(old.avec.len());
+        let rising = (0..words).any(|i| new.avec[i] & !old.avec[i] != 0);
+        let falling = (0..words).any(|i| !new.bvec[i] & old.bvec[i] != 0);
+        match (rising, falling) {
+            (true, false) => self.rise,
+            (false, true) => self.fall,
+            _ => self.rise.max(self.fall)
+        }
+    }
+}
+
+impl DelayModel {
+    pub fn from_name(name: &str) -> Result<DelayModel, String> {
+        match name {
+            "transport" => Ok(DelayModel::Transport),
+            "inertial" => Ok(DelayModel::Inertial),
+            _ => Err(format!("Unknown delay model {name}"))
+        }
+    }
+}
+
 impl Gate {
-    pub fn new(graph: GraphPtr, graph_id: String, gate_id: String, gate_params: JsGateParams, port_params: Vec<PortParams>) -> Result<GatePtr, String> {
+    pub fn new(graph: GraphId, graph_id: String, gate_id: String, gate_params: JsGateParams, port_params: Vec<PortParams>) -> Result<Gate, String> {
         let propagation = gate_params.get_propagation().unwrap_or(0);
+        let delay = match (gate_params.get_rise_delay(), gate_params.get_fall_delay()) {
+            (None, None) => None,
+            (rise, fall) => Some(PortDelay { rise: rise.unwrap_or(propagation), fall: fall.unwrap_or(propagation) })
+        };
+        let delay_model = match gate_params.get_delay_model() {
+            Some(m) => DelayModel::from_name(&m)?,
+            None => DelayModel::Transport
+        };
         let subgraph_net = gate_params.get_net();
+        let label = gate_params.get_label().filter(|l| !l.is_empty());
 
         let op_type = gate_params.get_type();
         let op = Operation::from_name(op_type.clone(), gate_params, graph_id, gate_id.clone())?;
 
-        let mut g = Gate {
+        let mut def = GateDefinition {
             id: gate_id,
-            graph,
-            in_vals: HashMap::new(),
-            out_vals: HashMap::new(),
+            label,
             links: HashSet::new(),
             linked_to: HashMap::new(),
             propagation,
+            delay,
+            port_delays: HashMap::new(),
+            delay_model,
             gate_type: op_type,
-            subgraph: None,
-            subgraph_io_map: None,
             subgraph_net,
             io_dirs: HashMap::new(),
-            operation: op,
-            monitors: HashMap::new()
         };
+        let mut in_vals = HashMap::new();
+        let mut out_vals = HashMap::new();
 
         for p in port_params {
             if p.get_dir() == "in" {
-                g.in_vals.insert(p.get_id(), Vec3vl::new(p.get_bits(), p.get_avec(), p.get_bvec()));
-                g.io_dirs.insert(p.get_id(), IoDir::In);
+                in_vals.insert(p.get_id(), Vec3vl::new(p.get_bits(), p.get_avec(), p.get_bvec()));
+                def.io_dirs.insert(p.get_id(), IoDir::In);
             } else {
-                g.out_vals.insert(p.get_id(), Vec3vl::new(p.get_bits(), p.get_avec(), p.get_bvec()));
-                g.linked_to.insert(p.get_id(), Vec::new());
-                g.io_dirs.insert(p.get_id(), IoDir::Out);
+                out_vals.insert(p.get_id(), Vec3vl::new(p.get_bits(), p.get_avec(), p.get_bvec()));
+                def.linked_to.insert(p.get_id(), Vec::new());
+                def.io_dirs.insert(p.get_id(), IoDir::Out);
+            }
+        }
+
+        if let Operation::Memory(memory_state) = &op {
+            for (port, latency) in memory_state.read_latencies() {
+                def.port_delays.insert(port, PortDelay { rise: latency, fall: latency });
             }
         }
 
-        Ok(Rc::new(RefCell::new(g)))
+        Ok(Gate {
+            def: Arc::new(def),
+            graph,
+            in_vals,
+            out_vals,
+            subgraph: None,
+            subgraph_io_map: None,
+            operation: op,
+            monitors: HashMap::new()
+        })
+    }
+
+    // Native tests cannot build `JsGateParams`, so they give the operation and port values directly.
+    #[cfg(test)]
+    pub fn with_operation(graph: GraphId, gate_id: String, operation: Operation, ports: Vec<(&str, IoDir, Vec3vl)>) -> Gate {
+        let mut def = GateDefinition {
+            id: gate_id,
+            label: None,
+            links: HashSet::new(),
+            linked_to: HashMap::new(),
+            propagation: 1,
+            delay: None,
+            port_delays: HashMap::new(),
+            delay_model: DelayModel::Transport,
+            gate_type: String::new(),
+            subgraph_net: None,
+            io_dirs: HashMap::new(),
+        };
+        let mut in_vals = HashMap::new();
+        let mut out_vals = HashMap::new();
+
+        for (port, dir, value) in ports {
+            if dir == IoDir::In {
+                in_vals.insert(port.to_string(), value);
+            } else {
+                out_vals.insert(port.to_string(), value);
+                def.linked_to.insert(port.to_string(), Vec::new());
+            }
+            def.io_dirs.insert(port.to_string(), dir);
+        }
+
+        Gate {
+            def: Arc::new(def),
+            graph,
+            in_vals,
+            out_vals,
+            subgraph: None,
+            subgraph_io_map: None,
+            operation,
+            monitors: HashMap::new()
+        }
     }
 
     pub fn get_id(&self) -> String {
-        self.id.clone()
+        self.def.id.clone()
+    }
+
+    pub fn get_name(&self) -> String {
+        self.def.label.clone()
+            .or_else(|| self.def.subgraph_net.clone())
+            .unwrap_or_else(|| self.def.id.clone())
     }
 
-    pub fn graph_id(&self) -> String {
-        self.graph.borrow().get_id()
+    pub fn has_name(&self, name: &str) -> bool {
+        self.def.label.as_deref() == Some(name) ||
+        self.def.subgraph_net.as_deref() == Some(name) ||
+        self.def.id == name
+    }
+
+    pub fn duplicate(&self, graph: GraphId, graph_id: String) -> Gate {
+        let mut gate = self.clone();
+        gate.graph = graph;
+        gate.subgraph = None;
+        gate.monitors = HashMap::new();
+        gate.operation.set_graph_id(graph_id);
+        gate
     }
 
     pub fn add_link_to(&mut self, port: &String, target: LinkTarget) -> Result<(), String> {
-        match self.linked_to.get_mut(port) {
+        let def = Arc::make_mut(&mut self.def);
+        match def.linked_to.get_mut(port) {
             Some(v) => { 
                 v.push(target);
                 Ok(())
             },
-            None => Err(format!("Gate {} has no port {}", self.id, port))
+            None => Err(format!("Gate {} has no port {}", def.id, port))
         }
     }
 
     pub fn remove_link_to(&mut self, port: &String, target: LinkTarget) -> Result<(), String> {
-        match self.linked_to.get_mut(port) {
+        let def = Arc::make_mut(&mut self.def);
+        match def.linked_to.get_mut(port) {
             Some(v) => {
                 v.retain(|t| t.id != target.id);
                 Ok(())
             },
-            None => Err(format!("Gate {} has no port {}", self.id, port))
+            None => Err(format!("Gate {} has no port {}", def.id, port))
         }
     }
 
     pub fn add_link(&mut self, link_id: String) {
-        self.links.insert(link_id);
+        Arc::make_mut(&mut self.def).links.insert(link_id);
     }
 
     pub fn remove_link(&mut self, link_id: &String) {
-        self.links.remove(link_id);
+        Arc::make_mut(&mut self.def).links.remove(link_id);
     }
 
     pub fn get_links_iter(&self) -> std::collections::hash_set::Iter<'_, String> {
-        self.links.iter()
+        self.def.links.iter()
     }
 
     pub fn get_targets(&self, port: &String) -> Result<Vec<LinkTarget>, String> {
-        match self.linked_to.get(port) {
+        match self.def.linked_to.get(port) {
             Some(l) => Ok(l.clone()),
-            None => Err(format!("Gate {} has no port {}", self.id, port))
+            None => Err(format!("Gate {} has no port {}", self.def.id, port))
         }
     }
 
     pub fn get_propagation(&self) -> u32 {
-        self.propagation
+        self.def.propagation
+    }
+
+    // Gates with rise/fall or per-port delays, or with inertial delay, are evaluated as
+    // soon as their inputs change, and each output change is scheduled with its own delay.
+    pub fn is_timed(&self) -> bool {
+        let inertial = self.def.delay_model == DelayModel::Inertial && self.def.propagation > 0;
+        (self.def.delay.is_some() || !self.def.port_delays.is_empty() || inertial) &&
+            !matches!(self.operation, Operation::Clock(_))
+    }
+
+    fn output_delay(&self, port: &String) -> Option<&PortDelay> {
+        self.def.port_delays.get(port).or(self.def.delay.as_ref())
+    }
+
+    pub fn get_output_delay(&self, port: &String, old: &Vec3vl, new: &Vec3vl) -> u32 {
+        match self.output_delay(port) {
+            Some(d) => d.for_change(old, new),
+            None => self.def.propagation
+        }
+    }
+
+    // Slowest change of an output port, as used by static timing.
+    pub fn get_max_output_delay(&self, port: &String) -> u32 {
+        match self.output_delay(port) {
+            Some(d) => d.rise.max(d.fall),
+            None => self.def.propagation
+        }
+    }
+
+    pub fn set_delay(&mut self, delay: PortDelay) {
+        Arc::make_mut(&mut self.def).delay = Some(delay);
+    }
+
+    pub fn set_port_delay(&mut self, port: &String, delay: PortDelay) -> Result<(), String> {
+        if self.get_port_dir(port)? != IoDir::Out {
+            return Err(format!("Port {} of gate {} is not an output", port, self.def.id));
+        }
+        Arc::make_mut(&mut self.def).port_delays.insert(port.clone(), delay);
+        Ok(())
+    }
+
+    pub fn get_delay_model(&self) -> DelayModel {
+        self.def.delay_model
+    }
+
+    pub fn set_delay_model(&mut self, model: DelayModel) {
+        Arc::make_mut(&mut self.def).delay_model = model;
     }
 
     pub fn get_inputs(&self) -> HashMap<String, Vec3vl> {
@@ -129,14 +316,21 @@ impl Gate {
     pub fn get_input(&self, port: &String) -> Result<Vec3vl, String> {
         match self.in_vals.get(port) {
             Some(i) => Ok(i.clone()),
-            None => Err(format!("Gate {} has no port {}", self.id, port)) 
+            None => Err(format!("Gate {} has no port {}", self.def.id, port)) 
+        }
+    }
+
+    pub fn get_port_value(&self, port: &String) -> Result<Vec3vl, String> {
+        match self.out_vals.get(port).or_else(|| self.in_vals.get(port)) {
+            Some(v) => Ok(v.clone()),
+            None => Err(format!("Gate {} has no port {}", self.def.id, port))
         }
     }
 
     pub fn get_output(&self, port: &String) -> Result<Vec3vl, String> {
         match self.out_vals.get(port) {
             Some(o ) => Ok(o.clone()),
-            None => Err(format!("Gate {} has no port {}", self.id, port))
+            None => Err(format!("Gate {} has no port {}", self.def.id, port))
         }
     }
 
@@ -148,18 +342,18 @@ impl Gate {
         self.in_vals.insert(port, sig);
     }
 
-    pub fn get_graph(&self) -> GraphPtr {
-        self.graph.clone()
+    pub fn get_graph(&self) -> GraphId {
+        self.graph
     }
 
-    pub fn set_subgraph(&mut self, subgraph: GraphPtr) {
+    pub fn set_subgraph(&mut self, subgraph: GraphId) {
         self.subgraph = Some(subgraph);
     }
 
-    pub fn get_subgraph(&self) -> Result<GraphPtr, String> {
-        match &self.subgraph {
-            Some(s ) => Ok(s.clone()),
-            None => Err(format!("Gate {} has no subgraph", self.id))
+    pub fn get_subgraph(&self) -> Result<GraphId, String> {
+        match self.subgraph {
+            Some(s) => Ok(s),
+            None => Err(format!("Gate {} has no subgraph", self.def.id))
         }
     }
 
@@ -167,23 +361,60 @@ impl Gate {
         self.subgraph.is_some()
     }
 
+    pub fn has_port(&self, port: &String) -> bool {
+        self.def.io_dirs.contains_key(port)
+    }
+
     pub fn get_port_dir(&self, port: &String) -> Result<IoDir, String> {
-        match self.io_dirs.get(port) {
+        match self.def.io_dirs.get(port) {
             Some(i) => Ok(i.clone()),
-            None => Err(format!("Gate {} has no port {}", self.id, port))
+            None => Err(format!("Gate {} has no port {}", self.def.id, port))
         }
     }
 
     pub fn is_output(&self) -> bool {
-        self.gate_type == "Output"
+        self.def.gate_type == "Output"
+    }
+
+    // Circuit inputs and outputs, including the interactive ones of top-level graphs.
+    pub fn is_io_input(&self) -> bool {
+        matches!(self.def.gate_type.as_str(), "Input" | "NumEntry" | "Button")
+    }
+
+    pub fn is_io_output(&self) -> bool {
+        matches!(self.def.gate_type.as_str(), "Output" | "NumDisplay" | "Lamp")
     }
 
     pub fn do_operation(&mut self, args: &HashMap<String, Vec3vl>) -> Result<ReturnValue, String> {
         self.operation.op(args)
     }
 
+    pub fn get_cell_type(&self) -> String {
+        self.operation.get_type()
+    }
+
+    pub fn default_capacitance(&self) -> f64 {
+        self.operation.default_capacitance()
+    }
+
+    pub fn is_sequential(&self) -> bool {
+        self.operation.is_sequential()
+    }
+
+    pub fn has_host_effects(&self) -> bool {
+        self.operation.has_host_effects()
+    }
+
+    pub fn async_outputs(&self, port: &str) -> Vec<String> {
+        self.operation.async_outputs(port)
+    }
+
+    pub fn detach_host(&mut self) {
+        self.operation.detach_host();
+    }
+
     pub fn set_subgraph_iomap(&mut self, map: HashMap<String, String>) {
-        self.subgraph_io_map = Some(map);
+        self.subgraph_io_map = Some(Arc::new(map));
     }
 
     pub fn get_subgraph_iomap_port(&self, port: &String) -> Result<String, String> {
@@ -191,7 +422,7 @@ impl Gate {
             Some(iomap) => {
                 match iomap.get(port) {
                     Some(i) => Ok(i.clone()),
-                    None => Err(format!("Gate {} has no port {}", self.id, port))
+                    None => Err(format!("Gate {} has no port {}", self.def.id, port))
                 }
             },
             None => Err("Subgraph has no io map".to_string())
@@ -199,14 +430,14 @@ impl Gate {
     }
 
     pub fn get_subcir_net(&self) -> Result<String, String> {
-        match &self.subgraph_net {
+        match &self.def.subgraph_net {
             Some(n) => Ok(n.clone()),
             None => Err("Subcircuit has no net".to_string())
         }
     }
 
     pub fn iodirs_iter(&self) -> Iter<'_, String, IoDir> {
-        self.io_dirs.iter()
+        self.def.io_dirs.iter()
     }
 
     pub fn monitor(&mut self, port: String, monitor_id: u32) {
@@ -219,6 +450,10 @@ impl Gate {
         }
     }
 
+    pub fn monitor_ids(&self) -> Vec<u32> {
+        self.monitors.values().flatten().copied().collect()
+    }
+
     pub fn get_monitors(&self, port: &String) -> std::slice::Iter<'_, u32> {
         match self.monitors.get(port) {
             Some(vec) => vec.iter(),
@@ -226,15 +461,96 @@ impl Gate {
         }
     }
 
+    pub fn fsm_coverage(&self) -> Result<JsValue, String> {
+        match &self.operation {
+            Operation::Fsm(fsm_state) => Ok(fsm_state.coverage()),
+            _ => Err(format!("Gate id {} type {} is not an FSM", self.def.id, self.operation.get_type()))
+        }
+    }
+
+    pub fn reset_fsm_coverage(&mut self) -> Result<(), String> {
+        match &mut self.operation {
+            Operation::Fsm(fsm_state) => {
+                fsm_state.reset_coverage();
+                Ok(())
+            },
+            _ => Err(format!("Gate id {} type {} is not an FSM", self.def.id, self.operation.get_type()))
+        }
+    }
+
+    pub fn get_memory(&self, addr: u32) -> Result<Vec3vl, String> {
+        match &self.operation {
+            Operation::Memory(memory_state) if (addr as u64) < memory_state.words => Ok(memory_state.memory.get(addr)),
+            Operation::Rom(rom_state) if (addr as u64) < rom_state.words => Ok(rom_state.get(addr)),
+            Operation::Memory(_) | Operation::Rom(_) => Err(format!("Address {} out of range of memory {}", addr, self.def.id)),
+            _ => Err(format!("Attempting to read memory in gate id {} type {}", self.def.id, self.operation.get_type()))
+        }
+    }
+
     pub fn set_memory(&mut self, addr: u32, data: Vec3vl) -> Result<(), String> {
         match &mut self.operation {
-            Operation::Memory(memory_state) => {
-                memory_state.memory[addr as usize] = data;
+            Operation::Memory(memory_state) if (addr as u64) < memory_state.words => {
+                memory_state.memory.set(addr, &data);
+                memory_state.last_read.clear();
+                Ok(())
+            },
+            Operation::Rom(rom_state) if (addr as u64) < rom_state.words => {
+                rom_state.set(addr, data);
                 Ok(())
             },
-            _ => Err(format!("Attempting to chenge memory in gate id {} type {}", self.id, self.operation.get_type()))
+            Operation::Memory(_) | Operation::Rom(_) => Err(format!("Address {} out of range of memory {}", addr, self.def.id)),
+            _ => Err(format!("Attempting to chenge memory in gate id {} type {}", self.def.id, self.operation.get_type()))
+        }
+
+    }
+
+    // Word width and number of words of a memory or ROM.
+    pub fn get_memory_size(&self) -> Result<(u32, u64), String> {
+        match &self.operation {
+            Operation::Memory(memory_state) => Ok((memory_state.bits, memory_state.words)),
+            Operation::Rom(rom_state) => Ok((rom_state.bits, rom_state.words)),
+            _ => Err(format!("Gate id {} type {} is not a memory", self.def.id, self.operation.get_type()))
+        }
+    }
+
+    // Address offset and contents of a memory or ROM.
+    pub fn get_memory_contents(&self) -> Result<(u32, Cow<'_, PagedMemory>), String> {
+        match &self.operation {
+            Operation::Memory(memory_state) => Ok((memory_state.offset, Cow::Borrowed(&memory_state.memory))),
+            Operation::Rom(rom_state) => Ok((rom_state.offset, Cow::Owned(rom_state.to_memory()))),
+            _ => Err(format!("Gate id {} type {} is not a memory", self.def.id, self.operation.get_type()))
+        }
+    }
+
+    // Memory-mapped devices are served by the read path of writable memories, so a ROM
+    // becomes one again when a device is added to it.
+    pub fn make_writable(&mut self, graph_id: String) {
+        if let Operation::Rom(rom_state) = &self.operation {
+            self.operation = Operation::Memory(MemoryState::from_rom(rom_state, graph_id, self.def.id.clone()));
+        }
+    }
+
+    pub fn get_memory_state_mut(&mut self) -> Result<&mut MemoryState, String> {
+        let error = format!("Gate id {} type {} is not a memory", self.def.id, self.operation.get_type());
+        match &mut self.operation {
+            Operation::Memory(memory_state) => {
+                memory_state.last_read.clear();
+                Ok(memory_state)
+            },
+            _ => Err(error)
         }
+    }
 
+    // Replaces the whole memory contents, returning the previous ones.
+    pub fn replace_memory(&mut self, memory: PagedMemory) -> Result<PagedMemory, String> {
+        match &mut self.operation {
+            Operation::Memory(memory_state) => {
+                memory_state.last_read.clear();
+                Ok(std::mem::replace(&mut memory_state.memory, memory))
+            },
+            Operation::Rom(rom_state) => Ok(rom_state.replace(&memory)),
+            _ => Err(format!("Gate id {} type {} is not a memory", self.def.id, self.operation.get_type()))
+        }
     }
 }
 
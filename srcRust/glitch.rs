@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use crate::monitor::PortRef;
+use crate::vector3vl::Vec3vl;
+
+const MAX_GLITCHES: usize = 10000;
+
+pub struct Glitch {
+    pub port: PortRef,
+    pub tick: u32,
+    pub width: u32
+}
+
+// Reports pulses on gate outputs: a value that returns to what it was
+// before within `max_width` ticks.
+pub struct GlitchDetector {
+    max_width: u32,
+    last: HashMap<PortRef, (u32, Vec3vl)>,
+    found: Vec<Glitch>,
+    dropped: u32
+}
+
+impl GlitchDetector {
+    pub fn new(max_width: u32) -> GlitchDetector {
+        GlitchDetector { max_width, last: HashMap::new(), found: Vec::new(), dropped: 0 }
+    }
+
+    pub fn record(&mut self, port: PortRef, old: &Vec3vl, new: &Vec3vl, tick: u32) {
+        if let Some((since, previous)) = self.last.get(&port) {
+            let width = tick.wrapping_sub(*since);
+            if width <= self.max_width && previous == new {
+                if self.found.len() < MAX_GLITCHES {
+                    self.found.push(Glitch { port: port.clone(), tick: *since, width });
+                } else {
+                    self.dropped += 1;
+                }
+            }
+        }
+        self.last.insert(port, (tick, old.clone()));
+    }
+
+    pub fn drain(&mut self) -> (Vec<Glitch>, u32) {
+        let dropped = std::mem::take(&mut self.dropped);
+        (std::mem::take(&mut self.found), dropped)
+    }
+}
@@ -1,88 +1,104 @@
-use std::cell::RefCell;
 use std::collections::hash_map::Values;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::Arc;
 
-use crate::gate::{Gate, GatePtr};
-use crate::js_types::{JsGateParams, PortParams};
+use crate::arena::{Arena, GateId};
+use crate::gate::Gate;
 use crate::link::{Link, LinkTarget};
 
-pub type GraphPtr = Rc<RefCell<Graph>>;
-
+#[derive(Clone)]
 pub struct Graph {
     id:         String,
-    gates:      HashMap<String, GatePtr>,
-    links:      HashMap<String, Link>,
-    subcircuit: Option<GatePtr>,
+    gates:      HashMap<String, GateId>,
+    links:      Arc<HashMap<String, Link>>,
+    subcircuit: Option<GateId>,
     observed:   bool,
+    definition: bool,
 }
 
 impl Graph {
-    pub fn new(id: String) -> GraphPtr {
-        Rc::new(RefCell::new(Graph {
+    pub fn new(id: String) -> Graph {
+        Graph {
+            id,
+            gates:      HashMap::new(),
+            links:      Arc::new(HashMap::new()),
+            subcircuit: None,
+            observed:   false,
+            definition: false,
+        }
+    }
+
+    pub fn new_definition(id: String) -> Graph {
+        Graph { definition: true, ..Graph::new(id) }
+    }
+
+    pub fn duplicate(&self, id: String) -> Graph {
+        Graph {
             id,
             gates:      HashMap::new(),
-            links:      HashMap::new(),
+            links:      self.links.clone(),
             subcircuit: None,
             observed:   false,
-        }))
+            definition: false,
+        }
     }
 
     pub fn get_id(&self) -> String {
         self.id.clone()
     }
 
-    pub fn add_link(&mut self, link_id: String, source: LinkTarget, target: LinkTarget) -> Result<(), String> {
-        self.links.insert(link_id.clone(), Link { source: source.clone(), target: target.clone() });
-        
+    pub fn add_link(&mut self, gates: &mut Arena<Gate>, link_id: String, source: LinkTarget, target: LinkTarget) -> Result<(), String> {
         let source_gate = self.get_gate(&source.id)?;
         let target_gate = self.get_gate(&target.id)?;
 
-        source_gate.borrow_mut().add_link_to(&source.port, target)?;
-        source_gate.borrow_mut().add_link(link_id.clone());
-        target_gate.borrow_mut().add_link(link_id);
+        Arc::make_mut(&mut self.links).insert(link_id.clone(), Link { source: source.clone(), target: target.clone() });
+
+        let gate = self.gate_mut(gates, source_gate)?;
+        gate.add_link_to(&source.port, target)?;
+        gate.add_link(link_id.clone());
+        self.gate_mut(gates, target_gate)?.add_link(link_id);
         Ok(())
     }
 
-    pub fn add_gate(&mut self, graph: GraphPtr, gate_id: String, gate_params: JsGateParams, port_params: Vec<PortParams>) -> Result<(), String> {
-        self.gates.insert(
-            gate_id.clone(), 
-            Gate::new(graph, self.id.clone(), gate_id, gate_params, port_params)?
-        );
-        Ok(())
+    pub fn add_gate(&mut self, gate_id: String, gate: GateId) -> Option<GateId> {
+        self.gates.insert(gate_id, gate)
     }
 
-    pub fn remove_link(&mut self, link_id: &String) -> Result<Link, String> {
-        let link = match self.links.remove(link_id) {
+    pub fn remove_link(&mut self, gates: &mut Arena<Gate>, link_id: &String) -> Result<Link, String> {
+        let link = match Arc::make_mut(&mut self.links).remove(link_id) {
             Some(l) => l,
             None => return Err(format!("Gate {} has no link id {}", self.id, link_id))
         };
 
-        let gate = self.get_gate(&link.source.id)?;
-        gate.borrow_mut().remove_link_to(&link.source.port, link.target.clone())?;
-        gate.borrow_mut().remove_link(link_id);
+        let source_gate = self.get_gate(&link.source.id)?;
+        let gate = self.gate_mut(gates, source_gate)?;
+        gate.remove_link_to(&link.source.port, link.target.clone())?;
+        gate.remove_link(link_id);
 
-        self.get_gate(&link.target.id)?.borrow_mut().remove_link(link_id);
+        let target_gate = self.get_gate(&link.target.id)?;
+        self.gate_mut(gates, target_gate)?.remove_link(link_id);
 
         Ok(link)
     }
 
-    pub fn remove_gate(&mut self, gate_id: &String) -> Result<(), String> {
-        let gate = match self.gates.remove(gate_id) {
-            Some(g ) => g,
-            None => return Err(format!("Graph {} has no gate {}", self.id, gate_id))
-        };
+    pub fn remove_gate(&mut self, gates: &mut Arena<Gate>, gate_id: &String) -> Result<Gate, String> {
+        let id = self.get_gate(gate_id)?;
+        let links = self.gate_mut(gates, id)?.get_links_iter().cloned().collect::<Vec<String>>();
 
-        for link_id in gate.borrow().get_links_iter() {
-            self.remove_link(link_id)?;
+        for link_id in links {
+            self.remove_link(gates, &link_id)?;
         }
 
-        Ok(())
+        self.gates.remove(gate_id);
+        match gates.remove(id) {
+            Some(g) => Ok(g),
+            None => Err(format!("Graph {} has no gate {}", self.id, gate_id))
+        }
     }
 
-    pub fn get_gate(&self, gate_id: &String) -> Result<GatePtr, String> {
+    pub fn get_gate(&self, gate_id: &String) -> Result<GateId, String> {
         match self.gates.get(gate_id) {
-            Some(g) => Ok(g.clone()),
+            Some(g) => Ok(*g),
             None => Err(format!("Graph {} has no gate {}", self.id, gate_id))
         }
     }
@@ -103,15 +119,26 @@ impl Graph {
         self.observed
     }
 
-    pub fn set_subcircuit(&mut self, gate: GatePtr) {
+    pub fn is_definition(&self) -> bool {
+        self.definition
+    }
+
+    pub fn set_subcircuit(&mut self, gate: GateId) {
         self.subcircuit = Some(gate);
     }
 
-    pub fn subcircuit(&self) -> Option<GatePtr> {
-        self.subcircuit.clone()
+    pub fn subcircuit(&self) -> Option<GateId> {
+        self.subcircuit
     }
 
-    pub fn gate_iter(&self) -> Values<'_, String, GatePtr> {
+    pub fn gate_iter(&self) -> Values<'_, String, GateId> {
         self.gates.values()
     }
-}
\ No newline at end of file
+
+    fn gate_mut<'a>(&self, gates: &'a mut Arena<Gate>, gate: GateId) -> Result<&'a mut Gate, String> {
+        match gates.get_mut(gate) {
+            Some(g) => Ok(g),
+            None => Err(format!("Graph {} refers to a removed gate", self.id))
+        }
+    }
+}
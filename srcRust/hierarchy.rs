@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use crate::arena::{Arena, GateId, GraphId};
+use crate::gate::Gate;
+use crate::graph::Graph;
+
+pub struct SignalPath {
+    pub graph: GraphId,
+    pub gate: GateId,
+    pub port: String
+}
+
+// Resolves `root.sub0.sub1.gate.port`. The first segment is the id of a top-level graph,
+// the following ones are gate labels (falling back to IO net names and gate ids).
+// The trailing port may be omitted for gates with a single output or input.
+pub fn resolve_path(gates: &Arena<Gate>, graphs: &Arena<Graph>, graph_ids: &HashMap<String, GraphId>, path: &str) -> Result<SignalPath, String> {
+    let mut segments = path.split('.');
+    let root = segments.next().unwrap_or_default();
+    let mut graph = match graph_ids.get(root) {
+        Some(g) => *g,
+        None => return Err(format!("No graph with id {root}"))
+    };
+    let segments = segments.collect::<Vec<&str>>();
+
+    let mut n = 0;
+    while n < segments.len() {
+        let gate_id = find_gate(gates, graphs, graph, segments[n])?;
+        let gate = gates.get(gate_id).unwrap();
+        let rest = &segments[n + 1..];
+
+        if rest.len() == 1 && gate.has_port(&rest[0].to_string()) {
+            return Ok(SignalPath { graph, gate: gate_id, port: rest[0].to_string() });
+        }
+
+        if rest.is_empty() {
+            return Ok(SignalPath { graph, gate: gate_id, port: default_port(gate)? });
+        }
+
+        graph = match gate.get_subgraph() {
+            Ok(g) => g,
+            Err(_) => return Err(format!("{} in path {path} is neither a subcircuit nor a port", rest[0]))
+        };
+        n += 1;
+    }
+
+    Err(format!("Path {path} does not name a gate"))
+}
+
+pub fn list_hierarchy(gates: &Arena<Gate>, graphs: &Arena<Graph>, graph: GraphId, prefix: &str, out: &mut Vec<String>) {
+    let graph = match graphs.get(graph) {
+        Some(g) => g,
+        None => return
+    };
+
+    let mut names = graph.gate_iter()
+        .filter_map(|id| gates.get(*id))
+        .map(|gate| (format!("{prefix}.{}", gate.get_name()), gate.get_subgraph().ok()))
+        .collect::<Vec<(String, Option<GraphId>)>>();
+    names.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (name, subgraph) in names {
+        out.push(name.clone());
+        if let Some(sub) = subgraph {
+            list_hierarchy(gates, graphs, sub, &name, out);
+        }
+    }
+}
+
+// Dotted name of a gate relative to `root`, the inverse of `resolve_path`.
+pub fn gate_path(gates: &Arena<Gate>, graphs: &Arena<Graph>, gate: GateId, root: GraphId) -> String {
+    let mut names = Vec::new();
+    let mut current = gates.get(gate);
+    while let Some(g) = current {
+        names.push(g.get_name());
+        if g.get_graph() == root { break; }
+        current = graphs.get(g.get_graph())
+            .and_then(|graph| graph.subcircuit())
+            .and_then(|sub| gates.get(sub));
+    }
+    names.reverse();
+    names.join(".")
+}
+
+pub fn find_gate(gates: &Arena<Gate>, graphs: &Arena<Graph>, graph: GraphId, name: &str) -> Result<GateId, String> {
+    let graph = match graphs.get(graph) {
+        Some(g) => g,
+        None => return Err(format!("No graph with handle {graph:?}"))
+    };
+
+    // Gates whose displayed name matches take precedence over matches by id or net.
+    let mut named = Vec::new();
+    let mut aliased = Vec::new();
+    for id in graph.gate_iter() {
+        if let Some(gate) = gates.get(*id) {
+            if gate.get_name() == name {
+                named.push(*id);
+            } else if gate.has_name(name) {
+                aliased.push(*id);
+            }
+        }
+    }
+
+    let found = if named.is_empty() { aliased } else { named };
+    match found[..] {
+        [g] => Ok(g),
+        [] => Err(format!("Graph {} has no gate named {name}", graph.get_id())),
+        _ => Err(format!("Ambiguous path: graph {} has {} gates named {name}", graph.get_id(), found.len()))
+    }
+}
+
+fn default_port(gate: &Gate) -> Result<String, String> {
+    let out = "out".to_string();
+    if gate.has_port(&out) { return Ok(out); }
+
+    let mut ports = gate.iodirs_iter().map(|(port, _)| port.clone()).collect::<Vec<String>>();
+    if ports.len() == 1 {
+        Ok(ports.remove(0))
+    } else {
+        Err(format!("Gate {} has no default port", gate.get_name()))
+    }
+}
@@ -1,3 +1,4 @@
+use js_sys::Array;
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -28,6 +29,15 @@ extern "C" {
   #[wasm_bindgen(method, structural, getter = propagation)]
   pub fn get_propagation(this: &JsGateParams) -> Option<u32>;
 
+  #[wasm_bindgen(method, structural, getter = delayModel)]
+  pub fn get_delay_model(this: &JsGateParams) -> Option<String>;
+
+  #[wasm_bindgen(method, structural, getter = riseDelay)]
+  pub fn get_rise_delay(this: &JsGateParams) -> Option<u32>;
+
+  #[wasm_bindgen(method, structural, getter = fallDelay)]
+  pub fn get_fall_delay(this: &JsGateParams) -> Option<u32>;
+
   #[wasm_bindgen(method, structural, getter = type)]
   pub fn get_type(this: &JsGateParams) -> String;
 
@@ -58,6 +68,9 @@ extern "C" {
   #[wasm_bindgen(method, structural, getter = memdata)]
   pub fn get_memdata(this: &JsGateParams) -> Option<Vec<JsValue>>;
 
+  #[wasm_bindgen(method, structural, getter = memfill)]
+  pub fn get_memfill(this: &JsGateParams) -> Option<String>;
+
   #[wasm_bindgen(method, structural, getter = rdports)]
   pub fn get_rdports(this: &JsGateParams) -> Option<Vec<MemoryPolarityStruct>>;
 
@@ -84,6 +97,39 @@ extern "C" {
 
   #[wasm_bindgen(method, structural, getter = extend)]
   pub fn get_extend(this: &JsGateParams) -> Option<JsExtendStruct>;
+
+  #[wasm_bindgen(method, structural, getter = parameters)]
+  pub fn get_parameters(this: &JsGateParams) -> Option<YosysParameters>;
+}
+
+// Parameters of a Yosys internal cell, as found in the JSON netlist.
+#[wasm_bindgen]
+extern "C" {
+  pub type YosysParameters;
+
+  #[wasm_bindgen(method, structural, getter = WIDTH)]
+  pub fn get_width(this: &YosysParameters) -> JsValue;
+
+  #[wasm_bindgen(method, structural, getter = S_WIDTH)]
+  pub fn get_s_width(this: &YosysParameters) -> JsValue;
+
+  #[wasm_bindgen(method, structural, getter = Y_WIDTH)]
+  pub fn get_y_width(this: &YosysParameters) -> JsValue;
+
+  #[wasm_bindgen(method, structural, getter = A_SIGNED)]
+  pub fn get_a_signed(this: &YosysParameters) -> JsValue;
+
+  #[wasm_bindgen(method, structural, getter = B_SIGNED)]
+  pub fn get_b_signed(this: &YosysParameters) -> JsValue;
+
+  #[wasm_bindgen(method, structural, getter = DEPTH)]
+  pub fn get_depth(this: &YosysParameters) -> JsValue;
+
+  #[wasm_bindgen(method, structural, getter = LUT)]
+  pub fn get_lut(this: &YosysParameters) -> JsValue;
+
+  #[wasm_bindgen(method, structural, getter = TABLE)]
+  pub fn get_table(this: &YosysParameters) -> JsValue;
 }
 
 #[wasm_bindgen]
@@ -192,6 +238,15 @@ extern "C" {
   #[wasm_bindgen(method, structural, getter = clock_polarity)]
   pub fn get_clock(this: &MemoryPolarityStruct) -> Option<bool>;
 
+  #[wasm_bindgen(method, structural, getter = latency)]
+  pub fn get_latency(this: &MemoryPolarityStruct) -> Option<u32>;
+
+  #[wasm_bindgen(method, structural, getter = no_bit_enable)]
+  pub fn get_no_bit_enable(this: &MemoryPolarityStruct) -> Option<bool>;
+
+  #[wasm_bindgen(method, structural, getter = byte_enable)]
+  pub fn get_byte_enable(this: &MemoryPolarityStruct) -> Option<bool>;
+
   #[wasm_bindgen(method, structural, getter = transparent)]
   pub fn get_transparent(this: &MemoryPolarityStruct) -> Option<bool>;
 
@@ -251,6 +306,84 @@ extern "C" {
 
   #[wasm_bindgen(method, structural, getter = synchronous)]
   pub fn get_synchronous(this: &JsMonitorParams) -> Option<bool>;
+
+  #[wasm_bindgen(method, structural, getter = trigger)]
+  pub fn get_trigger(this: &JsMonitorParams) -> Option<JsTrigger>;
+
+  #[wasm_bindgen(method, structural, getter = capacity)]
+  pub fn get_capacity(this: &JsMonitorParams) -> Option<u32>;
+
+  #[wasm_bindgen(method, structural, getter = decimation)]
+  pub fn get_decimation(this: &JsMonitorParams) -> Option<u32>;
+}
+
+#[wasm_bindgen]
+extern "C" {
+  pub type JsFaultParams;
+
+  #[wasm_bindgen(method, structural, getter = type)]
+  pub fn get_type(this: &JsFaultParams) -> String;
+
+  #[wasm_bindgen(method, structural, getter = bit)]
+  pub fn get_bit(this: &JsFaultParams) -> Option<u32>;
+
+  #[wasm_bindgen(method, structural, getter = tick)]
+  pub fn get_tick(this: &JsFaultParams) -> Option<u32>;
+}
+
+#[wasm_bindgen]
+extern "C" {
+  pub type JsFaultCampaign;
+
+  #[wasm_bindgen(method, structural, getter = inputs)]
+  pub fn get_inputs(this: &JsFaultCampaign) -> Vec<String>;
+
+  #[wasm_bindgen(method, structural, getter = outputs)]
+  pub fn get_outputs(this: &JsFaultCampaign) -> Option<Vec<String>>;
+
+  #[wasm_bindgen(method, structural, getter = vectors)]
+  pub fn get_vectors(this: &JsFaultCampaign) -> Array;
+
+  #[wasm_bindgen(method, structural, getter = settle)]
+  pub fn get_settle(this: &JsFaultCampaign) -> Option<u32>;
+
+  #[wasm_bindgen(method, structural, getter = lanes)]
+  pub fn get_lanes(this: &JsFaultCampaign) -> Option<u32>;
+}
+
+#[wasm_bindgen]
+extern "C" {
+  pub type JsTrigger;
+
+  #[wasm_bindgen(method, structural, getter = type)]
+  pub fn get_type(this: &JsTrigger) -> String;
+
+  #[wasm_bindgen(method, structural, getter = values)]
+  pub fn get_values(this: &JsTrigger) -> Option<Vec<JsVec3vl>>;
+
+  #[wasm_bindgen(method, structural, getter = min)]
+  pub fn get_min(this: &JsTrigger) -> Option<JsVec3vl>;
+
+  #[wasm_bindgen(method, structural, getter = max)]
+  pub fn get_max(this: &JsTrigger) -> Option<JsVec3vl>;
+
+  #[wasm_bindgen(method, structural, getter = pattern)]
+  pub fn get_pattern(this: &JsTrigger) -> Option<JsVec3vl>;
+
+  #[wasm_bindgen(method, structural, getter = operands)]
+  pub fn get_operands(this: &JsTrigger) -> Option<Vec<JsTrigger>>;
+
+  #[wasm_bindgen(method, structural, getter = path)]
+  pub fn get_path(this: &JsTrigger) -> Option<String>;
+
+  #[wasm_bindgen(method, structural, getter = graph)]
+  pub fn get_graph(this: &JsTrigger) -> Option<String>;
+
+  #[wasm_bindgen(method, structural, getter = gate)]
+  pub fn get_gate(this: &JsTrigger) -> Option<String>;
+
+  #[wasm_bindgen(method, structural, getter = port)]
+  pub fn get_port(this: &JsTrigger) -> Option<String>;
 }
 
 #[wasm_bindgen]
@@ -304,4 +437,20 @@ extern "C" {
 
   #[wasm_bindgen(method, structural, getter = output)]
   pub fn get_output(this: &JsExtendStruct) -> Option<u32>;
-}
\ No newline at end of file
+}
+#[wasm_bindgen]
+extern "C" {
+  pub type JsEquivalenceParams;
+
+  #[wasm_bindgen(method, structural, getter = cycles)]
+  pub fn get_cycles(this: &JsEquivalenceParams) -> Option<u32>;
+
+  #[wasm_bindgen(method, structural, getter = exhaustiveBits)]
+  pub fn get_exhaustive_bits(this: &JsEquivalenceParams) -> Option<u32>;
+
+  #[wasm_bindgen(method, structural, getter = seed)]
+  pub fn get_seed(this: &JsEquivalenceParams) -> Option<u32>;
+
+  #[wasm_bindgen(method, structural, getter = settle)]
+  pub fn get_settle(this: &JsEquivalenceParams) -> Option<u32>;
+}
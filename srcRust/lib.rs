@@ -1,20 +1,48 @@
 #![allow(dead_code)]
 use std::collections::{BTreeSet, HashMap, HashSet};
 
-use gate::{GatePtr, IoDir};
-use graph::{Graph, GraphPtr};
-use js_types::{JsGateParams, IOmap, PortParams, TargetParams, JsMonitorParams, JsVec3vl, JsAlarmStruct};
+use arena::{Arena, GateId, GraphId};
+use breakpoint::Breakpoint;
+use coverage::Coverage;
+use fault::{Fault, FaultSite};
+use gate::{DelayModel, Gate, IoDir, PortDelay};
+use glitch::GlitchDetector;
+use graph::Graph;
+use hierarchy::{gate_path, list_hierarchy, resolve_path};
+use js_sys::{Array, Object, Reflect, Uint32Array};
+use js_types::{JsGateParams, IOmap, PortParams, TargetParams, JsMonitorParams, JsEquivalenceParams, JsFaultCampaign, JsFaultParams, JsTrigger, JsVec3vl, JsAlarmStruct};
 use link::LinkTarget;
+use memimage::ImageFormat;
+use mmio::{ConsoleDevice, HostDevice, MmioHandler, MmioRegion};
+use monitor::{Condition, History, PortRef};
 use operations::ReturnValue;
+use power::Power;
+use timing::analyze;
 use vector3vl::Vec3vl;
 use wasm_bindgen::prelude::*;
 
+mod arena;
+mod breakpoint;
+mod campaign;
+mod coverage;
+mod equivalence;
+mod fault;
 mod graph;
 mod gate;
+mod glitch;
+mod hierarchy;
+mod memimage;
+mod mmio;
 mod link;
+mod monitor;
 mod js_types;
 mod vector3vl;
 mod operations;
+mod paged_memory;
+#[cfg(feature = "threads")]
+mod parallel;
+mod power;
+mod timing;
 
 mod cell_arith;
 mod cell_bus;
@@ -24,6 +52,8 @@ mod cell_gates;
 mod cell_io;
 mod cell_memory;
 mod cell_mux;
+mod cell_rom;
+mod cell_yosys;
 
 #[wasm_bindgen]
 extern "C" {
@@ -35,43 +65,70 @@ extern "C" {
 #[wasm_bindgen(module = "/src/engines/wasm-js-functions.mjs")]
 extern "C" {
     fn sendUpdates(tick: u32, pendingEvents: bool, updates: Vec<UpdateStruct>);
-    fn triggerMemoryUpdate(graphId: String, gateId: String, address: i32, bits: u32, avec: Vec<u32>, bvec: Vec<u32>);
+    fn triggerMemoryUpdate(graphId: String, gateId: String, address: u32, bits: u32, avec: Vec<u32>, bvec: Vec<u32>);
     fn triggerFSMCurrentStateChange(graphId: String, gateId: String, currentState: u32);
     fn triggerFSMNextTransChange(graphId: String, gateId: String, transitionId: Option<String>);
     fn postMonitorValue(monitorId: u32, tick: u32, bits: u32, avec: Vec<u32>, bvec: Vec<u32>, stopOnTrigger: Option<bool>, oneShot: Option<bool>);
     fn updater_stop();
+    fn sendMonitorStopped(monitorId: u32, tick: u32);
     fn sendAck(reqid: u32, response: Option<u32>);
     fn sendAlarmReached(alarmId: u32, tick: u32, stopOnAlarm: bool);
+    fn sendBreakpointHit(breakpointId: u32, tick: u32);
+    fn mmioWrite(deviceId: u32, offset: u32, bits: u32, avec: Vec<u32>, bvec: Vec<u32>);
 }
 
 #[cfg(feature = "node")]
 #[wasm_bindgen(module = "/lib/engines/wasm-js-functions.js")]
 extern "C" {
     fn sendUpdates(tick: u32, pendingEvents: bool, updates: Vec<UpdateStruct>);
-    fn triggerMemoryUpdate(graphId: String, gateId: String, address: i32, bits: u32, avec: Vec<u32>, bvec: Vec<u32>);
+    fn triggerMemoryUpdate(graphId: String, gateId: String, address: u32, bits: u32, avec: Vec<u32>, bvec: Vec<u32>);
     fn triggerFSMCurrentStateChange(graphId: String, gateId: String, currentState: u32);
     fn triggerFSMNextTransChange(graphId: String, gateId: String, transitionId: Option<String>);
     fn postMonitorValue(monitorId: u32, tick: u32, bits: u32, avec: Vec<u32>, bvec: Vec<u32>, stopOnTrigger: Option<bool>, oneShot: Option<bool>);
     fn updater_stop();
+    fn sendMonitorStopped(monitorId: u32, tick: u32);
     fn sendAck(reqid: u32, response: Option<u32>);
     fn sendAlarmReached(alarmId: u32, tick: u32, stopOnAlarm: bool);
+    fn sendBreakpointHit(breakpointId: u32, tick: u32);
+    fn mmioWrite(deviceId: u32, offset: u32, bits: u32, avec: Vec<u32>, bvec: Vec<u32>);
 }
 
 
-pub type GateUpdateCollection = HashMap<String, (GatePtr, HashMap<String, Vec3vl>)>;
+pub type GateUpdateCollection = HashMap<GateId, HashMap<String, Vec3vl>>;
+
+const READ_PAGE_WORDS: u64 = 1 << 16;
+
+#[cfg(feature = "threads")]
+const PARALLEL_THRESHOLD: usize = 64;
 
 #[wasm_bindgen]
 struct RustEngine {
     interval: u32,
     tick: u32,
-    graphs: HashMap<String, GraphPtr>,
+    gates: Arena<Gate>,
+    graphs: Arena<Graph>,
+    graph_ids: HashMap<String, GraphId>,
     monitors: HashMap<u32, MonitorParams>,
-    monitor_checks: HashMap<u32, Vec3vl>,
+    monitor_checks: HashSet<u32>,
+    monitor_history: HashMap<u32, History>,
+    breakpoints: HashMap<u32, Breakpoint>,
+    coverage: Option<Coverage>,
+    power: Option<Power>,
+    glitches: Option<GlitchDetector>,
+    faults: HashMap<u32, Fault>,
+    port_faults: HashMap<PortRef, Vec<u32>>,
+    fault_free: HashMap<PortRef, Vec3vl>,
+    fault_queue: HashMap<u32, Vec<u32>>,
+    capacitance: HashMap<String, f64>,
     alarms: HashMap<u32, AlarmParams>,
     alarm_queue: HashMap<u32, HashSet<u32>>,
     queue: HashMap<u32, GateUpdateCollection>,
+    output_queue: HashMap<u32, Vec<(GateId, String, Vec3vl)>>,
+    // Ticks with changes in `output_queue`, by output port.
+    output_ticks: HashMap<PortRef, BTreeSet<u32>>,
     pq: BTreeSet<u32>,
-    to_update: HashMap<String, (GatePtr, HashSet<String>)>,
+    to_update: HashMap<GateId, HashSet<String>>,
+    instance_counter: u32,
 }
 
 #[wasm_bindgen]
@@ -80,14 +137,29 @@ impl RustEngine {
         RustEngine { 
             interval: 10,
             tick: 0,
-            graphs: HashMap::new(),
+            gates: Arena::new(),
+            graphs: Arena::new(),
+            graph_ids: HashMap::new(),
             monitors: HashMap::new(),
-            monitor_checks: HashMap::new(),
+            monitor_checks: HashSet::new(),
+            monitor_history: HashMap::new(),
+            breakpoints: HashMap::new(),
+            coverage: None,
+            power: None,
+            glitches: None,
+            faults: HashMap::new(),
+            port_faults: HashMap::new(),
+            fault_free: HashMap::new(),
+            fault_queue: HashMap::new(),
+            capacitance: HashMap::new(),
             alarms: HashMap::new(),
             alarm_queue: HashMap::new(),
             queue: HashMap::new(),
+            output_queue: HashMap::new(),
+            output_ticks: HashMap::new(),
             pq: BTreeSet::new(),
-            to_update: HashMap::new()
+            to_update: HashMap::new(),
+            instance_counter: 0
         }
     }
 
@@ -112,15 +184,8 @@ impl RustEngine {
     #[wasm_bindgen(js_name = _updateGates)]
     pub fn update_gates_priv(&mut self) -> Result<u32, String> {
         Ok(match self.pq.first() {
-            Some(k) => {
-                if *k == self.tick {
-                    self.update_gates_next_priv()?
-                } else {
-                    self.tick = self.tick.wrapping_add(1);
-                    0
-                }
-            },
-            None => {
+            Some(k) if *k == self.tick => self.update_gates_next_priv()?,
+            _ => {
                 self.tick = self.tick.wrapping_add(1);
                 0
             }
@@ -144,21 +209,29 @@ impl RustEngine {
         self.tick = k;
         let mut count = 0;
 
+        for (gate_id, port, sig) in self.output_queue.remove(&k).unwrap_or_default() {
+            self.forget_output_tick_priv(gate_id, &port, k);
+            if self.gates.contains(gate_id) {
+                self.set_gate_output_signal_priv(gate_id, port, sig)?;
+            }
+        }
+        for fault_id in self.fault_queue.remove(&k).unwrap_or_default() {
+            self.fire_fault_priv(fault_id)?;
+        }
+
         while let Some(q) = self.queue.remove(&k) {
-            for (gate, sigs) in q.values() {
-                let result = gate.borrow_mut().do_operation(sigs)?;
+            for (gate_id, result) in self.evaluate_gates(&q)? {
                 if result.is_clock() {
-                    self.enqueue(gate);
+                    self.enqueue(gate_id)?;
                 }
                 
-                self.set_gate_output_signals_priv(gate, result)?;
+                self.set_gate_output_signals_priv(gate_id, result)?;
                 count += 1;
             }
-
-            if self.queue.contains_key(&k) {
-                self.pq.pop_first();
-            }
         }
+        // Outputs, faults and zero-delay gates may have queued events for this tick
+        // again; they have all been handled by now.
+        self.pq.remove(&k);
         
         self.tick = self.tick.wrapping_add(1);
         Ok(count)
@@ -172,21 +245,27 @@ impl RustEngine {
 
     #[wasm_bindgen(js_name = addGraph)]
     pub fn add_graph(&mut self, id: String) {
-        self.graphs.insert(id.clone(), Graph::new(id));
+        let graph = self.graphs.insert(Graph::new(id.clone()));
+        if let Some(old) = self.graph_ids.insert(id, graph) {
+            self.graphs.remove(old);
+        }
     }
 
     #[wasm_bindgen(js_name = addLink)]
     pub fn add_link(&mut self, graph_id: String, link_id: String, from: TargetParams, to: TargetParams) -> Result<(), String> {
-        let graph = self.get_graph(graph_id)?;
+        let graph = self.get_graph(&graph_id)?;
         let source_target = LinkTarget { id: from.get_id(), port: from.get_port(), magnet: from.get_magnet() };
         let target_target = LinkTarget { id: to.get_id(), port: to.get_port(), magnet: to.get_magnet() };
 
-        graph.borrow_mut().add_link(link_id, source_target.clone(), target_target.clone())?;
+        match self.graphs.get_mut(graph) {
+            Some(g) => g.add_link(&mut self.gates, link_id, source_target.clone(), target_target.clone())?,
+            None => return Err(format!("No graph with id {graph_id}"))
+        };
 
-        let source_gate = graph.borrow().get_gate(&source_target.id)?;
-        let target_gate = graph.borrow().get_gate(&target_target.id)?;
+        let source_gate = self.graph(graph)?.get_gate(&source_target.id)?;
+        let target_gate = self.graph(graph)?.get_gate(&target_target.id)?;
 
-        let sig = source_gate.borrow().get_output(&source_target.port)?;
+        let sig = self.gate(source_gate)?.get_output(&source_target.port)?;
 
         self.set_gate_input_signal_priv(target_gate, target_target.port, sig)?;
         Ok(())
@@ -194,46 +273,58 @@ impl RustEngine {
 
     #[wasm_bindgen(js_name = addGate)]
     pub fn add_gate(&mut self, graph_id: String, gate_id: String, gate_params: JsGateParams, port_params: Vec<PortParams>) -> Result<(), String> {
-        let graph = self.get_graph(graph_id)?.clone();
+        let graph = self.get_graph(&graph_id)?;
+        let gate = self.gates.insert(Gate::new(graph, graph_id, gate_id.clone(), gate_params, port_params)?);
 
-        graph.borrow_mut().add_gate(graph.clone(), gate_id.clone(), gate_params, port_params)?;
-        self.enqueue(&graph.borrow().get_gate(&gate_id)?);
-        Ok(())
+        if let Some(old) = self.graph_mut(graph)?.add_gate(gate_id, gate) {
+            self.gates.remove(old);
+        }
+        self.enqueue(gate)
+    }
+
+    #[wasm_bindgen(js_name = addModule)]
+    pub fn add_module(&mut self, id: String) {
+        let graph = self.graphs.insert(Graph::new_definition(id.clone()));
+        if let Some(old) = self.graph_ids.insert(id, graph) {
+            self.graphs.remove(old);
+        }
     }
 
     #[wasm_bindgen(js_name = addSubcircuit)]
     pub fn add_subcircuit(&mut self, graph_id: String, gate_id: String, subgraph_id: String, io_map: Vec<IOmap>) -> Result<(), String> {
-        let graph = self.get_graph(graph_id)?;
-        let gate = graph.borrow().get_gate(&gate_id)?;
-        let subgraph = self.get_graph(subgraph_id)?.clone();
-
-        gate.borrow_mut().set_subgraph(subgraph.clone());
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        let subgraph = self.get_graph(&subgraph_id)?;
+        self.bind_subcircuit_priv(gate, subgraph, io_map)
+    }
 
-        subgraph.borrow_mut().set_subcircuit(gate.clone());
-        let mut map = HashMap::new();
-        for i in io_map {
-            let port = i.get_port();
-            let io_id = i.get_io_id();
+    #[wasm_bindgen(js_name = instantiateSubcircuit)]
+    pub fn instantiate_subcircuit(&mut self, graph_id: String, gate_id: String, module_id: String, instance_id: String, io_map: Vec<IOmap>) -> Result<(), String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        let module = self.get_graph(&module_id)?;
+        if !self.graph(module)?.is_definition() {
+            return Err(format!("Graph {module_id} is not a module definition"));
+        }
 
-            map.insert(port.clone(), io_id.clone());
-            let io = subgraph.borrow().get_gate(&io_id)?;
-            match gate.borrow().get_port_dir(&port)? {
-                IoDir::In => self.set_gate_output_signal_priv(&io, "out".to_string(), gate.borrow().get_input(&port)?)?,
-                IoDir::Out => self.set_gate_output_signal_priv(&gate, port, io.borrow().get_input(&"in".to_string())?)?,
-            };
+        let mut copies = HashMap::new();
+        let suffix = format!("@{instance_id}");
+        let instance = self.clone_graph_priv(module, instance_id, &suffix, &mut copies)?;
+        for copy in copies.into_values() {
+            self.enqueue(copy)?;
         }
 
-        gate.borrow_mut().set_subgraph_iomap(map);
-        Ok(())
+        self.bind_subcircuit_priv(gate, instance, io_map)
     }
 
     #[wasm_bindgen(js_name = removeLink)]
     pub fn remove_link(&mut self, graph_id: String, link_id: String) -> Result<(), String> {
-        let graph = self.get_graph(graph_id)?;
-        let link = graph.borrow_mut().remove_link(&link_id)?;
+        let graph = self.get_graph(&graph_id)?;
+        let link = match self.graphs.get_mut(graph) {
+            Some(g) => g.remove_link(&mut self.gates, &link_id)?,
+            None => return Err(format!("No graph with id {graph_id}"))
+        };
 
-        let target_gate = graph.borrow().get_gate(&link.target.id)?;
-        let sig = Vec3vl::xes(target_gate.borrow().get_input(&link.target.port)?.bits);
+        let target_gate = self.graph(graph)?.get_gate(&link.target.id)?;
+        let sig = Vec3vl::xes(self.gate(target_gate)?.get_input(&link.target.port)?.bits);
 
         self.set_gate_input_signal_priv(target_gate, link.target.port, sig)?;
         Ok(())
@@ -241,20 +332,99 @@ impl RustEngine {
 
     #[wasm_bindgen(js_name = removeGate)]
     pub fn remove_gate(&mut self, graph_id: String, gate_id: String) -> Result<(), String> {
-        self.get_graph(graph_id)?.borrow_mut().remove_gate(&gate_id)?;
+        let graph = self.get_graph(&graph_id)?;
+        let gate = self.graph(graph)?.get_gate(&gate_id)?;
+        if let Ok(sub) = self.gate(gate)?.get_subgraph() {
+            self.remove_graph_priv(sub)?;
+        }
+        match self.graphs.get_mut(graph) {
+            Some(g) => g.remove_gate(&mut self.gates, &gate_id)?,
+            None => return Err(format!("No graph with id {graph_id}"))
+        };
+
+        self.to_update.remove(&gate);
         Ok(())
     }
 
+    #[wasm_bindgen(js_name = instantiateGraph)]
+    pub fn instantiate_graph(&mut self, graph_id: String, count: u32) -> Result<Vec<String>, String> {
+        let graph = self.get_graph(&graph_id)?;
+        let mut instances = Vec::new();
+
+        for _ in 0..count {
+            let suffix = format!("@{}", self.instance_counter);
+            self.instance_counter += 1;
+
+            let mut copies = HashMap::new();
+            let name = format!("{graph_id}{suffix}");
+            let instance = self.clone_graph_priv(graph, name, &suffix, &mut copies)?;
+            instances.push(self.graph(instance)?.get_id());
+
+            for bucket in self.queue.values_mut() {
+                let pending = bucket.iter()
+                    .filter_map(|(gate, sigs)| copies.get(gate).map(|copy| (*copy, sigs.clone())))
+                    .collect::<Vec<(GateId, HashMap<String, Vec3vl>)>>();
+                bucket.extend(pending);
+            }
+            for (k, bucket) in self.output_queue.iter_mut() {
+                let pending = bucket.iter()
+                    .filter_map(|(gate, port, sig)| copies.get(gate).map(|copy| (*copy, port.clone(), sig.clone())))
+                    .collect::<Vec<(GateId, String, Vec3vl)>>();
+                for (gate, port, _) in &pending {
+                    self.output_ticks.entry((*gate, port.clone())).or_default().insert(*k);
+                }
+                bucket.extend(pending);
+            }
+        }
+
+        Ok(instances)
+    }
+
+    #[wasm_bindgen(js_name = removeGraph)]
+    pub fn remove_graph(&mut self, graph_id: String) -> Result<(), String> {
+        let graph = self.get_graph(&graph_id)?;
+        if let Some(subcir) = self.graph(graph)?.subcircuit() {
+            if self.gates.contains(subcir) {
+                return Err(format!("Graph {graph_id} is used by a subcircuit"));
+            }
+        }
+        self.remove_graph_priv(graph)
+    }
+
+    #[wasm_bindgen(js_name = changeInputs)]
+    pub fn change_inputs(&mut self, graph_ids: Vec<String>, gate_id: String, sigs: Vec<JsVec3vl>) -> Result<(), String> {
+        if graph_ids.len() != sigs.len() {
+            return Err(format!("Got {} signals for {} graphs", sigs.len(), graph_ids.len()));
+        }
+
+        for (graph_id, sig) in graph_ids.iter().zip(sigs) {
+            let gate = self.find_gate(graph_id, &gate_id)?;
+            self.set_gate_output_signal_priv(gate, String::from("out"), Vec3vl::from_clonable(sig))?;
+        }
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = getOutputs)]
+    pub fn get_outputs(&self, graph_ids: Vec<String>, gate_id: String, port: String) -> Result<Vec<JsValue>, String> {
+        graph_ids.iter().map(|graph_id| {
+            let gate = self.find_gate(graph_id, &gate_id)?;
+            Ok(self.gate(gate)?.get_port_value(&port)?.to_clonable())
+        }).collect()
+    }
+
     #[wasm_bindgen(js_name = observeGraph)]
     pub fn observe_graph(&mut self, graph_id: String) -> Result<(), String> {
-        let graph = self.get_graph(graph_id)?.clone();
-        graph.borrow_mut().observe();
-
-        for gate in graph.borrow().gate_iter() {
-            for (port, dir) in gate.borrow().iodirs_iter() {
-                if *dir == IoDir::Out {
-                    self.mark_update_priv(gate, port.clone());
-                }
+        let graph = self.get_graph(&graph_id)?;
+        self.graph_mut(graph)?.observe();
+
+        let gates = self.graph(graph)?.gate_iter().copied().collect::<Vec<GateId>>();
+        for gate in gates {
+            let ports = self.gate(gate)?.iodirs_iter()
+                .filter(|(_, dir)| **dir == IoDir::Out)
+                .map(|(port, _)| port.clone())
+                .collect::<Vec<String>>();
+            for port in ports {
+                self.mark_update_priv(gate, port)?;
             }
         }
 
@@ -263,54 +433,490 @@ impl RustEngine {
 
     #[wasm_bindgen(js_name = unobserveGraph)]
     pub fn unobserve_graph(&mut self, graph_id: String) -> Result<(), String> {
-        self.get_graph(graph_id)?.borrow_mut().unobserve();
+        let graph = self.get_graph(&graph_id)?;
+        self.graph_mut(graph)?.unobserve();
         Ok(())
     }
 
     #[wasm_bindgen(js_name = changeInput)]
     pub fn change_input(&mut self, graph_id: String, gate_id: String, sig: JsVec3vl) -> Result<(), String> {
-        let graph = self.get_graph(graph_id)?;
-        let gate = graph.borrow().get_gate(&gate_id)?;
+        let gate = self.find_gate(&graph_id, &gate_id)?;
         self.set_gate_output_signal_priv(
-            &gate, 
+            gate, 
             String::from("out"), 
             Vec3vl::from_clonable(sig)
         )?;
         Ok(())
     }
 
+    #[wasm_bindgen(js_name = changeInputPath)]
+    pub fn change_input_path(&mut self, path: String, sig: JsVec3vl) -> Result<(), String> {
+        let signal = resolve_path(&self.gates, &self.graphs, &self.graph_ids, &path)?;
+        self.set_gate_output_signal_priv(signal.gate, signal.port, Vec3vl::from_clonable(sig))
+    }
+
     // change param
 
     #[wasm_bindgen(js_name = manualMemChange)]
     pub fn manual_mem_change(&mut self, graph_id: String, gate_id: String, addr: u32, data: JsVec3vl) -> Result<(), String> {
-        let gate = self.get_graph(graph_id)?.borrow().get_gate(&gate_id)?;
-        gate.borrow_mut().set_memory(addr, Vec3vl::from_clonable(data))?;
-        self.enqueue(&gate);
-        Ok(())
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        self.gate_mut(gate)?.set_memory(addr, Vec3vl::from_clonable(data))?;
+        self.enqueue(gate)
+    }
+
+    // Replaces the contents of a memory with an image in one of the `ImageFormat`s.
+    #[wasm_bindgen(js_name = loadMemoryImage)]
+    pub fn load_memory_image(&mut self, graph_id: String, gate_id: String, format: String, data: JsValue) -> Result<(), String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        let format = ImageFormat::from_name(&format)?;
+        let (offset, current) = self.gate(gate)?.get_memory_contents()?;
+        let memory = memimage::load(format, &data, offset, &current)?;
+        let old = self.gate_mut(gate)?.replace_memory(memory)?;
+
+        let (_, memory) = self.gate(gate)?.get_memory_contents()?;
+        let addrs = memory.allocated().chain(old.allocated())
+            .map(|(addr, _)| addr)
+            .collect::<BTreeSet<u32>>();
+        for addr in addrs {
+            let word = memory.get(addr);
+            if old.get(addr) != word {
+                triggerMemoryUpdate(graph_id.clone(), gate_id.clone(), addr, word.bits, word.avec, word.bvec);
+            }
+        }
+        self.enqueue(gate)
+    }
+
+    #[wasm_bindgen(js_name = dumpMemoryImage)]
+    pub fn dump_memory_image(&self, graph_id: String, gate_id: String, format: String) -> Result<JsValue, String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        let format = ImageFormat::from_name(&format)?;
+        let (offset, memory) = self.gate(gate)?.get_memory_contents()?;
+        Ok(memimage::dump(format, offset, &memory))
+    }
+
+    // Returns `{ bits, start, count, next, avec, bvec }` with the words packed one after another,
+    // each taking `ceil(bits / 32)` elements of `avec` and `bvec`. At most `READ_PAGE_WORDS` words
+    // are returned at once, `next` is the address to continue from or null after the last one.
+    #[wasm_bindgen(js_name = readMemory)]
+    pub fn read_memory(&self, graph_id: String, gate_id: String, start: u32, count: Option<u32>) -> Result<JsValue, String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        let gate = self.gate(gate)?;
+        let (bits, words) = gate.get_memory_size()?;
+        if start as u64 > words {
+            return Err(format!("Start address {start} is outside of memory {gate_id}"));
+        }
+        let end = count.map_or(words, |c| start as u64 + c as u64).min(words);
+        let page_end = end.min(start as u64 + READ_PAGE_WORDS);
+
+        let mut avec = Vec::new();
+        let mut bvec = Vec::new();
+        for addr in start as u64..page_end {
+            let word = gate.get_memory(addr as u32)?;
+            avec.extend_from_slice(&word.avec);
+            bvec.extend_from_slice(&word.bvec);
+        }
+        let count = (page_end - start as u64) as u32;
+        let next = if page_end < end { JsValue::from(page_end as u32) } else { JsValue::NULL };
+
+        let obj = Object::new();
+        let _ = Reflect::set(&obj, &"bits".into(), &bits.into());
+        let _ = Reflect::set(&obj, &"start".into(), &start.into());
+        let _ = Reflect::set(&obj, &"count".into(), &count.into());
+        let _ = Reflect::set(&obj, &"next".into(), &next);
+        let _ = Reflect::set(&obj, &"avec".into(), &Uint32Array::from(&avec[..]));
+        let _ = Reflect::set(&obj, &"bvec".into(), &Uint32Array::from(&bvec[..]));
+        Ok(obj.into())
+    }
+
+    // Serves `count` words starting at address `start` by a JS device: writes are posted
+    // to the host, reads return the values set with `setMmioValue`.
+    #[wasm_bindgen(js_name = addMmioRegion)]
+    pub fn add_mmio_region(&mut self, graph_id: String, gate_id: String, device_id: u32, start: u32, count: u32) -> Result<(), String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        let device = HostDevice::new(device_id, self.gate(gate)?.get_memory_size()?.0);
+        self.add_mmio_priv(gate, device_id, start, count, MmioHandler::Host(device))
+    }
+
+    #[wasm_bindgen(js_name = addMmioConsole)]
+    pub fn add_mmio_console(&mut self, graph_id: String, gate_id: String, device_id: u32, address: u32) -> Result<(), String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        let device = ConsoleDevice::new(self.gate(gate)?.get_memory_size()?.0);
+        self.add_mmio_priv(gate, device_id, address, 1, MmioHandler::Native(Box::new(device)))
+    }
+
+    #[wasm_bindgen(js_name = removeMmioRegion)]
+    pub fn remove_mmio_region(&mut self, graph_id: String, gate_id: String, device_id: u32) -> Result<(), String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        self.gate_mut(gate)?.get_memory_state_mut()?.mmio.retain(|r| r.id != device_id);
+        self.enqueue(gate)
+    }
+
+    #[wasm_bindgen(js_name = setMmioValue)]
+    pub fn set_mmio_value(&mut self, graph_id: String, gate_id: String, device_id: u32, offset: u32, sig: JsVec3vl) -> Result<(), String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        let state = self.gate_mut(gate)?.get_memory_state_mut()?;
+        match state.mmio.iter_mut().find(|r| r.id == device_id).map(|r| &mut r.handler) {
+            Some(MmioHandler::Host(device)) => device.set_value(offset, Vec3vl::from_clonable(sig)),
+            _ => return Err(format!("Memory {gate_id} has no host device {device_id}"))
+        }
+        self.enqueue(gate)
+    }
+
+    #[wasm_bindgen(js_name = drainMmio)]
+    pub fn drain_mmio(&mut self, graph_id: String, gate_id: String, device_id: u32) -> Result<JsValue, String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        let state = self.gate_mut(gate)?.get_memory_state_mut()?;
+        match state.mmio.iter_mut().find(|r| r.id == device_id) {
+            Some(region) => Ok(region.device_mut().drain()),
+            None => Err(format!("Memory {gate_id} has no device {device_id}"))
+        }
     }
 
     pub fn monitor(&mut self, graph_id: String, gate_id: String, port: String, monitor_id: u32, params: JsMonitorParams) -> Result<(), String> {
-        let gate = self.get_graph(graph_id)?.borrow().get_gate(&gate_id)?;
-        let monitor_params = MonitorParams::new(params, gate.clone(), port.clone());
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        self.monitor_priv(gate, port, monitor_id, params)
+    }
 
-        if monitor_params.trigger_values.is_none() {
-            let sig = gate.borrow().get_output(&port)?;
-            postMonitorValue(monitor_id, self.tick, sig.bits, sig.avec, sig.bvec, None, None);
+    #[wasm_bindgen(js_name = monitorPath)]
+    pub fn monitor_path(&mut self, path: String, monitor_id: u32, params: JsMonitorParams) -> Result<(), String> {
+        let signal = resolve_path(&self.gates, &self.graphs, &self.graph_ids, &path)?;
+        self.monitor_priv(signal.gate, signal.port, monitor_id, params)
+    }
+
+    #[wasm_bindgen(js_name = resolvePath)]
+    pub fn resolve_path(&self, path: String) -> Result<Vec<String>, String> {
+        let signal = resolve_path(&self.gates, &self.graphs, &self.graph_ids, &path)?;
+        Ok(vec![self.graph(signal.graph)?.get_id(), self.gate(signal.gate)?.get_id(), signal.port])
+    }
+
+    #[wasm_bindgen(js_name = listHierarchy)]
+    pub fn list_hierarchy(&self, graph_id: String) -> Result<Vec<String>, String> {
+        let graph = self.get_graph(&graph_id)?;
+        let mut paths = Vec::new();
+        list_hierarchy(&self.gates, &self.graphs, graph, &graph_id, &mut paths);
+        Ok(paths)
+    }
+
+    fn monitor_priv(&mut self, gate: GateId, port: String, monitor_id: u32, params: JsMonitorParams) -> Result<(), String> {
+        let sig = self.gate(gate)?.get_port_value(&port)?;
+        let resolve = |trigger: &JsTrigger| self.resolve_trigger_port(trigger, gate, &port);
+        let history = params.get_capacity().map(|capacity| 
+            History::new(capacity, params.get_decimation().unwrap_or(1), sig.bits)
+        );
+        let mut monitor_params = MonitorParams::new(params, gate, port.clone(), &resolve)?;
+
+        for (g, p) in monitor_params.ports.clone() {
+            let value = self.gate(g)?.get_port_value(&p)?;
+            self.gate_mut(g)?.monitor(p.clone(), monitor_id);
+            monitor_params.previous.insert((g, p), value);
         }
-        
+
+        match history {
+            Some(mut h) => {
+                if monitor_params.condition.is_none() { h.push(self.tick, &sig); }
+                self.monitor_history.insert(monitor_id, h);
+            },
+            None => if monitor_params.condition.is_none() {
+                postMonitorValue(monitor_id, self.tick, sig.bits, sig.avec, sig.bvec, None, None);
+            }
+        }
+
         self.monitors.insert(monitor_id, monitor_params);
-        gate.borrow_mut().monitor(port, monitor_id);
         Ok(())
     }
 
+    #[wasm_bindgen(js_name = drainMonitor)]
+    pub fn drain_monitor(&mut self, monitor_id: u32) -> Result<JsValue, String> {
+        match self.monitor_history.get_mut(&monitor_id) {
+            Some(h) => Ok(h.drain()),
+            None => Err(format!("Monitor id {} is not buffered", monitor_id))
+        }
+    }
+
+    // Trigger leaves may name a signal by hierarchical path, by graph/gate/port,
+    // or just by port; anything left out defaults to the monitored signal.
+    fn resolve_trigger_port(&self, trigger: &JsTrigger, gate: GateId, port: &str) -> Result<PortRef, String> {
+        if let Some(path) = trigger.get_path() {
+            let signal = resolve_path(&self.gates, &self.graphs, &self.graph_ids, &path)?;
+            return Ok((signal.gate, signal.port));
+        }
+
+        let target = match (trigger.get_graph(), trigger.get_gate()) {
+            (Some(graph_id), Some(gate_id)) => self.find_gate(&graph_id, &gate_id)?,
+            (None, Some(gate_id)) => self.graph(self.gate(gate)?.get_graph())?.get_gate(&gate_id)?,
+            _ => gate
+        };
+        let target_port = trigger.get_port().unwrap_or_else(|| port.to_string());
+        if !self.gate(target)?.has_port(&target_port) {
+            return Err(format!("Gate {} has no port {}", self.gate(target)?.get_id(), target_port));
+        }
+        Ok((target, target_port))
+    }
+
     pub fn unmonitor(&mut self, monitor_id: u32) -> Result<(), String> {
         if let Some(monitor) = self.monitors.remove(&monitor_id) {
-            monitor.gate.borrow_mut().unmonitor(&monitor.port, monitor_id);
+            for (g, p) in monitor.ports {
+                if let Some(gate) = self.gates.get_mut(g) {
+                    gate.unmonitor(&p, monitor_id);
+                }
+            }
             self.monitor_checks.remove(&monitor_id);
         }
+        self.monitor_history.remove(&monitor_id);
+        Ok(())
+    }
+
+    pub fn breakpoint(&mut self, graph_id: String, breakpoint_id: u32, expr: String) -> Result<(), String> {
+        let resolve = |name: &str| {
+            let signal = resolve_path(&self.gates, &self.graphs, &self.graph_ids, &format!("{graph_id}.{name}"))?;
+            let bits = self.gate(signal.gate)?.get_port_value(&signal.port)?.bits;
+            Ok(((signal.gate, signal.port), bits))
+        };
+        let mut breakpoint = Breakpoint::compile(&expr, &resolve)?;
+
+        // Only a transition to true should stop the simulation.
+        breakpoint.update(&|p| self.port_value(p));
+        self.breakpoints.insert(breakpoint_id, breakpoint);
         Ok(())
     }
 
+    pub fn unbreakpoint(&mut self, breakpoint_id: u32) {
+        self.breakpoints.remove(&breakpoint_id);
+    }
+
+    #[wasm_bindgen(js_name = startCoverage)]
+    pub fn start_coverage(&mut self) {
+        self.coverage = Some(Coverage::new(self.tick));
+    }
+
+    #[wasm_bindgen(js_name = stopCoverage)]
+    pub fn stop_coverage(&mut self) {
+        self.coverage = None;
+    }
+
+    // Returns `{ gateId: { port: stats } }` for the output ports of one gate,
+    // or of every gate in the graph when no gate is given.
+    #[wasm_bindgen(js_name = coverageReport)]
+    pub fn coverage_report(&self, graph_id: String, gate_id: Option<String>) -> Result<JsValue, String> {
+        let coverage = match &self.coverage {
+            Some(c) => c,
+            None => return Err("Coverage collection is not running".to_string())
+        };
+
+        let gates = match gate_id {
+            Some(g) => vec![self.find_gate(&graph_id, &g)?],
+            None => self.graph(self.get_graph(&graph_id)?)?.gate_iter().copied().collect()
+        };
+
+        let report = Object::new();
+        for gate_id in gates {
+            let gate = self.gate(gate_id)?;
+            let ports = Object::new();
+            for (port, dir) in gate.iodirs_iter() {
+                if *dir != IoDir::Out { continue; }
+                let stats = coverage.report(&(gate_id, port.clone()), &gate.get_output(port)?, self.tick);
+                let _ = Reflect::set(&ports, &port.into(), &stats);
+            }
+            let _ = Reflect::set(&report, &gate.get_id().into(), &ports);
+        }
+        Ok(report.into())
+    }
+
+    #[wasm_bindgen(js_name = startPowerEstimate)]
+    pub fn start_power_estimate(&mut self) {
+        self.power = Some(Power::new(self.tick));
+    }
+
+    #[wasm_bindgen(js_name = stopPowerEstimate)]
+    pub fn stop_power_estimate(&mut self) {
+        self.power = None;
+    }
+
+    // Overrides the capacitance weight of a cell type, named as by `Operation::get_type`.
+    #[wasm_bindgen(js_name = setCapacitance)]
+    pub fn set_capacitance(&mut self, cell_type: String, weight: f64) {
+        self.capacitance.insert(cell_type, weight);
+    }
+
+    #[wasm_bindgen(js_name = powerReport)]
+    pub fn power_report(&self, graph_id: String) -> Result<JsValue, String> {
+        let power = match &self.power {
+            Some(p) => p,
+            None => return Err("Power estimation is not running".to_string())
+        };
+        let graph = self.get_graph(&graph_id)?;
+        Ok(power.report(&self.gates, &self.graphs, graph, &self.capacitance, self.tick))
+    }
+
+    // Returns `{ delay, minClockPeriod, maxFrequency, path: [{ gate, port, arrival }] }`
+    // for the critical path, or undefined when the graph has no timing endpoints.
+    // Gates are named by dotted paths relative to the graph, frequency is per tick.
+    #[wasm_bindgen(js_name = staticTiming)]
+    pub fn static_timing(&self, graph_id: String) -> Result<JsValue, String> {
+        let graph = self.get_graph(&graph_id)?;
+        let report = match analyze(&self.gates, &self.graphs, graph)? {
+            Some(r) => r,
+            None => return Ok(JsValue::UNDEFINED)
+        };
+
+        let path = Array::new();
+        for step in report.path {
+            let obj = Object::new();
+            let _ = Reflect::set(&obj, &"gate".into(), &gate_path(&self.gates, &self.graphs, step.port.0, graph).into());
+            let _ = Reflect::set(&obj, &"port".into(), &step.port.1.into());
+            let _ = Reflect::set(&obj, &"arrival".into(), &step.arrival.into());
+            path.push(&obj);
+        }
+
+        let obj = Object::new();
+        let _ = Reflect::set(&obj, &"delay".into(), &report.delay.into());
+        let _ = Reflect::set(&obj, &"minClockPeriod".into(), &report.delay.max(1).into());
+        let _ = Reflect::set(&obj, &"maxFrequency".into(), &(1.0 / report.delay.max(1) as f64).into());
+        let _ = Reflect::set(&obj, &"path".into(), &path);
+        Ok(obj.into())
+    }
+
+    #[wasm_bindgen(js_name = addFault)]
+    pub fn add_fault(&mut self, graph_id: String, gate_id: String, port: String, fault_id: u32, params: JsFaultParams) -> Result<(), String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        self.gate(gate)?.get_port_dir(&port)?;
+        let fault = Fault::new(FaultSite::Port((gate, port.clone())), &params)?;
+        self.add_fault_priv(fault_id, fault)
+    }
+
+    #[wasm_bindgen(js_name = addMemoryFault)]
+    pub fn add_memory_fault(&mut self, graph_id: String, gate_id: String, address: u32, fault_id: u32, params: JsFaultParams) -> Result<(), String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        self.gate(gate)?.get_memory(address)?;
+        let fault = Fault::new(FaultSite::Memory(gate, address), &params)?;
+        self.add_fault_priv(fault_id, fault)
+    }
+
+    #[wasm_bindgen(js_name = removeFault)]
+    pub fn remove_fault(&mut self, fault_id: u32) -> Result<(), String> {
+        let fault = match self.faults.remove(&fault_id) {
+            Some(f) => f,
+            None => return Ok(())
+        };
+
+        if let Some(tick) = fault.tick {
+            if let Some(pending) = self.fault_queue.get_mut(&tick) {
+                pending.retain(|f| *f != fault_id);
+                if pending.is_empty() {
+                    self.fault_queue.remove(&tick);
+                    self.release_tick_priv(tick);
+                }
+            }
+        }
+
+        if !fault.is_stuck() { return Ok(()); }
+        if let FaultSite::Port(port) = fault.site {
+            let remaining = match self.port_faults.get_mut(&port) {
+                Some(ids) => {
+                    ids.retain(|f| *f != fault_id);
+                    ids.len()
+                },
+                None => return Ok(())
+            };
+
+            let raw = if remaining == 0 {
+                self.port_faults.remove(&port);
+                self.fault_free.remove(&port)
+            } else {
+                self.fault_free.get(&port).cloned()
+            };
+            if let Some(raw) = raw {
+                self.drive_port_priv(&port, raw)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = faultCampaign)]
+    pub fn fault_campaign(&self, graph_id: String, params: JsFaultCampaign) -> Result<JsValue, String> {
+        let graph = self.get_graph(&graph_id)?;
+        campaign::run(self, graph, &params)
+    }
+
+    #[wasm_bindgen(js_name = checkEquivalence)]
+    pub fn check_equivalence(&self, graph_id: String, reference_id: String, params: JsEquivalenceParams) -> Result<JsValue, String> {
+        let graph = self.get_graph(&graph_id)?;
+        let reference = self.get_graph(&reference_id)?;
+        equivalence::run(self, graph, reference, &params)
+    }
+
+    #[wasm_bindgen(js_name = setGateDelay)]
+    pub fn set_gate_delay(&mut self, graph_id: String, gate_id: String, rise: u32, fall: u32) -> Result<(), String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        self.gate_mut(gate)?.set_delay(PortDelay { rise, fall });
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = setPortDelay)]
+    pub fn set_port_delay(&mut self, graph_id: String, gate_id: String, port: String, rise: u32, fall: u32) -> Result<(), String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        self.gate_mut(gate)?.set_port_delay(&port, PortDelay { rise, fall })
+    }
+
+    #[wasm_bindgen(js_name = setDelayModel)]
+    pub fn set_delay_model(&mut self, graph_id: String, gate_id: String, model: String) -> Result<(), String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        self.gate_mut(gate)?.set_delay_model(DelayModel::from_name(&model)?);
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = startGlitchDetection)]
+    pub fn start_glitch_detection(&mut self, max_width: u32) {
+        self.glitches = Some(GlitchDetector::new(max_width));
+    }
+
+    #[wasm_bindgen(js_name = stopGlitchDetection)]
+    pub fn stop_glitch_detection(&mut self) {
+        self.glitches = None;
+    }
+
+    // Returns `{ glitches: [{ graphId, gateId, port, tick, width }], dropped }` and
+    // forgets the returned glitches. `dropped` counts glitches over the buffer limit.
+    #[wasm_bindgen(js_name = drainGlitches)]
+    pub fn drain_glitches(&mut self) -> Result<JsValue, String> {
+        let (found, dropped) = match &mut self.glitches {
+            Some(g) => g.drain(),
+            None => return Err("Glitch detection is not running".to_string())
+        };
+
+        let glitches = Array::new();
+        for glitch in found {
+            let gate = match self.gates.get(glitch.port.0) {
+                Some(g) => g,
+                None => continue
+            };
+            let obj = Object::new();
+            let _ = Reflect::set(&obj, &"graphId".into(), &self.graph(gate.get_graph())?.get_id().into());
+            let _ = Reflect::set(&obj, &"gateId".into(), &gate.get_id().into());
+            let _ = Reflect::set(&obj, &"port".into(), &glitch.port.1.into());
+            let _ = Reflect::set(&obj, &"tick".into(), &glitch.tick.into());
+            let _ = Reflect::set(&obj, &"width".into(), &glitch.width.into());
+            glitches.push(&obj);
+        }
+
+        let obj = Object::new();
+        let _ = Reflect::set(&obj, &"glitches".into(), &glitches);
+        let _ = Reflect::set(&obj, &"dropped".into(), &dropped.into());
+        Ok(obj.into())
+    }
+
+    #[wasm_bindgen(js_name = fsmCoverage)]
+    pub fn fsm_coverage(&self, graph_id: String, gate_id: String) -> Result<JsValue, String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        self.gate(gate)?.fsm_coverage()
+    }
+
+    #[wasm_bindgen(js_name = resetFsmCoverage)]
+    pub fn reset_fsm_coverage(&mut self, graph_id: String, gate_id: String) -> Result<(), String> {
+        let gate = self.find_gate(&graph_id, &gate_id)?;
+        self.gate_mut(gate)?.reset_fsm_coverage()
+    }
+
     pub fn alarm(&mut self, tick: u32, alarm_id: u32, data: JsAlarmStruct) {
         if tick <= self.tick { return; }
 
@@ -333,36 +939,64 @@ impl RustEngine {
         } 
     }
 
-    fn enqueue(&mut self, gate: &GatePtr) {
-        let k = self.tick.wrapping_add(gate.borrow().get_propagation());
+    fn enqueue(&mut self, gate_id: GateId) -> Result<(), String> {
+        let gate = self.gate(gate_id)?;
+        if self.graph(gate.get_graph())?.is_definition() {
+            return Ok(());
+        }
+
+        let k = if gate.is_timed() { self.tick } else { self.tick.wrapping_add(gate.get_propagation()) };
+        let inputs = gate.get_inputs();
+
         let sq = self.queue.entry(k).or_insert_with(|| {
             self.pq.insert(k);
             HashMap::new()
         });
 
-        let name = gate.borrow().get_graph().borrow().get_id() + &gate.borrow().get_id();
-        sq.insert(name, (gate.clone(), gate.borrow().get_inputs().clone()));
+        sq.insert(gate_id, inputs);
+        Ok(())
     }
 
     #[wasm_bindgen(js_name = _postMonitors)]
     pub fn post_monitors(&mut self) -> Result<(), String> {
-        let monitors = self.monitor_checks.clone();
-        self.monitor_checks = HashMap::new();
+        let monitors = std::mem::take(&mut self.monitor_checks);
 
-        for (monitor_id, sig) in monitors {
+        for monitor_id in monitors {
+            let sig = match self.check_monitor(monitor_id)? {
+                Some(sig) => sig,
+                None => continue
+            };
             let params = self.get_monitor(monitor_id)?;
-            let mut triggered = true;
-            if let Some(trigger_values) = params.trigger_values {
-                triggered = trigger_values.contains(&sig);
-            }
 
-            if triggered {
+            if let Some(mut history) = self.monitor_history.remove(&monitor_id) {
+                history.push(self.tick, &sig);
                 if params.one_shot { self.unmonitor(monitor_id)?; }
-                if params.synchronous { self.send_updates_priv()?; }
-                postMonitorValue(monitor_id, self.tick, sig.bits, sig.avec, sig.bvec, Some(params.stop_on_trigger), Some(params.one_shot));
-                if params.stop_on_trigger { updater_stop(); }
+                // Samples stay available to drainMonitor until the monitor is removed from JS.
+                self.monitor_history.insert(monitor_id, history);
+                if params.stop_on_trigger {
+                    if params.synchronous { self.send_updates_priv()?; }
+                    sendMonitorStopped(monitor_id, self.tick);
+                    updater_stop();
+                }
+                continue;
+            }
+
+            if params.one_shot { self.unmonitor(monitor_id)?; }
+            if params.synchronous { self.send_updates_priv()?; }
+            postMonitorValue(monitor_id, self.tick, sig.bits, sig.avec, sig.bvec, Some(params.stop_on_trigger), Some(params.one_shot));
+            if params.stop_on_trigger { updater_stop(); }
+        }
+
+        let mut breakpoints = std::mem::take(&mut self.breakpoints);
+        let mut hit = false;
+        for (breakpoint_id, breakpoint) in breakpoints.iter_mut() {
+            if breakpoint.update(&|p| self.port_value(p)) {
+                sendBreakpointHit(*breakpoint_id, self.tick);
+                hit = true;
             }
         }
+        self.breakpoints = breakpoints;
+        if hit { updater_stop(); }
 
         if self.alarm_queue.contains_key(&self.tick) {
             let aq = self.alarm_queue.remove(&self.tick).unwrap();
@@ -377,85 +1011,326 @@ impl RustEngine {
         Ok(())
     }
 
-    fn set_gate_output_signals_priv(&mut self, gate: &GatePtr, sigs: ReturnValue) -> Result<(), String> {
+    fn check_monitor(&mut self, monitor_id: u32) -> Result<Option<Vec3vl>, String> {
+        let monitor = match self.monitors.get(&monitor_id) {
+            Some(m) => m,
+            None => return Ok(None)
+        };
+
+        let sig = match self.gates.get(monitor.gate) {
+            Some(gate) => gate.get_port_value(&monitor.port)?,
+            None => {
+                // The monitored gate is gone; samples already buffered stay drainable.
+                let history = self.monitor_history.remove(&monitor_id);
+                self.unmonitor(monitor_id)?;
+                if let Some(history) = history { self.monitor_history.insert(monitor_id, history); }
+                return Ok(None);
+            }
+        };
+
+        let mut current = HashMap::new();
+        for (g, p) in &monitor.ports {
+            if let Some(gate) = self.gates.get(*g) {
+                current.insert((*g, p.clone()), gate.get_port_value(p)?);
+            }
+        }
+
+        let triggered = match &monitor.condition {
+            Some(c) => c.eval(&current, &monitor.previous),
+            None => true
+        };
+
+        if let Some(m) = self.monitors.get_mut(&monitor_id) {
+            m.previous = current;
+        }
+        Ok(if triggered { Some(sig) } else { None })
+    }
+
+    fn set_gate_output_signals_priv(&mut self, gate: GateId, sigs: ReturnValue) -> Result<(), String> {
         if let Some(sig) = sigs.get_out() {
-            self.set_gate_output_signal_priv(gate, "out".to_string(), sig)?;
+            self.schedule_output_priv(gate, "out".to_string(), sig)?;
         }
 
         for (port, sig) in sigs.signals_iter() {
-            self.set_gate_output_signal_priv(gate, port.clone(), sig.clone())?;
+            self.schedule_output_priv(gate, port.clone(), sig.clone())?;
         };
 
         Ok(())
-    } 
+    }
+
+    fn schedule_output_priv(&mut self, gate_id: GateId, port: String, sig: Vec3vl) -> Result<(), String> {
+        let gate = self.gate(gate_id)?;
+        if !gate.is_timed() {
+            return self.set_gate_output_signal_priv(gate_id, port, sig);
+        }
 
-    fn set_gate_output_signal_priv(&mut self, gate: &GatePtr, port: String, sig: Vec3vl) -> Result<(), String> {
-        let old_sig = gate.borrow().get_output(&port)?;
+        // A later change of a port supersedes everything scheduled after it, and
+        // with inertial delay also the earlier changes to another value, which are
+        // pulses that have not come out yet.
+        let old_sig = gate.get_output(&port)?;
+        let k = self.tick.wrapping_add(gate.get_output_delay(&port, &old_sig, &sig));
+        let inertial = gate.get_delay_model() == DelayModel::Inertial;
+        let pending = self.output_ticks.remove(&(gate_id, port.clone())).unwrap_or_default();
+        let mut kept = BTreeSet::new();
+        for p in pending {
+            if let Some(changes) = self.output_queue.get_mut(&p) {
+                changes.retain(|(g, pt, v)| *g != gate_id || *pt != port || (p < k && (!inertial || *v == sig)));
+                if changes.is_empty() {
+                    self.output_queue.remove(&p);
+                    self.release_tick_priv(p);
+                } else if changes.iter().any(|(g, pt, _)| *g == gate_id && *pt == port) {
+                    kept.insert(p);
+                }
+            }
+        }
+
+        if k == self.tick {
+            if !kept.is_empty() {
+                self.output_ticks.insert((gate_id, port.clone()), kept);
+            }
+            return self.set_gate_output_signal_priv(gate_id, port, sig);
+        }
+
+        kept.insert(k);
+        self.output_ticks.insert((gate_id, port.clone()), kept);
+        self.output_queue.entry(k).or_insert_with(|| {
+            self.pq.insert(k);
+            Vec::new()
+        }).push((gate_id, port, sig));
+        Ok(())
+    }
+
+    fn forget_output_tick_priv(&mut self, gate_id: GateId, port: &str, k: u32) {
+        let key = (gate_id, port.to_string());
+        if let Some(ticks) = self.output_ticks.get_mut(&key) {
+            ticks.remove(&k);
+            if ticks.is_empty() {
+                self.output_ticks.remove(&key);
+            }
+        }
+    }
+
+    // Drops a tick from the event queue once nothing is scheduled for it.
+    fn release_tick_priv(&mut self, k: u32) {
+        if !self.queue.contains_key(&k) && !self.output_queue.contains_key(&k) && !self.fault_queue.contains_key(&k) {
+            self.pq.remove(&k);
+        }
+    }
+
+    fn add_mmio_priv(&mut self, gate: GateId, id: u32, start: u32, count: u32, handler: MmioHandler) -> Result<(), String> {
+        let graph_id = self.graph(self.gate(gate)?.get_graph())?.get_id();
+        self.gate_mut(gate)?.make_writable(graph_id);
+        let state = self.gate_mut(gate)?.get_memory_state_mut()?;
+        let index = match start.checked_sub(state.offset) {
+            Some(s) if s as u64 + count as u64 <= state.words => s,
+            _ => return Err(format!("Address range {start}+{count} is outside of the memory"))
+        };
+        if state.mmio.iter().any(|r| r.id != id && index < r.start + r.count && r.start < index + count) {
+            return Err(format!("Address range {start}+{count} overlaps another device"));
+        }
+
+        state.mmio.retain(|r| r.id != id);
+        state.mmio.push(MmioRegion { id, start: index, count, handler });
+        self.enqueue(gate)
+    }
+
+    // Copy of the engine state without any pending events, monitors or statistics.
+    // The host does not know about it, so its cells do not notify the host.
+    fn scratch_priv(&self) -> RustEngine {
+        let mut sim = RustEngine::new();
+        sim.tick = self.tick;
+        sim.gates = self.gates.clone();
+        for (_, gate) in sim.gates.iter_mut() {
+            gate.detach_host();
+        }
+        sim.graphs = self.graphs.clone();
+        sim.graph_ids = self.graph_ids.clone();
+        sim.instance_counter = self.instance_counter;
+        sim
+    }
+
+    // Runs pending events scheduled at most `limit` ticks ahead.
+    fn settle_priv(&mut self, limit: u32) -> Result<(), String> {
+        let start = self.tick;
+        while let Some(k) = self.pq.first().copied() {
+            if k.wrapping_sub(start) > limit { break; }
+            self.update_gates_next_priv()?;
+        }
+        Ok(())
+    }
+
+    fn add_fault_priv(&mut self, fault_id: u32, fault: Fault) -> Result<(), String> {
+        self.remove_fault(fault_id)?;
+
+        if let Some(tick) = fault.tick {
+            if tick <= self.tick {
+                return Err(format!("Fault tick {} is not in the future", tick));
+            }
+            self.fault_queue.entry(tick).or_default().push(fault_id);
+            self.pq.insert(tick);
+            self.faults.insert(fault_id, fault);
+            return Ok(());
+        }
+
+        self.activate_stuck_fault_priv(fault_id, fault)
+    }
+
+    fn activate_stuck_fault_priv(&mut self, fault_id: u32, fault: Fault) -> Result<(), String> {
+        let port = match &fault.site {
+            FaultSite::Port(p) => p.clone(),
+            FaultSite::Memory(_, _) => return Err("Memory faults need a tick".to_string())
+        };
+        let raw = match self.fault_free.get(&port) {
+            Some(v) => v.clone(),
+            None => self.gate(port.0)?.get_port_value(&port.1)?
+        };
+
+        self.faults.insert(fault_id, fault);
+        self.port_faults.entry(port.clone()).or_default().push(fault_id);
+        self.drive_port_priv(&port, raw)
+    }
+
+    fn fire_fault_priv(&mut self, fault_id: u32) -> Result<(), String> {
+        let fault = match self.faults.remove(&fault_id) {
+            Some(f) => f,
+            None => return Ok(())
+        };
+
+        if fault.is_stuck() {
+            if let FaultSite::Port(port) = &fault.site {
+                if !self.gates.contains(port.0) { return Ok(()); }
+            }
+            return self.activate_stuck_fault_priv(fault_id, fault);
+        }
+
+        match &fault.site {
+            FaultSite::Port(port) => {
+                if !self.gates.contains(port.0) { return Ok(()); }
+                let value = self.gate(port.0)?.get_port_value(&port.1)?;
+                self.drive_port_priv(port, fault.apply(&value))
+            },
+            FaultSite::Memory(gate_id, address) => {
+                if !self.gates.contains(*gate_id) { return Ok(()); }
+                let word = fault.apply(&self.gate(*gate_id)?.get_memory(*address)?);
+                self.gate_mut(*gate_id)?.set_memory(*address, word.clone())?;
+
+                let gate = self.gate(*gate_id)?;
+                triggerMemoryUpdate(self.graph(gate.get_graph())?.get_id(), gate.get_id(), *address, word.bits, word.avec, word.bvec);
+                self.enqueue(*gate_id)
+            }
+        }
+    }
+
+    // Sets a port as if its driver had produced `sig`, so stuck-at faults apply.
+    fn drive_port_priv(&mut self, (gate, port): &PortRef, sig: Vec3vl) -> Result<(), String> {
+        match self.gate(*gate)?.get_port_dir(port)? {
+            IoDir::Out => self.set_gate_output_signal_priv(*gate, port.clone(), sig),
+            IoDir::In => self.set_gate_input_signal_priv(*gate, port.clone(), sig)
+        }
+    }
+
+    fn apply_faults_priv(&mut self, port: PortRef, sig: Vec3vl) -> Vec3vl {
+        let ids = match self.port_faults.get(&port) {
+            Some(ids) => ids,
+            None => return sig
+        };
+
+        let faulty = ids.iter()
+            .filter_map(|id| self.faults.get(id))
+            .fold(sig.clone(), |v, fault| fault.apply(&v));
+        self.fault_free.insert(port, sig);
+        faulty
+    }
+
+    fn set_gate_output_signal_priv(&mut self, gate_id: GateId, port: String, sig: Vec3vl) -> Result<(), String> {
+        let sig = self.apply_faults_priv((gate_id, port.clone()), sig);
+        let gate = self.gate(gate_id)?;
+        let old_sig = gate.get_output(&port)?;
         if old_sig == sig { return Ok(()); }
 
-        gate.borrow_mut().set_output(port.clone(), sig.clone());
-        self.mark_update_priv(gate, port.clone());
+        let graph = gate.get_graph();
+        let tgts = gate.get_targets(&port)?;
+        let monitors = gate.get_monitors(&port).copied().collect::<Vec<u32>>();
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record((gate_id, port.clone()), &old_sig, &sig, self.tick);
+        }
+        if let Some(power) = &mut self.power {
+            power.record(gate_id, &old_sig, &sig);
+        }
+        if let Some(glitches) = &mut self.glitches {
+            glitches.record((gate_id, port.clone()), &old_sig, &sig, self.tick);
+        }
+
+        self.gate_mut(gate_id)?.set_output(port.clone(), sig.clone());
+        self.mark_update_priv(gate_id, port.clone())?;
 
-        let tgts = gate.borrow().get_targets(&port)?;
         for target in tgts {
-            let target_gate = gate.borrow().get_graph().borrow().get_gate(&target.id)?;
+            let target_gate = self.graph(graph)?.get_gate(&target.id)?;
             self.set_gate_input_signal_priv(target_gate, target.port, sig.clone())?;
         }
         
-        for monitor_id in gate.borrow().get_monitors(&port) {
-            self.monitor_checks.insert(*monitor_id, sig.clone());
-        }
+        self.monitor_checks.extend(monitors);
 
         Ok(())
     }
 
-    fn set_gate_input_signal_priv(&mut self, target_gate: GatePtr, port: String, sig: Vec3vl) -> Result<(), String> {
-        let old_sig = target_gate.borrow().get_input(&port)?;
+    fn set_gate_input_signal_priv(&mut self, target_gate: GateId, port: String, sig: Vec3vl) -> Result<(), String> {
+        let sig = self.apply_faults_priv((target_gate, port.clone()), sig);
+        let old_sig = self.gate(target_gate)?.get_input(&port)?;
         if old_sig == sig { return Ok(()); }
-        target_gate.borrow_mut().set_input(port.clone(), sig.clone());
-
-        if target_gate.borrow().is_subcircuit() {
-            let subgraph = target_gate.borrow().get_subgraph()?; 
-            let iomap = target_gate.borrow().get_subgraph_iomap_port(&port)?;
-            let gate = subgraph.borrow().get_gate(&iomap)?;
-
-            self.set_gate_output_signal_priv(&gate, "out".to_string(), sig)?;
-        } else if target_gate.borrow().is_output() {
-            let subgraph = target_gate.borrow().get_graph();
-            if let Some(subcir) = subgraph.borrow().subcircuit() {
-                let subcir_port = target_gate.borrow().get_subcir_net()?;
-                self.set_gate_output_signal_priv(&subcir, subcir_port, sig)?;
+        self.gate_mut(target_gate)?.set_input(port.clone(), sig.clone());
+
+        let monitors = self.gate(target_gate)?.get_monitors(&port).copied().collect::<Vec<u32>>();
+        self.monitor_checks.extend(monitors);
+
+        let gate = self.gate(target_gate)?;
+        if gate.is_subcircuit() {
+            let subgraph = gate.get_subgraph()?; 
+            let iomap = gate.get_subgraph_iomap_port(&port)?;
+            let io = self.graph(subgraph)?.get_gate(&iomap)?;
+
+            self.set_gate_output_signal_priv(io, "out".to_string(), sig)?;
+        } else if gate.is_output() {
+            let subcir_port = gate.get_subcir_net();
+            if let Some(subcir) = self.graph(gate.get_graph())?.subcircuit() {
+                self.set_gate_output_signal_priv(subcir, subcir_port?, sig)?;
             };
         } else {
-            self.enqueue(&target_gate);
+            self.enqueue(target_gate)?;
         }
         Ok(())
     }
 
-    fn mark_update_priv(&mut self, gate: &GatePtr, port: String) {
-        if !gate.borrow().get_graph().borrow().observed() {
-            return;
+    fn mark_update_priv(&mut self, gate: GateId, port: String) -> Result<(), String> {
+        let graph = self.gate(gate)?.get_graph();
+        if !self.graph(graph)?.observed() {
+            return Ok(());
         }
 
-        let name = format!("{}{}", gate.borrow().graph_id(), gate.borrow().get_id());
-        self.to_update.entry(name).or_insert((gate.clone(), HashSet::new())).1.insert(port);
+        self.to_update.entry(gate).or_default().insert(port);
+        Ok(())
     }
 
     #[wasm_bindgen(js_name = _sendUpdates)]
     pub fn send_updates_priv(&mut self) -> Result<(), String> {
         let mut updates= Vec::new();
 
-        for (_gate_id, (gate, ports)) in self.to_update.iter() {
+        for (gate_id, ports) in self.to_update.iter() {
+            let gate = match self.gates.get(*gate_id) {
+                Some(g) => g,
+                None => continue
+            };
             let mut signals = Vec::new();
 
             for port in ports {
-                let sig = gate.borrow().get_output(port)?;
+                let sig = gate.get_output(port)?;
                 signals.push(PortUpdate { port: port.clone(), bits: sig.bits, avec: sig.avec, bvec: sig.bvec });
             }
 
             updates.push(UpdateStruct {
-                graph_id: gate.borrow().graph_id(),
-                gate_id: gate.borrow().get_id(),
+                graph_id: self.graph(gate.get_graph())?.get_id(),
+                gate_id: gate.get_id(),
                 val: signals
             });
         }
@@ -470,18 +1345,169 @@ impl RustEngine {
     }
 
     fn has_pending_updates(&self) -> bool {
-        !self.queue.is_empty()
+        !self.queue.is_empty() || !self.output_queue.is_empty() || !self.fault_queue.is_empty()
     }
 }
 
 impl RustEngine {
-    fn get_graph(&self, graph_id: String) -> Result<&GraphPtr, String> {
-        match self.graphs.get(&graph_id) {
-            Some(g) => Ok(g),
+    // Every gate in a tick bucket works on its own snapshot of inputs, so all of them
+    // can be evaluated before any output is propagated.
+    #[cfg(not(feature = "threads"))]
+    fn evaluate_gates(&mut self, q: &GateUpdateCollection) -> Result<Vec<(GateId, ReturnValue)>, String> {
+        self.evaluate_gates_seq(q)
+    }
+
+    // Gates calling back into JS (memories, FSMs) must stay on the engine thread.
+    #[cfg(feature = "threads")]
+    fn evaluate_gates(&mut self, q: &GateUpdateCollection) -> Result<Vec<(GateId, ReturnValue)>, String> {
+        if q.len() < PARALLEL_THRESHOLD {
+            return self.evaluate_gates_seq(q);
+        }
+
+        let ids = q.keys().copied().collect::<Vec<GateId>>();
+        let jobs = ids.iter()
+            .zip(self.gates.get_disjoint_mut(&ids))
+            .filter_map(|(id, gate)| gate.map(|g| (*id, g)))
+            .collect::<Vec<_>>();
+
+        parallel::evaluate(jobs, |(_, gate)| gate.has_host_effects(),
+            |(id, gate)| gate.do_operation(&q[&id]).map(|r| (id, r)))
+    }
+
+    fn evaluate_gates_seq(&mut self, q: &GateUpdateCollection) -> Result<Vec<(GateId, ReturnValue)>, String> {
+        let mut results = Vec::with_capacity(q.len());
+        for (gate_id, sigs) in q.iter() {
+            if let Some(gate) = self.gates.get_mut(*gate_id) {
+                results.push((*gate_id, gate.do_operation(sigs)?));
+            }
+        }
+        Ok(results)
+    }
+
+    fn bind_subcircuit_priv(&mut self, gate: GateId, subgraph: GraphId, io_map: Vec<IOmap>) -> Result<(), String> {
+        self.gate_mut(gate)?.set_subgraph(subgraph);
+        self.graph_mut(subgraph)?.set_subcircuit(gate);
+
+        let mut map = HashMap::new();
+        for i in io_map {
+            let port = i.get_port();
+            let io_id = i.get_io_id();
+
+            map.insert(port.clone(), io_id.clone());
+            let io = self.graph(subgraph)?.get_gate(&io_id)?;
+            match self.gate(gate)?.get_port_dir(&port)? {
+                IoDir::In => {
+                    let sig = self.gate(gate)?.get_input(&port)?;
+                    self.set_gate_output_signal_priv(io, "out".to_string(), sig)?
+                },
+                IoDir::Out => {
+                    let sig = self.gate(io)?.get_input(&"in".to_string())?;
+                    self.set_gate_output_signal_priv(gate, port, sig)?
+                },
+            };
+        }
+
+        self.gate_mut(gate)?.set_subgraph_iomap(map);
+        Ok(())
+    }
+
+    fn clone_graph_priv(&mut self, graph: GraphId, name: String, suffix: &str, copies: &mut HashMap<GateId, GateId>) -> Result<GraphId, String> {
+        let source = self.graph(graph)?;
+        if self.graph_ids.contains_key(&name) {
+            return Err(format!("Graph {name} already exists"));
+        }
+
+        let gates = source.gate_iter().copied().collect::<Vec<GateId>>();
+        let copy = self.graphs.insert(source.duplicate(name.clone()));
+        self.graph_ids.insert(name.clone(), copy);
+
+        for gate in gates {
+            let original = self.gate(gate)?;
+            let gate_id = original.get_id();
+            let subgraph = original.get_subgraph().ok();
+            let new_gate = self.gates.insert(original.duplicate(copy, name.clone()));
+
+            self.graph_mut(copy)?.add_gate(gate_id, new_gate);
+            copies.insert(gate, new_gate);
+
+            if let Some(sub) = subgraph {
+                let sub_name = format!("{}{suffix}", self.graph(sub)?.get_id());
+                let new_sub = self.clone_graph_priv(sub, sub_name, suffix, copies)?;
+                self.gate_mut(new_gate)?.set_subgraph(new_sub);
+                self.graph_mut(new_sub)?.set_subcircuit(new_gate);
+            }
+        }
+
+        Ok(copy)
+    }
+
+    fn remove_graph_priv(&mut self, graph: GraphId) -> Result<(), String> {
+        let gates = self.graph(graph)?.gate_iter().copied().collect::<Vec<GateId>>();
+
+        for gate_id in gates {
+            let gate = match self.gates.remove(gate_id) {
+                Some(g) => g,
+                None => continue
+            };
+            if let Ok(sub) = gate.get_subgraph() {
+                self.remove_graph_priv(sub)?;
+            }
+            for monitor_id in gate.monitor_ids() {
+                self.unmonitor(monitor_id)?;
+            }
+            self.to_update.remove(&gate_id);
+        }
+
+        if let Some(g) = self.graphs.remove(graph) {
+            self.graph_ids.remove(&g.get_id());
+        }
+        Ok(())
+    }
+
+    fn get_graph(&self, graph_id: &String) -> Result<GraphId, String> {
+        match self.graph_ids.get(graph_id) {
+            Some(g) => Ok(*g),
             None => Err(format!("No graph with id {graph_id}"))
         }
     }
 
+    fn find_gate(&self, graph_id: &String, gate_id: &String) -> Result<GateId, String> {
+        let graph = self.get_graph(graph_id)?;
+        self.graph(graph)?.get_gate(gate_id)
+    }
+
+    fn graph(&self, graph: GraphId) -> Result<&Graph, String> {
+        match self.graphs.get(graph) {
+            Some(g) => Ok(g),
+            None => Err(format!("No graph with handle {graph:?}"))
+        }
+    }
+
+    fn graph_mut(&mut self, graph: GraphId) -> Result<&mut Graph, String> {
+        match self.graphs.get_mut(graph) {
+            Some(g) => Ok(g),
+            None => Err(format!("No graph with handle {graph:?}"))
+        }
+    }
+
+    fn gate(&self, gate: GateId) -> Result<&Gate, String> {
+        match self.gates.get(gate) {
+            Some(g) => Ok(g),
+            None => Err(format!("No gate with handle {gate:?}"))
+        }
+    }
+
+    fn gate_mut(&mut self, gate: GateId) -> Result<&mut Gate, String> {
+        match self.gates.get_mut(gate) {
+            Some(g) => Ok(g),
+            None => Err(format!("No gate with handle {gate:?}"))
+        }
+    }
+
+    fn port_value(&self, (gate, port): &PortRef) -> Option<Vec3vl> {
+        self.gates.get(*gate)?.get_port_value(port).ok()
+    }
+
     fn get_monitor(&self, monitor_id: u32) -> Result<MonitorParams, String> {
         match self.monitors.get(&monitor_id) {
             Some(m) => Ok(m.clone()),
@@ -508,26 +1534,40 @@ struct PortUpdate {
 
 #[derive(Clone)]
 struct MonitorParams {
-    pub trigger_values: Option<Vec<Vec3vl>>,
+    pub condition: Option<Condition>,
+    pub previous: HashMap<PortRef, Vec3vl>,
+    pub ports: Vec<PortRef>,
     pub stop_on_trigger: bool,
     pub one_shot: bool,
     pub synchronous: bool,
-    pub gate: GatePtr,
+    pub gate: GateId,
     pub port: String
 }
 
 impl MonitorParams {
-    pub fn new(params: JsMonitorParams, gate: GatePtr, port: String) -> MonitorParams {
-        MonitorParams { 
-            trigger_values: params.get_trigger_values().map(|v| 
-                v.into_iter().map(Vec3vl::from_clonable).collect()
-            ), 
+    pub fn new(params: JsMonitorParams, gate: GateId, port: String, resolve: &dyn Fn(&JsTrigger) -> Result<PortRef, String>) -> Result<MonitorParams, String> {
+        let condition = match (params.get_trigger(), params.get_trigger_values()) {
+            (Some(trigger), _) => Some(Condition::from_js(&trigger, resolve)?),
+            (None, Some(values)) => Some(Condition::Values(
+                (gate, port.clone()),
+                values.into_iter().map(Vec3vl::from_clonable).collect()
+            )),
+            (None, None) => None
+        };
+
+        let mut ports = vec![(gate, port.clone())];
+        if let Some(c) = &condition { c.ports(&mut ports); }
+
+        Ok(MonitorParams { 
+            condition,
+            previous: HashMap::new(),
+            ports,
             stop_on_trigger: params.get_stop_on_trigger().unwrap_or(false), 
             one_shot: params.get_one_shot().unwrap_or(false), 
             synchronous: params.get_synchronous().unwrap_or(false), 
             gate, 
             port 
-        }
+        })
     }
 }
 
@@ -546,4 +1586,94 @@ impl AlarmParams {
             synchronous: params.get_synchronous() 
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(all(test, feature = "threads"))]
+mod tests {
+    use super::*;
+    use crate::cell_memory::{MemoryPortPolarity, MemoryState};
+    use crate::operations::Operation;
+    use crate::paged_memory::PagedMemory;
+
+    const INVERTERS: u32 = 120;
+
+    fn memory(gate_id: String) -> MemoryState {
+        let mut memory = PagedMemory::new(8, 8, Vec3vl::zeros(8));
+        for addr in 0..8 {
+            memory.set(addr, &Vec3vl::from_number(addr * 3 + 1, 8));
+        }
+        MemoryState {
+            gate_id,
+            graph_id: "top".to_string(),
+            abits: 3,
+            bits: 8,
+            memory,
+            mmio: Vec::new(),
+            offset: 0,
+            words: 8,
+            rdports: vec![("rd0".to_string(), MemoryPortPolarity::default())],
+            wrports: Vec::new(),
+            outputs: HashMap::new(),
+            last_clk: HashMap::new(),
+            last_read: HashMap::new(),
+            detached: true
+        }
+    }
+
+    // Inverters with a memory after every eighth one, updated `batch` gates per tick.
+    // Returns the outputs of all gates once the engine is stable.
+    fn run(batch: usize) -> Vec<(String, Vec<i32>)> {
+        let mut engine = RustEngine::new();
+        engine.add_graph("top".to_string());
+        let graph = engine.get_graph(&"top".to_string()).unwrap();
+
+        let mut gates = Vec::new();
+        for n in 0..INVERTERS {
+            let input = [Vec3vl::ones(1), Vec3vl::zeros(1), Vec3vl::xes(1)][n as usize % 3].clone();
+            gates.push(Gate::with_operation(graph, format!("n{n}"), Operation::Gate11(Vec3vl::not),
+                vec![("in", IoDir::In, input), ("out", IoDir::Out, Vec3vl::xes(1))]));
+            if n % 8 == 0 {
+                let id = format!("m{n}");
+                let addr = Vec3vl::from_number(n / 8 % 8, 3);
+                gates.push(Gate::with_operation(graph, id.clone(), Operation::Memory(memory(id)),
+                    vec![("rd0addr", IoDir::In, addr), ("rd0data", IoDir::Out, Vec3vl::xes(8))]));
+            }
+        }
+
+        let mut ids = Vec::new();
+        for gate in gates {
+            let id = gate.get_id();
+            let gate_id = engine.gates.insert(gate);
+            engine.graph_mut(graph).unwrap().add_gate(id.clone(), gate_id);
+            ids.push((id, gate_id));
+        }
+
+        for chunk in ids.chunks(batch) {
+            for (_, gate_id) in chunk {
+                engine.enqueue(*gate_id).unwrap();
+            }
+            while engine.has_pending_updates() {
+                engine.update_gates_priv().unwrap();
+            }
+        }
+
+        ids.iter().map(|(id, gate_id)| {
+            let gate = engine.gate(*gate_id).unwrap();
+            let port = if id.starts_with('m') { "rd0data" } else { "out" };
+            (id.clone(), gate.get_output(&port.to_string()).unwrap().to_array())
+        }).collect()
+    }
+
+    #[test]
+    fn parallel_tick_matches_serial_run() {
+        let parallel = run(usize::MAX);
+        assert!(parallel.len() >= PARALLEL_THRESHOLD);
+        assert_eq!(parallel, run(PARALLEL_THRESHOLD / 4));
+
+        let output = |id: &str| parallel.iter().find(|(gate, _)| gate == id).unwrap().1.clone();
+        assert_eq!(output("n0"), vec![-1]);
+        assert_eq!(output("n1"), vec![1]);
+        assert_eq!(output("n2"), vec![0]);
+        assert_eq!(output("m8"), Vec3vl::from_number(4, 8).to_array());
+        assert_eq!(output("m112"), Vec3vl::from_number(19, 8).to_array());
+    }
+}
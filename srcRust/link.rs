@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub struct Link {
     pub source: LinkTarget,
     pub target: LinkTarget
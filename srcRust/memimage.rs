@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+
+use js_sys::Uint8Array;
+use wasm_bindgen::JsValue;
+
+use crate::paged_memory::PagedMemory;
+use crate::vector3vl::Vec3vl;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Hex,
+    Bin,
+    Raw,
+    IntelHex
+}
+
+impl ImageFormat {
+    pub fn from_name(name: &str) -> Result<ImageFormat, String> {
+        match name {
+            "hex"  => Ok(ImageFormat::Hex),
+            "bin"  => Ok(ImageFormat::Bin),
+            "raw"  => Ok(ImageFormat::Raw),
+            "ihex" => Ok(ImageFormat::IntelHex),
+            _ => Err(format!("Unknown memory image format {name}"))
+        }
+    }
+}
+
+// Builds the full contents of a memory from an image. Addresses in `@` directives and
+// Intel HEX records are absolute (Intel HEX ones in bytes), raw images start at `offset`.
+// Words the image does not cover keep the fill value of the memory.
+pub fn load(format: ImageFormat, data: &JsValue, offset: u32, memory: &PagedMemory) -> Result<PagedMemory, String> {
+    match format {
+        ImageFormat::Raw => load_raw(&Uint8Array::new(data).to_vec(), memory),
+        _ => load_text(format, &data.as_string().ok_or("Expected memory image text")?, offset, memory)
+    }
+}
+
+// Words that are entirely x and pages that were never written are left out of the text
+// and Intel HEX images. Raw images end with the last written page.
+pub fn dump(format: ImageFormat, offset: u32, memory: &PagedMemory) -> JsValue {
+    match format {
+        ImageFormat::Raw => Uint8Array::from(&dump_raw(memory)[..]).into(),
+        _ => dump_text(format, offset, memory).into()
+    }
+}
+
+fn load_text(format: ImageFormat, text: &str, offset: u32, layout: &PagedMemory) -> Result<PagedMemory, String> {
+    let mut memory = PagedMemory::new(layout.bits(), layout.words(), layout.fill().clone());
+    if format == ImageFormat::IntelHex {
+        for (addr, bytes) in load_ihex(text, bytes_per_word(memory.bits()))? {
+            let index = word_index(addr as u64, offset, memory.words())?;
+            memory.set(index, &from_bytes(&bytes, memory.bits()));
+        }
+    } else {
+        load_readmem(text, format == ImageFormat::Hex, offset, &mut memory)?;
+    }
+    Ok(memory)
+}
+
+fn load_raw(bytes: &[u8], layout: &PagedMemory) -> Result<PagedMemory, String> {
+    let mut memory = PagedMemory::new(layout.bits(), layout.words(), layout.fill().clone());
+    let size = bytes_per_word(memory.bits());
+    if bytes.len() as u64 > size as u64 * memory.words() {
+        return Err(format!("Image of {} bytes does not fit in {} words", bytes.len(), memory.words()));
+    }
+    for (index, chunk) in bytes.chunks(size).enumerate() {
+        memory.set(index as u32, &from_bytes(chunk, memory.bits()));
+    }
+    Ok(memory)
+}
+
+fn dump_text(format: ImageFormat, offset: u32, memory: &PagedMemory) -> String {
+    let defined = |word: &Vec3vl| (0..word.bits).any(|n| word.get(n) != 0);
+    if format == ImageFormat::IntelHex {
+        let size = bytes_per_word(memory.bits()) as u64;
+        let bytes = memory.allocated()
+            .filter(|(_, word)| defined(word))
+            .flat_map(|(index, word)| {
+                let base = (offset as u64 + index as u64) * size;
+                to_bytes(&word).into_iter().enumerate().map(move |(i, b)| (base + i as u64, b))
+            });
+        return dump_ihex(bytes);
+    }
+
+    let mut out = String::new();
+    let mut next = 0;
+    for (index, word) in memory.allocated().filter(|(_, word)| defined(word)) {
+        if index != next || (index == 0 && offset > 0) {
+            out.push_str(&format!("@{:x}\n", offset as u64 + index as u64));
+        }
+        next = index + 1;
+        out.push_str(&if format == ImageFormat::Hex { word.to_hex() } else { to_binary(&word) });
+        out.push('\n');
+    }
+    out
+}
+
+fn dump_raw(memory: &PagedMemory) -> Vec<u8> {
+    (0..memory.allocated_end())
+        .flat_map(|index| to_bytes(&memory.get(index as u32)))
+        .collect()
+}
+
+fn bytes_per_word(bits: u32) -> usize {
+    bits.div_ceil(8).max(1) as usize
+}
+
+fn word_index(addr: u64, offset: u32, words: u64) -> Result<u32, String> {
+    match addr.checked_sub(offset as u64) {
+        Some(index) if index < words => Ok(index as u32),
+        _ => Err(format!("Address {addr:x} is outside of the memory"))
+    }
+}
+
+fn from_bytes(bytes: &[u8], bits: u32) -> Vec3vl {
+    let mut words = vec![0u32; bits.div_ceil(32).max(1) as usize];
+    for (i, b) in bytes.iter().enumerate().take(words.len() * 4) {
+        words[i / 4] |= (*b as u32) << (8 * (i % 4));
+    }
+    let mut v = Vec3vl::new(bits, words.clone(), words);
+    v.normalize();
+    v
+}
+
+// Little-endian bytes of a word, undefined bits become 0.
+fn to_bytes(word: &Vec3vl) -> Vec<u8> {
+    (0..bytes_per_word(word.bits))
+        .map(|i| ((word.avec[i / 4] & word.bvec[i / 4]) >> (8 * (i % 4))) as u8)
+        .collect()
+}
+
+fn to_binary(word: &Vec3vl) -> String {
+    (0..word.bits).rev()
+        .map(|n| match word.get(n) {
+            1 => '1',
+            -1 => '0',
+            _ => 'x'
+        })
+        .collect()
+}
+
+fn strip_comments(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix("//") {
+            rest = r.find('\n').map_or("", |n| &r[n..]);
+        } else if let Some(r) = rest.strip_prefix("/*") {
+            rest = r.find("*/").map_or("", |n| &r[n + 2..]);
+            out.push(' ');
+        } else {
+            let c = rest.chars().next().unwrap();
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+fn load_readmem(text: &str, hex: bool, offset: u32, memory: &mut PagedMemory) -> Result<(), String> {
+    let mut addr = offset as u64;
+    for token in strip_comments(text).split_whitespace() {
+        if let Some(a) = token.strip_prefix('@') {
+            addr = u64::from_str_radix(a, 16).map_err(|_| format!("Invalid address {token}"))?;
+            continue;
+        }
+
+        let mut digits = String::new();
+        for c in token.chars().filter(|c| *c != '_') {
+            match c.to_ascii_lowercase() {
+                'x' | 'z' | '?' => digits.push_str(if hex { "xxxx" } else { "x" }),
+                c if hex && c.is_ascii_hexdigit() => digits.push_str(&format!("{:04b}", c.to_digit(16).unwrap())),
+                c @ ('0' | '1') if !hex => digits.push(c),
+                _ => return Err(format!("Invalid memory word {token}"))
+            }
+        }
+        if digits.is_empty() {
+            return Err(format!("Invalid memory word {token}"));
+        }
+
+        let mut word = Vec3vl::from_binary(digits, Some(memory.bits() as usize));
+        word.normalize();
+        memory.set(word_index(addr, offset, memory.words())?, &word);
+        addr += 1;
+    }
+    Ok(())
+}
+
+// Data bytes of an Intel HEX image grouped by word address.
+fn load_ihex(text: &str, size: usize) -> Result<BTreeMap<u32, Vec<u8>>, String> {
+    let mut words = BTreeMap::new();
+    let mut base = 0u32;
+    for (n, line) in text.lines().map(str::trim).enumerate().filter(|(_, l)| !l.is_empty()) {
+        let record = line.strip_prefix(':')
+            .filter(|r| r.len() % 2 == 0 && r.len() >= 10)
+            .and_then(|r| (0..r.len()).step_by(2).map(|i| u8::from_str_radix(&r[i..i + 2], 16).ok()).collect::<Option<Vec<u8>>>())
+            .ok_or(format!("Invalid Intel HEX record on line {}", n + 1))?;
+        if record.len() != record[0] as usize + 5 {
+            return Err(format!("Wrong Intel HEX record length on line {}", n + 1));
+        }
+        if record.iter().fold(0u8, |s, b| s.wrapping_add(*b)) != 0 {
+            return Err(format!("Wrong Intel HEX checksum on line {}", n + 1));
+        }
+
+        let addr = (record[1] as u32) << 8 | record[2] as u32;
+        let data = &record[4..record.len() - 1];
+        match record[3] {
+            0 => for (i, b) in data.iter().enumerate() {
+                let byte = base.wrapping_add(addr).wrapping_add(i as u32);
+                let word = words.entry(byte / size as u32).or_insert_with(|| vec![0; size]);
+                word[byte as usize % size] = *b;
+            },
+            1 => break,
+            2 if data.len() == 2 => base = ((data[0] as u32) << 8 | data[1] as u32) << 4,
+            4 if data.len() == 2 => base = ((data[0] as u32) << 8 | data[1] as u32) << 16,
+            3 | 5 => {},
+            t => return Err(format!("Unsupported Intel HEX record type {t} on line {}", n + 1))
+        }
+    }
+    Ok(words)
+}
+
+fn dump_ihex(bytes: impl Iterator<Item = (u64, u8)>) -> String {
+    let mut out = String::new();
+    let mut upper = 0;
+    let mut record: Vec<u8> = Vec::new();
+    let mut start = 0u64;
+
+    for (addr, b) in bytes {
+        let next = start + record.len() as u64;
+        if record.len() == 16 || addr != next || (!record.is_empty() && addr >> 16 != start >> 16) {
+            flush_ihex(&mut out, start, &mut record, &mut upper);
+        }
+        if record.is_empty() { start = addr; }
+        record.push(b);
+    }
+    flush_ihex(&mut out, start, &mut record, &mut upper);
+    push_ihex_record(&mut out, 0, 1, &[]);
+    out
+}
+
+// Writes buffered data bytes, preceded by an extended linear address record when needed.
+fn flush_ihex(out: &mut String, start: u64, record: &mut Vec<u8>, upper: &mut u64) {
+    if record.is_empty() { return; }
+    if start >> 16 != *upper {
+        *upper = start >> 16;
+        push_ihex_record(out, 0, 4, &[(*upper >> 8) as u8, *upper as u8]);
+    }
+    push_ihex_record(out, start as u16, 0, record);
+    record.clear();
+}
+
+fn push_ihex_record(out: &mut String, addr: u16, kind: u8, data: &[u8]) {
+    let mut bytes = vec![data.len() as u8, (addr >> 8) as u8, addr as u8, kind];
+    bytes.extend_from_slice(data);
+    let checksum = bytes.iter().fold(0u8, |s, b| s.wrapping_add(*b)).wrapping_neg();
+    bytes.push(checksum);
+
+    out.push(':');
+    for b in bytes {
+        out.push_str(&format!("{b:02X}"));
+    }
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory(bits: u32, words: u64) -> PagedMemory {
+        PagedMemory::new(bits, words, Vec3vl::xes(bits))
+    }
+
+    fn word(bits: u32, value: u32) -> Vec3vl {
+        Vec3vl::from_number(value, bits)
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let text = "@110\n12\nx4\n@200\nff\n";
+        let loaded = load_text(ImageFormat::Hex, text, 0x100, &memory(8, 1024)).unwrap();
+        assert!(loaded.get(0x10) == word(8, 0x12));
+        assert_eq!(to_binary(&loaded.get(0x11)), "xxxx0100");
+        assert_eq!(to_binary(&loaded.get(0x12)), "xxxxxxxx");
+        assert!(loaded.get(0x100) == word(8, 0xff));
+        assert_eq!(dump_text(ImageFormat::Hex, 0x100, &loaded), text);
+    }
+
+    #[test]
+    fn hex_without_addresses_starts_at_offset() {
+        let loaded = load_text(ImageFormat::Hex, "// comment\n1_2 /* skipped */ 34\n", 8, &memory(8, 16)).unwrap();
+        assert!(loaded.get(0) == word(8, 0x12));
+        assert!(loaded.get(1) == word(8, 0x34));
+        assert_eq!(dump_text(ImageFormat::Hex, 8, &loaded), "@8\n12\n34\n");
+    }
+
+    #[test]
+    fn bin_round_trip() {
+        let text = "@3\n1x01\n0000\n@a\n1111\n";
+        let loaded = load_text(ImageFormat::Bin, text, 0, &memory(4, 16)).unwrap();
+        assert_eq!(to_binary(&loaded.get(3)), "1x01");
+        assert!(loaded.get(4) == word(4, 0));
+        assert!(loaded.get(10) == word(4, 15));
+        assert_eq!(dump_text(ImageFormat::Bin, 0, &loaded), text);
+    }
+
+    #[test]
+    fn uncovered_words_keep_the_fill() {
+        let layout = PagedMemory::new(8, 1024, word(8, 0xa5));
+        let loaded = load_text(ImageFormat::Hex, "@300\n12\n", 0, &layout).unwrap();
+        assert!(loaded.get(0) == word(8, 0xa5));
+        assert!(loaded.get(0x300) == word(8, 0x12));
+        assert!(loaded.get(0x301) == word(8, 0xa5));
+        assert!(load_raw(&[1], &layout).unwrap().get(1) == word(8, 0xa5));
+    }
+
+    #[test]
+    fn addresses_outside_of_the_memory() {
+        assert!(load_text(ImageFormat::Hex, "@f\n00\n", 0x10, &memory(8, 16)).is_err());
+        assert!(load_text(ImageFormat::Hex, "@20\n00\n", 0x10, &memory(8, 16)).is_err());
+        assert!(load_text(ImageFormat::Bin, "0 1 2\n", 0, &memory(1, 16)).is_err());
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let loaded = load_raw(&[0x34, 0x12, 0xff, 0x0f], &memory(12, 1024)).unwrap();
+        assert!(loaded.get(0) == word(12, 0x234));
+        assert!(loaded.get(1) == word(12, 0xfff));
+
+        let mut partial = loaded.clone();
+        partial.set(2, &Vec3vl::from_binary("x1x1x1x1x1x1".to_string(), None));
+        let bytes = dump_raw(&partial);
+        assert_eq!(bytes.len(), 2 * 256);
+        assert_eq!(bytes[..6], [0x34, 0x02, 0xff, 0x0f, 0x55, 0x05]);
+
+        let reloaded = load_raw(&bytes, &memory(12, 1024)).unwrap();
+        assert!(reloaded.get(0) == loaded.get(0) && reloaded.get(1) == loaded.get(1));
+        assert!(load_raw(&bytes, &memory(12, 16)).is_err());
+    }
+
+    #[test]
+    fn ihex_round_trip() {
+        let mut mem = memory(16, 64);
+        mem.set(1, &word(16, 0xbeef));
+        mem.set(2, &word(16, 0x1234));
+        mem.set(40, &Vec3vl::from_binary("xxxxxxxx00000001".to_string(), None));
+
+        let text = dump_text(ImageFormat::IntelHex, 0x8000, &mem);
+        assert_eq!(text.lines().next(), Some(":020000040001F9"));
+        assert_eq!(text.lines().last(), Some(":00000001FF"));
+
+        let loaded = load_text(ImageFormat::IntelHex, &text, 0x8000, &memory(16, 64)).unwrap();
+        assert!(loaded.get(1) == word(16, 0xbeef));
+        assert!(loaded.get(2) == word(16, 0x1234));
+        assert!(loaded.get(40) == word(16, 1));
+        assert_eq!(to_binary(&loaded.get(3)), "x".repeat(16));
+        assert_eq!(dump_text(ImageFormat::IntelHex, 0x8000, &loaded), text);
+    }
+
+    #[test]
+    fn ihex_errors() {
+        assert!(load_text(ImageFormat::IntelHex, ":0100000001FF\n", 0, &memory(8, 16)).is_err());
+        assert!(load_text(ImageFormat::IntelHex, ":01001000AA45\n", 0, &memory(8, 16)).is_err());
+        assert!(load_text(ImageFormat::IntelHex, ":01000000AA55\n:00000001FF\n", 0, &memory(8, 16)).is_ok());
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::JsValue;
+
+use crate::mmioWrite;
+use crate::vector3vl::Vec3vl;
+
+// A peripheral serving a range of memory words. Reads happen on every evaluation
+// of a read port, so they must not have side effects.
+pub trait MmioDevice: Send {
+    fn read(&self, offset: u32) -> Vec3vl;
+    fn write(&mut self, offset: u32, value: &Vec3vl);
+    fn clone_device(&self) -> Box<dyn MmioDevice>;
+
+    // Output buffered for the host since the last call.
+    fn drain(&mut self) -> JsValue {
+        JsValue::UNDEFINED
+    }
+}
+
+// Device living in the host: writes are posted to its JS handler, reads return the
+// values last set by the host.
+#[derive(Clone)]
+pub struct HostDevice {
+    id: u32,
+    bits: u32,
+    values: HashMap<u32, Vec3vl>,
+    detached: bool
+}
+
+impl HostDevice {
+    pub fn new(id: u32, bits: u32) -> HostDevice {
+        HostDevice { id, bits, values: HashMap::new(), detached: false }
+    }
+
+    pub fn set_value(&mut self, offset: u32, value: Vec3vl) {
+        self.values.insert(offset, value);
+    }
+}
+
+impl MmioDevice for HostDevice {
+    fn read(&self, offset: u32) -> Vec3vl {
+        self.values.get(&offset).cloned().unwrap_or_else(|| Vec3vl::zeros(self.bits))
+    }
+
+    fn write(&mut self, offset: u32, value: &Vec3vl) {
+        if self.detached { return; }
+        mmioWrite(self.id, offset, value.bits, value.avec.clone(), value.bvec.clone());
+    }
+
+    fn clone_device(&self) -> Box<dyn MmioDevice> {
+        Box::new(self.clone())
+    }
+}
+
+// UART transmit register collecting the low byte of every write.
+#[derive(Clone)]
+pub struct ConsoleDevice {
+    bits: u32,
+    buffer: Vec<u8>
+}
+
+impl ConsoleDevice {
+    pub fn new(bits: u32) -> ConsoleDevice {
+        ConsoleDevice { bits, buffer: Vec::new() }
+    }
+}
+
+impl MmioDevice for ConsoleDevice {
+    fn read(&self, _offset: u32) -> Vec3vl {
+        Vec3vl::zeros(self.bits)
+    }
+
+    fn write(&mut self, _offset: u32, value: &Vec3vl) {
+        self.buffer.push((value.avec[0] & value.bvec[0]) as u8);
+    }
+
+    fn clone_device(&self) -> Box<dyn MmioDevice> {
+        Box::new(self.clone())
+    }
+
+    fn drain(&mut self) -> JsValue {
+        String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned().into()
+    }
+}
+
+pub enum MmioHandler {
+    Host(HostDevice),
+    Native(Box<dyn MmioDevice>)
+}
+
+impl Clone for MmioHandler {
+    fn clone(&self) -> MmioHandler {
+        match self {
+            MmioHandler::Host(device) => MmioHandler::Host(device.clone()),
+            MmioHandler::Native(device) => MmioHandler::Native(device.clone_device())
+        }
+    }
+}
+
+// Words `start..start + count` of a memory, counted from its first word.
+#[derive(Clone)]
+pub struct MmioRegion {
+    pub id: u32,
+    pub start: u32,
+    pub count: u32,
+    pub handler: MmioHandler
+}
+
+impl MmioRegion {
+    fn device(&self) -> &dyn MmioDevice {
+        match &self.handler {
+            MmioHandler::Host(device) => device,
+            MmioHandler::Native(device) => device.as_ref()
+        }
+    }
+
+    pub fn device_mut(&mut self) -> &mut dyn MmioDevice {
+        match &mut self.handler {
+            MmioHandler::Host(device) => device,
+            MmioHandler::Native(device) => device.as_mut()
+        }
+    }
+
+    // Copies in scratch engines keep their state but stop posting writes to the host.
+    pub fn detach(&mut self) {
+        if let MmioHandler::Host(device) = &mut self.handler {
+            device.detached = true;
+        }
+    }
+
+    fn offset(&self, addr: u32) -> Option<u32> {
+        addr.checked_sub(self.start).filter(|o| *o < self.count)
+    }
+}
+
+pub fn mmio_read(regions: &[MmioRegion], addr: u32) -> Option<Vec3vl> {
+    regions.iter().find_map(|r| r.offset(addr).map(|o| r.device().read(o)))
+}
+
+// Returns false when no region covers `addr`.
+pub fn mmio_write(regions: &mut [MmioRegion], addr: u32, value: &Vec3vl) -> bool {
+    match regions.iter_mut().find_map(|r| r.offset(addr).map(|o| (r, o))) {
+        Some((region, offset)) => {
+            region.device_mut().write(offset, value);
+            true
+        },
+        None => false
+    }
+}
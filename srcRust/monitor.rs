@@ -0,0 +1,174 @@
+use std::collections::{HashMap, VecDeque};
+
+use js_sys::{Object, Reflect, Uint32Array};
+use wasm_bindgen::JsValue;
+
+use crate::arena::GateId;
+use crate::js_types::{JsTrigger, JsVec3vl};
+use crate::vector3vl::Vec3vl;
+
+pub type PortRef = (GateId, String);
+
+#[derive(Clone)]
+pub enum Condition {
+    Values(PortRef, Vec<Vec3vl>),
+    Rising(PortRef),
+    Falling(PortRef),
+    BecomesX(PortRef),
+    Range(PortRef, Vec3vl, Vec3vl),
+    Mask(PortRef, Vec3vl),
+    Not(Box<Condition>),
+    And(Vec<Condition>),
+    Or(Vec<Condition>)
+}
+
+impl Condition {
+    pub fn from_js(trigger: &JsTrigger, resolve: &dyn Fn(&JsTrigger) -> Result<PortRef, String>) -> Result<Condition, String> {
+        let operands = |trigger: &JsTrigger| -> Result<Vec<Condition>, String> {
+            match trigger.get_operands() {
+                Some(v) => v.iter().map(|t| Condition::from_js(t, resolve)).collect(),
+                None => Err("Trigger is missing its operands".to_string())
+            }
+        };
+        let value = |v: Option<JsVec3vl>, name: &str| -> Result<Vec3vl, String> {
+            match v {
+                Some(v) => Ok(Vec3vl::from_clonable(v)),
+                None => Err(format!("Trigger is missing {name}"))
+            }
+        };
+
+        let kind = trigger.get_type();
+        Ok(match kind.as_str() {
+            "values"    => Condition::Values(resolve(trigger)?, trigger.get_values()
+                .map(|v| v.into_iter().map(Vec3vl::from_clonable).collect())
+                .unwrap_or_default()),
+            "rising"    => Condition::Rising(resolve(trigger)?),
+            "falling"   => Condition::Falling(resolve(trigger)?),
+            "becomesX"  => Condition::BecomesX(resolve(trigger)?),
+            "range"     => Condition::Range(resolve(trigger)?, value(trigger.get_min(), "min")?, value(trigger.get_max(), "max")?),
+            "mask"      => Condition::Mask(resolve(trigger)?, value(trigger.get_pattern(), "pattern")?),
+            "not"       => match operands(trigger)?.pop() {
+                Some(c) => Condition::Not(Box::new(c)),
+                None => return Err("Trigger not needs an operand".to_string())
+            },
+            "and"       => Condition::And(operands(trigger)?),
+            "or"        => Condition::Or(operands(trigger)?),
+            _ => return Err(format!("Unknown trigger type {kind}"))
+        })
+    }
+
+    pub fn ports(&self, out: &mut Vec<PortRef>) {
+        match self {
+            Condition::Values(p, _) |
+            Condition::Rising(p) |
+            Condition::Falling(p) |
+            Condition::BecomesX(p) |
+            Condition::Range(p, _, _) |
+            Condition::Mask(p, _) => {
+                if !out.contains(p) { out.push(p.clone()); }
+            },
+            Condition::Not(c) => c.ports(out),
+            Condition::And(cs) | Condition::Or(cs) => cs.iter().for_each(|c| c.ports(out))
+        }
+    }
+
+    pub fn eval(&self, current: &HashMap<PortRef, Vec3vl>, previous: &HashMap<PortRef, Vec3vl>) -> bool {
+        let cur = |p: &PortRef| current.get(p);
+        let prev = |p: &PortRef| previous.get(p);
+
+        match self {
+            Condition::Values(p, values) => cur(p).is_some_and(|v| values.contains(v)),
+            Condition::Rising(p) => matches!((prev(p), cur(p)), (Some(a), Some(b)) if a.lsb() == -1 && b.lsb() == 1),
+            Condition::Falling(p) => matches!((prev(p), cur(p)), (Some(a), Some(b)) if a.lsb() == 1 && b.lsb() == -1),
+            Condition::BecomesX(p) => matches!((prev(p), cur(p)), (Some(a), Some(b)) if a.is_fully_defined() && !b.is_fully_defined()),
+            Condition::Range(p, min, max) => match cur(p).map(|v| v.to_biguint()) {
+                Some(Ok(v)) => match (min.to_biguint(), max.to_biguint()) {
+                    (Ok(min), Ok(max)) => min <= v && v <= max,
+                    _ => false
+                },
+                _ => false
+            },
+            Condition::Mask(p, pattern) => match cur(p) {
+                Some(v) => {
+                    let xmask = pattern.xmask();
+                    matches!((v.or(&xmask), pattern.or(&xmask)), (Ok(a), Ok(b)) if a == b)
+                },
+                None => false
+            },
+            Condition::Not(c) => !c.eval(current, previous),
+            Condition::And(cs) => cs.iter().all(|c| c.eval(current, previous)),
+            Condition::Or(cs) => cs.iter().any(|c| c.eval(current, previous))
+        }
+    }
+}
+
+// Ring buffer of (tick, value) samples kept for a monitor instead of posting
+// every change. Only every `decimation`-th sample is stored; once `capacity`
+// is reached the oldest samples are dropped.
+pub struct History {
+    capacity: usize,
+    decimation: u32,
+    counter: u32,
+    bits: u32,
+    ticks: VecDeque<u32>,
+    avec: VecDeque<u32>,
+    bvec: VecDeque<u32>,
+    dropped: u32
+}
+
+impl History {
+    pub fn new(capacity: u32, decimation: u32, bits: u32) -> History {
+        History {
+            capacity: capacity.max(1) as usize,
+            decimation: decimation.max(1),
+            counter: 0,
+            bits,
+            ticks: VecDeque::new(),
+            avec: VecDeque::new(),
+            bvec: VecDeque::new(),
+            dropped: 0
+        }
+    }
+
+    pub fn push(&mut self, tick: u32, sig: &Vec3vl) {
+        let record = self.counter == 0;
+        self.counter = (self.counter + 1) % self.decimation;
+        if !record { return; }
+
+        let words = self.words();
+        if self.ticks.len() == self.capacity {
+            self.ticks.pop_front();
+            self.avec.drain(..words);
+            self.bvec.drain(..words);
+            self.dropped += 1;
+        }
+
+        let mut sig = sig.clone();
+        sig.normalize();
+        sig.avec.resize(words, 0);
+        sig.bvec.resize(words, 0);
+
+        self.ticks.push_back(tick);
+        self.avec.extend(sig.avec);
+        self.bvec.extend(sig.bvec);
+    }
+
+    // Returns `{ bits, ticks, avec, bvec, dropped }` with the values packed
+    // `ceil(bits / 32)` words per sample, and empties the buffer.
+    pub fn drain(&mut self) -> JsValue {
+        let pack = |v: &mut VecDeque<u32>| Uint32Array::from(&v.drain(..).collect::<Vec<u32>>()[..]);
+
+        let obj = Object::new();
+        let _ = Reflect::set(&obj, &"bits".into(), &self.bits.into());
+        let _ = Reflect::set(&obj, &"ticks".into(), &pack(&mut self.ticks).into());
+        let _ = Reflect::set(&obj, &"avec".into(), &pack(&mut self.avec).into());
+        let _ = Reflect::set(&obj, &"bvec".into(), &pack(&mut self.bvec).into());
+        let _ = Reflect::set(&obj, &"dropped".into(), &self.dropped.into());
+        self.dropped = 0;
+        obj.into()
+    }
+
+    fn words(&self) -> usize {
+        self.bits.div_ceil(32).max(1) as usize
+    }
+}
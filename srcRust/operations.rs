@@ -8,12 +8,15 @@ use crate::cell_fsm::{fsm, FsmState};
 use crate::cell_io::{clock, constant};
 use crate::cell_memory::{memory_op, MemoryState};
 use crate::cell_mux::{mux1hot_idx, mux_idx, mux_op, sparse_mux_op, MuxIdx};
+use crate::cell_rom::{rom_op, RomState};
+use crate::cell_yosys::{bmux, bweqx, bwmux, demux, lut, param_bits, param_num, pmux, shift, sop, ShiftParams};
 use crate::gate::SliceOptions;
 use crate::js_types::JsGateParams;
 use crate::vector3vl::Vec3vl;
 
 use crate::cell_gates::{gate_11, gate_reduce, gate_x1, Binop, Monop, ReduceFn};
 
+#[derive(Clone)]
 pub enum Operation {
     Arith11(ArithMonop, u32),
     Arith21(ArithBinop, u32, (bool, bool)),
@@ -34,6 +37,15 @@ pub enum Operation {
     Mux(u32, MuxIdx),
     MuxSparse(u32, HashMap<String, String>),
     Memory(MemoryState),
+    Rom(RomState),
+    Pmux(u32, u32),
+    Bmux(u32),
+    Demux(u32, u32),
+    Shift(ShiftParams),
+    Lut(Vec3vl),
+    Sop(u32, u32, Vec3vl),
+    BwEqx,
+    BwMux,
     None
 }
 
@@ -109,7 +121,19 @@ impl Operation {
             "Mux1Hot"   => create_multiplexer(gate_params, mux1hot_idx),
             "MuxSparse" => create_mux_sparse(gate_params),
 
+            "Memory" if RomState::is_rom(&gate_params) => Operation::Rom(RomState::new(gate_params)),
             "Memory"    => Operation::Memory(MemoryState::new(gate_params, graph_id, gate_id)),
+
+            "Pmux"      => create_yosys_mux(gate_params, Operation::Pmux),
+            "Demux"     => create_yosys_mux(gate_params, Operation::Demux),
+            "Bmux"      => create_yosys_mux(gate_params, |width, _| Operation::Bmux(width)),
+            "Shift"     => create_yosys_shift(gate_params, false),
+            "ShiftX"    => create_yosys_shift(gate_params, true),
+            "Lut"       => create_yosys_lut(gate_params),
+            "Sop"       => create_yosys_sop(gate_params),
+            "BwEqx"     => Operation::BwEqx,
+            "BwMux"     => Operation::BwMux,
+
             "NumEntry" | 
             "NumDisplay" | 
             "Button" |
@@ -142,10 +166,78 @@ impl Operation {
             Operation::Mux(bits, op) => mux_op(args, *bits, op),
             Operation::MuxSparse(bits, map) => sparse_mux_op(args, *bits, map),
             Operation::Memory(state) => memory_op(args, state),
+            Operation::Rom(state) => rom_op(args, state),
+            Operation::Pmux(width, s_width) => pmux(args, *width, *s_width),
+            Operation::Bmux(width) => bmux(args, *width),
+            Operation::Demux(width, s_width) => demux(args, *width, *s_width),
+            Operation::Shift(params) => shift(args, params),
+            Operation::Lut(table) => lut(args, table),
+            Operation::Sop(width, depth, table) => sop(args, *width, *depth, table),
+            Operation::BwEqx => bweqx(args),
+            Operation::BwMux => bwmux(args),
             Operation::None => ReturnValue::values(None, HashMap::new())
         }
     }
 
+    pub fn set_graph_id(&mut self, graph_id: String) {
+        match self {
+            Operation::Fsm(state) => state.set_graph_id(graph_id),
+            Operation::Memory(state) => state.graph_id = graph_id,
+            _ => {}
+        }
+    }
+
+    // Keeps the cell from notifying the host, for copies in scratch engines.
+    pub fn detach_host(&mut self) {
+        match self {
+            Operation::Fsm(state) => state.detach(),
+            Operation::Memory(state) => state.detach(),
+            _ => {}
+        }
+    }
+
+    pub fn is_sequential(&self) -> bool {
+        matches!(self, Operation::Dff(_) | Operation::Fsm(_) | Operation::Memory(_))
+    }
+
+    pub fn has_host_effects(&self) -> bool {
+        matches!(self, Operation::Fsm(_) | Operation::Memory(_))
+    }
+
+    // Outputs of a sequential cell that follow an input without waiting for a clock edge.
+    pub fn async_outputs(&self, port: &str) -> Vec<String> {
+        match self {
+            Operation::Memory(memory_state) => memory_state.async_read_outputs(port),
+            _ => Vec::new()
+        }
+    }
+
+    // Relative switched capacitance per toggled output bit, used by the power estimate.
+    // Bus and IO cells are plain wiring and cost nothing.
+    pub fn default_capacitance(&self) -> f64 {
+        match self {
+            Operation::Gate11(_) => 1.0,
+            Operation::GateX1(_) | Operation::GateReduce(_) => 1.5,
+            Operation::Lut(_) | Operation::Sop(_, _, _) | Operation::BwEqx => 1.5,
+            Operation::Comp(_, _) | Operation::CompConst(_, _, _, _) => 2.0,
+            Operation::Mux(_, _) | Operation::MuxSparse(_, _) => 2.0,
+            Operation::Pmux(_, _) | Operation::Bmux(_) | Operation::Demux(_, _) | Operation::BwMux => 2.0,
+            Operation::Shift(_) => 2.0,
+            Operation::Arith11(_, _) => 2.0,
+            Operation::Arith21(_, _, _) | Operation::ArithConst(_, _, _, _, _) => 4.0,
+            Operation::Dff(_) => 3.0,
+            Operation::Fsm(_) => 4.0,
+            Operation::Memory(_) | Operation::Rom(_) => 6.0,
+            Operation::Clock(_) => 1.0,
+            Operation::BitExtend(_, _) |
+            Operation::BusGroup |
+            Operation::BusUngroup(_) |
+            Operation::BusSlice(_) |
+            Operation::Constant(_) |
+            Operation::None => 0.0
+        }
+    }
+
     pub fn get_type(&self) -> String {
         match self {
             Operation::Arith11(_, _)             => "Arith11",
@@ -167,6 +259,16 @@ impl Operation {
             Operation::Mux(_, _)                 => "Mux",
             Operation::MuxSparse(_, _)           => "MuxSparse",
             Operation::Memory(_)                 => "Memory",
+            Operation::Rom(_)                    => "Rom",
+            Operation::Pmux(_, _)                => "Pmux",
+            Operation::Bmux(_)                   => "Bmux",
+            Operation::Demux(_, _)               => "Demux",
+            Operation::Shift(p) if p.fill_x      => "ShiftX",
+            Operation::Shift(_)                  => "Shift",
+            Operation::Lut(_)                    => "Lut",
+            Operation::Sop(_, _, _)              => "Sop",
+            Operation::BwEqx                     => "BwEqx",
+            Operation::BwMux                     => "BwMux",
             Operation::None                      => "None",
         }.to_string()
     }
@@ -302,6 +404,36 @@ fn create_multiplexer(gate_params: JsGateParams, operation: MuxIdx) -> Operation
     Operation::Mux(bits, operation)
 }
 
+fn create_yosys_mux(gate_params: JsGateParams, make: fn(u32, u32) -> Operation) -> Operation {
+    let params = gate_params.get_parameters();
+    let width = param_num(params.as_ref().map(|p| p.get_width()), 1);
+    let s_width = param_num(params.as_ref().map(|p| p.get_s_width()), 1);
+    make(width, s_width)
+}
+
+fn create_yosys_shift(gate_params: JsGateParams, fill_x: bool) -> Operation {
+    let params = gate_params.get_parameters();
+    Operation::Shift(ShiftParams {
+        a_signed: param_num(params.as_ref().map(|p| p.get_a_signed()), 0) != 0,
+        b_signed: param_num(params.as_ref().map(|p| p.get_b_signed()), 0) != 0,
+        y_width: param_num(params.as_ref().map(|p| p.get_y_width()), 1),
+        fill_x
+    })
+}
+
+fn create_yosys_lut(gate_params: JsGateParams) -> Operation {
+    let params = gate_params.get_parameters();
+    let width = param_num(params.as_ref().map(|p| p.get_width()), 1);
+    Operation::Lut(param_bits(params.map(|p| p.get_lut()), 1 << width))
+}
+
+fn create_yosys_sop(gate_params: JsGateParams) -> Operation {
+    let params = gate_params.get_parameters();
+    let width = param_num(params.as_ref().map(|p| p.get_width()), 1);
+    let depth = param_num(params.as_ref().map(|p| p.get_depth()), 1);
+    Operation::Sop(width, depth, param_bits(params.map(|p| p.get_table()), 2 * width * depth))
+}
+
 fn create_mux_sparse(gate_params: JsGateParams) -> Operation {
     let bits = match gate_params.get_bits_struct() {
         Some(s) => s.get_bits_in(),
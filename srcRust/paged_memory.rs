@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use crate::vector3vl::Vec3vl;
+
+const PAGE_BITS: u32 = 8;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+// Memory contents stored in pages of packed words, allocated on the first write
+// that differs from the fill value. Unallocated words read as `fill`.
+#[derive(Clone)]
+pub struct PagedMemory {
+    bits: u32,
+    stride: usize,
+    words: u64,
+    fill: Vec3vl,
+    pages: BTreeMap<u32, Page>
+}
+
+#[derive(Clone)]
+struct Page {
+    avec: Vec<u32>,
+    bvec: Vec<u32>
+}
+
+impl PagedMemory {
+    pub fn new(bits: u32, words: u64, fill: Vec3vl) -> PagedMemory {
+        let stride = bits.div_ceil(32).max(1) as usize;
+        PagedMemory { bits, stride, words, fill, pages: BTreeMap::new() }
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn fill(&self) -> &Vec3vl {
+        &self.fill
+    }
+
+    pub fn words(&self) -> u64 {
+        self.words
+    }
+
+    pub fn get(&self, addr: u32) -> Vec3vl {
+        match self.pages.get(&(addr >> PAGE_BITS)) {
+            Some(page) => {
+                let start = (addr as usize % PAGE_SIZE) * self.stride;
+                Vec3vl::new(self.bits, page.avec[start..start + self.stride].to_vec(), page.bvec[start..start + self.stride].to_vec())
+            },
+            None => self.fill.clone()
+        }
+    }
+
+    pub fn set(&mut self, addr: u32, value: &Vec3vl) {
+        if addr as u64 >= self.words { return; }
+        let key = addr >> PAGE_BITS;
+        if !self.pages.contains_key(&key) && *value == self.fill { return; }
+
+        let (stride, fill) = (self.stride, &self.fill);
+        let page = self.pages.entry(key).or_insert_with(|| Page {
+            avec: fill.avec.iter().copied().cycle().take(stride * PAGE_SIZE).collect(),
+            bvec: fill.bvec.iter().copied().cycle().take(stride * PAGE_SIZE).collect()
+        });
+        let start = (addr as usize % PAGE_SIZE) * stride;
+        for i in 0..stride {
+            page.avec[start + i] = value.avec.get(i).copied().unwrap_or(0);
+            page.bvec[start + i] = value.bvec.get(i).copied().unwrap_or(0);
+        }
+    }
+
+    // Addresses and values of all words in allocated pages, in address order.
+    pub fn allocated(&self) -> impl Iterator<Item = (u32, Vec3vl)> + '_ {
+        self.pages.keys()
+            .flat_map(|key| (0..PAGE_SIZE as u64).map(move |i| ((*key as u64) << PAGE_BITS) + i))
+            .take_while(|addr| *addr < self.words)
+            .map(|addr| (addr as u32, self.get(addr as u32)))
+    }
+
+    // One past the last allocated word.
+    pub fn allocated_end(&self) -> u64 {
+        self.pages.keys().next_back()
+            .map_or(0, |key| ((*key as u64 + 1) << PAGE_BITS).min(self.words))
+    }
+}
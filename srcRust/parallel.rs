@@ -0,0 +1,109 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+use rayon::prelude::*;
+use rayon::ThreadBuilder;
+use wasm_bindgen::prelude::*;
+
+// In the browser rayon threads run in web workers sharing the module's memory. The JS side
+// creates the workers with `receiver()`, waits for them to load the module and then calls
+// `build()`; each worker picks up one rayon thread in `startRayonWorker`.
+#[wasm_bindgen(js_name = ThreadPoolBuilder)]
+pub struct PoolBuilder {
+    num_threads: usize,
+    sender: Sender<ThreadBuilder>,
+    receiver: &'static Mutex<Receiver<ThreadBuilder>>
+}
+
+#[wasm_bindgen(js_class = ThreadPoolBuilder)]
+impl PoolBuilder {
+    #[wasm_bindgen(constructor)]
+    pub fn new(num_threads: usize) -> PoolBuilder {
+        let (sender, receiver) = channel();
+        PoolBuilder { num_threads, sender, receiver: Box::leak(Box::new(Mutex::new(receiver))) }
+    }
+
+    #[wasm_bindgen(js_name = numThreads)]
+    pub fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    pub fn receiver(&self) -> usize {
+        self.receiver as *const Mutex<Receiver<ThreadBuilder>> as usize
+    }
+
+    pub fn build(self) -> Result<(), String> {
+        let sender = self.sender;
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(self.num_threads)
+            .spawn_handler(move |thread| sender.send(thread).map_err(|e| std::io::Error::other(e.to_string())))
+            .build_global()
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[wasm_bindgen(js_name = startRayonWorker)]
+pub fn start_worker(receiver: usize) -> Result<(), String> {
+    let receiver = unsafe { &*(receiver as *const Mutex<Receiver<ThreadBuilder>>) };
+    let thread = receiver.lock().map_err(|e| e.to_string())?.recv().map_err(|e| e.to_string())?;
+    thread.run();
+    Ok(())
+}
+
+// Jobs with host effects call back into JS, so they run on the calling thread after the
+// others have been evaluated by the pool.
+pub fn evaluate<T, R>(jobs: Vec<T>, has_host_effects: impl Fn(&T) -> bool,
+                      eval: impl Fn(T) -> Result<R, String> + Sync + Send) -> Result<Vec<R>, String>
+    where T: Send, R: Send
+{
+    let (host, pure): (Vec<T>, Vec<T>) = jobs.into_iter().partition(has_host_effects);
+
+    let mut results = pure.into_par_iter()
+        .map(&eval)
+        .collect::<Result<Vec<R>, String>>()?;
+
+    for job in host {
+        results.push(eval(job)?);
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool() -> rayon::ThreadPool {
+        rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap()
+    }
+
+    #[test]
+    fn evaluates_pure_jobs_on_the_pool() {
+        let results = pool().install(|| {
+            evaluate((0..256u32).collect(), |_| false, |n| Ok((n, rayon::current_thread_index())))
+        }).unwrap();
+
+        let mut values = results.iter().map(|(n, _)| *n).collect::<Vec<u32>>();
+        values.sort();
+        assert_eq!(values, (0..256).collect::<Vec<u32>>());
+        assert!(results.iter().all(|(_, thread)| thread.is_some()));
+    }
+
+    #[test]
+    fn evaluates_host_jobs_on_the_calling_thread() {
+        let caller = std::thread::current().id();
+        let results = evaluate((0..128u32).collect(), |n| n % 2 == 0, |n| Ok((n, std::thread::current().id()))).unwrap();
+
+        assert_eq!(results.len(), 128);
+        assert!(results[..64].iter().all(|(n, _)| n % 2 == 1));
+        assert!(results[64..].iter().all(|(n, thread)| n % 2 == 0 && *thread == caller));
+    }
+
+    #[test]
+    fn reports_errors() {
+        let result = pool().install(|| {
+            evaluate((0..100u32).collect(), |_| false, |n| if n == 42 { Err("failed".to_string()) } else { Ok(n) })
+        });
+        assert_eq!(result, Err("failed".to_string()));
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use js_sys::{Object, Reflect};
+use wasm_bindgen::JsValue;
+
+use crate::arena::{Arena, GateId, GraphId};
+use crate::gate::Gate;
+use crate::graph::Graph;
+use crate::vector3vl::Vec3vl;
+
+// Switching activity of gate outputs, collected between startPowerEstimate and
+// stopPowerEstimate. Energy is relative: toggled bits times the capacitance
+// weight of the cell type.
+pub struct Power {
+    start: u32,
+    toggles: HashMap<GateId, u64>
+}
+
+impl Power {
+    pub fn new(tick: u32) -> Power {
+        Power { start: tick, toggles: HashMap::new() }
+    }
+
+    pub fn record(&mut self, gate: GateId, old: &Vec3vl, new: &Vec3vl) {
+        let (mut old, mut new) = (old.clone(), new.clone());
+        old.normalize();
+        new.normalize();
+
+        let changed = old.avec.iter().zip(&old.bvec)
+            .zip(new.avec.iter().zip(&new.bvec))
+            .map(|((a0, b0), (a1, b1))| ((a0 ^ a1) | (b0 ^ b1)).count_ones() as u64)
+            .sum::<u64>();
+        *self.toggles.entry(gate).or_default() += changed;
+    }
+
+    // Returns `{ toggles, energy, power, subcircuits: { path: report } }` for the graph,
+    // where the totals include everything below it, subcircuits are keyed by their dotted
+    // path from the graph and `power` is energy per tick.
+    pub fn report(&self, gates: &Arena<Gate>, graphs: &Arena<Graph>, graph: GraphId, weights: &HashMap<String, f64>, tick: u32) -> JsValue {
+        let ticks = tick.wrapping_sub(self.start).max(1) as f64;
+        let mut paths = Vec::new();
+        let (toggles, energy) = self.report_graph(gates, graphs, graph, weights, "", &mut paths);
+
+        let subcircuits = Object::new();
+        for (path, t, e) in paths {
+            let _ = Reflect::set(&subcircuits, &path.into(), &summary(t, e, ticks));
+        }
+        let obj = summary(toggles, energy, ticks);
+        let _ = Reflect::set(&obj, &"subcircuits".into(), &subcircuits);
+        obj.into()
+    }
+
+    fn report_graph(&self, gates: &Arena<Gate>, graphs: &Arena<Graph>, graph: GraphId, weights: &HashMap<String, f64>,
+                    prefix: &str, paths: &mut Vec<(String, u64, f64)>) -> (u64, f64) {
+        let mut toggles = 0;
+        let mut energy = 0.0;
+
+        let graph = match graphs.get(graph) {
+            Some(g) => g,
+            None => return (toggles, energy)
+        };
+        let nested = graph.subcircuit().is_some();
+
+        for id in graph.gate_iter() {
+            let gate = match gates.get(*id) {
+                Some(g) => g,
+                None => continue
+            };
+
+            if let Ok(sub) = gate.get_subgraph() {
+                let path = format!("{prefix}{}", gate.get_name());
+                let (t, e) = self.report_graph(gates, graphs, sub, weights, &format!("{path}."), paths);
+                toggles += t;
+                energy += e;
+                paths.push((path, t, e));
+                continue;
+            }
+
+            // The I/O cells of a subcircuit repeat signals already driven on the other side.
+            if nested && (gate.is_io_input() || gate.is_io_output()) {
+                continue;
+            }
+
+            let count = self.toggles.get(id).copied().unwrap_or(0);
+            let weight = weights.get(&gate.get_cell_type()).copied().unwrap_or_else(|| gate.default_capacitance());
+            toggles += count;
+            energy += count as f64 * weight;
+        }
+
+        (toggles, energy)
+    }
+}
+
+fn summary(toggles: u64, energy: f64, ticks: f64) -> Object {
+    let obj = Object::new();
+    let _ = Reflect::set(&obj, &"toggles".into(), &(toggles as f64).into());
+    let _ = Reflect::set(&obj, &"energy".into(), &energy.into());
+    let _ = Reflect::set(&obj, &"power".into(), &(energy / ticks).into());
+    obj
+}
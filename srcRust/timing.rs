@@ -0,0 +1,162 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::arena::{Arena, GateId, GraphId};
+use crate::gate::{Gate, IoDir};
+use crate::graph::Graph;
+use crate::monitor::PortRef;
+
+// One step of the critical path: a gate port and the tick at which its value settles.
+pub struct PathStep {
+    pub port: PortRef,
+    pub arrival: u32
+}
+
+pub struct TimingReport {
+    pub delay: u32,
+    pub path: Vec<PathStep>
+}
+
+struct TimingGraph {
+    edges: HashMap<PortRef, Vec<(PortRef, u32)>>,
+    // Data inputs of sequential elements and top-level outputs, keyed by their driver.
+    endpoints: HashMap<PortRef, Vec<PortRef>>,
+    in_degree: HashMap<PortRef, u32>
+}
+
+// Longest combinational path between sequential elements (Dff, Memory, FSM),
+// sources and outputs of `root`, with subcircuits flattened. Paths start at
+// the clock-to-output delay of a sequential element (or 0 at a source) and
+// add the slowest output delay of every combinational gate on the way. Only
+// clocked inputs of sequential elements are timing endpoints: asynchronous
+// memory reads pass through, and clock inputs end no path.
+pub fn analyze(gates: &Arena<Gate>, graphs: &Arena<Graph>, root: GraphId) -> Result<Option<TimingReport>, String> {
+    let mut tg = TimingGraph { edges: HashMap::new(), endpoints: HashMap::new(), in_degree: HashMap::new() };
+    collect(gates, graphs, root, &mut tg)?;
+
+    let mut arrival = HashMap::new();
+    let mut from: HashMap<PortRef, PortRef> = HashMap::new();
+    let mut ready = VecDeque::new();
+    for (node, degree) in &tg.in_degree {
+        if *degree == 0 {
+            let gate = gate_ref(gates, node.0)?;
+            arrival.insert(node.clone(), if gate.is_sequential() { gate.get_max_output_delay(&node.1) } else { 0 });
+            ready.push_back(node.clone());
+        }
+    }
+
+    let mut in_degree = tg.in_degree.clone();
+    let mut visited = 0;
+    while let Some(node) = ready.pop_front() {
+        visited += 1;
+        let t = arrival[&node];
+        for (next, delay) in tg.edges.get(&node).into_iter().flatten() {
+            if arrival.get(next).is_none_or(|a| *a < t + delay) {
+                arrival.insert(next.clone(), t + delay);
+                from.insert(next.clone(), node.clone());
+            }
+            let d = in_degree.get_mut(next).unwrap();
+            *d -= 1;
+            if *d == 0 { ready.push_back(next.clone()); }
+        }
+    }
+
+    if visited < tg.in_degree.len() {
+        let (gate_id, _) = in_degree.iter().find(|(_, d)| **d > 0).unwrap().0;
+        return Err(format!("Combinational loop through gate {}", gate_ref(gates, *gate_id)?.get_id()));
+    }
+
+    let critical = tg.endpoints.iter()
+        .flat_map(|(driver, ends)| ends.iter().map(move |end| (driver, end)))
+        .max_by_key(|(driver, _)| arrival[*driver]);
+
+    let (driver, end) = match critical {
+        Some(c) => c,
+        None => return Ok(None)
+    };
+
+    let delay = arrival[driver];
+    let mut path = vec![PathStep { port: end.clone(), arrival: delay }];
+    let mut node = Some(driver.clone());
+    while let Some(n) = node {
+        path.push(PathStep { port: n.clone(), arrival: arrival[&n] });
+        node = from.get(&n).cloned();
+    }
+    path.reverse();
+
+    Ok(Some(TimingReport { delay, path }))
+}
+
+fn collect(gates: &Arena<Gate>, graphs: &Arena<Graph>, graph_id: GraphId, tg: &mut TimingGraph) -> Result<(), String> {
+    let graph = match graphs.get(graph_id) {
+        Some(g) => g,
+        None => return Err(format!("No graph with handle {graph_id:?}"))
+    };
+
+    for gate_id in graph.gate_iter() {
+        let gate = match gates.get(*gate_id) {
+            Some(g) => g,
+            None => continue
+        };
+        if let Ok(sub) = gate.get_subgraph() {
+            collect(gates, graphs, sub, tg)?;
+        }
+
+        for (port, dir) in gate.iodirs_iter() {
+            if *dir != IoDir::Out { continue; }
+            let node = (*gate_id, port.clone());
+            tg.in_degree.entry(node.clone()).or_insert(0);
+
+            for target in gate.get_targets(port)? {
+                let target_id = graph.get_gate(&target.id)?;
+                let target_gate = gate_ref(gates, target_id)?;
+
+                let mut next = Vec::new();
+                if target_gate.is_subcircuit() {
+                    let sub = target_gate.get_subgraph()?;
+                    let io = graph_ref(graphs, sub)?.get_gate(&target_gate.get_subgraph_iomap_port(&target.port)?)?;
+                    next.push(((io, "out".to_string()), 0));
+                } else if target_gate.is_output() {
+                    match graph.subcircuit() {
+                        Some(sub) => next.push(((sub, target_gate.get_subcir_net()?), 0)),
+                        None => tg.endpoints.entry(node.clone()).or_default().push((target_id, target.port.clone()))
+                    }
+                } else if target_gate.is_sequential() {
+                    let outs = target_gate.async_outputs(&target.port);
+                    if outs.is_empty() && !target.port.ends_with("clk") {
+                        tg.endpoints.entry(node.clone()).or_default().push((target_id, target.port.clone()));
+                    }
+                    for out in outs {
+                        let delay = target_gate.get_max_output_delay(&out);
+                        next.push(((target_id, out), delay));
+                    }
+                } else {
+                    for (out, dir) in target_gate.iodirs_iter() {
+                        if *dir == IoDir::Out {
+                            next.push(((target_id, out.clone()), target_gate.get_max_output_delay(out)));
+                        }
+                    }
+                }
+
+                for (n, delay) in next {
+                    *tg.in_degree.entry(n.clone()).or_insert(0) += 1;
+                    tg.edges.entry(node.clone()).or_default().push((n, delay));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn gate_ref(gates: &Arena<Gate>, id: GateId) -> Result<&Gate, String> {
+    match gates.get(id) {
+        Some(g) => Ok(g),
+        None => Err(format!("No gate with handle {id:?}"))
+    }
+}
+
+fn graph_ref(graphs: &Arena<Graph>, id: GraphId) -> Result<&Graph, String> {
+    match graphs.get(id) {
+        Some(g) => Ok(g),
+        None => Err(format!("No graph with handle {id:?}"))
+    }
+}
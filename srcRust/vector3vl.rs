@@ -1,6 +1,9 @@
 use std::cmp::Ordering;
 use std::ops::{BitAndAssign, BitOrAssign};
 
+use js_sys::{Object, Reflect, Uint32Array};
+use wasm_bindgen::JsValue;
+
 use crate::cell_arith::{extend_number, BigInt, BigUInt};
 use crate::js_types::JsVec3vl;
 
@@ -182,8 +185,8 @@ impl Vec3vl {
             let mut avec = vec![0; words];
             let mut bvec = vec![0; words];
             let mut k = 0usize;
-            avec[k] = self.avec[start >> 5] >> start;
-            bvec[k] = self.bvec[start >> 5] >> start;
+            avec[k] = self.avec[start >> 5] >> (start % 32);
+            bvec[k] = self.bvec[start >> 5] >> (start % 32);
 
             let mut i = (start >> 5) + 1;
             while i <= (end >> 5) {
@@ -209,7 +212,7 @@ impl Vec3vl {
         self.get(0)
     }
 
-    fn get(&self, n: u32) -> i32 {
+    pub fn get(&self, n: u32) -> i32 {
         let bn = bitnum(n) as usize;
         let wn = wordnum(n) as usize;
         let a = (self.avec[wn] >> bn) & 1;
@@ -392,13 +395,13 @@ impl Vec3vl {
         self.reduce_xor().not()
     }
 
-    pub fn to_hex(&mut self) -> String {
-        self.normalize();
-        Vec3vl::to_hex_internal(0, self.bits, &self.avec, &self.bvec)
+    pub fn to_hex(&self) -> String {
+        let mut v = self.clone();
+        v.normalize();
+        Vec3vl::to_hex_internal(0, v.bits, &v.avec, &v.bvec)
     }
 
-    pub fn to_array(&mut self) -> Vec<i32> {
-        self.normalize();
+    pub fn to_array(&self) -> Vec<i32> {
         let skip = 1;
         let sm = (1 << skip) - 1;
         let mut bit = 0;
@@ -513,7 +516,7 @@ impl Vec3vl {
         let len = r.len();
 
         match len.cmp(&words) {
-            Ordering::Less => r = [r, vec![0, (words - len) as u32]].concat(),
+            Ordering::Less => r = [r, vec![0; words - len]].concat(),
             Ordering::Equal => { },
             Ordering::Greater => { let _ = r.split_off(words); },
         };
@@ -556,8 +559,8 @@ impl Vec3vl {
         
         match len.cmp(&words) {
             Ordering::Less => { 
-                a = [a, vec![0, (words - len) as u32]].concat();
-                b = [b, vec![0, (words - len) as u32]].concat()
+                a = [a, vec![0; words - len]].concat();
+                b = [b, vec![0; words - len]].concat()
             },
             Ordering::Equal => { },
             Ordering::Greater => { 
@@ -579,6 +582,14 @@ impl Vec3vl {
         v
     }
 
+    pub fn to_clonable(&self) -> JsValue {
+        let obj = Object::new();
+        let _ = Reflect::set(&obj, &"_bits".into(), &self.bits.into());
+        let _ = Reflect::set(&obj, &"_avec".into(), &Uint32Array::from(&self.avec[..]));
+        let _ = Reflect::set(&obj, &"_bvec".into(), &Uint32Array::from(&self.bvec[..]));
+        obj.into()
+    }
+
     pub fn normalize(&mut self) {
         let lastmask = self.lastmask();
         let len_a = self.avec.len() - 1;
@@ -589,7 +600,7 @@ impl Vec3vl {
     }
 
     fn lastmask(&self) -> u32 {
-        !0u32 >> (32 - bitnum(self.bits))
+        !0u32 >> ((32 - bitnum(self.bits)) & 0x1f)
     }
 }
 
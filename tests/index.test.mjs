@@ -658,6 +658,111 @@ describe('$busslice', () => {
     });
 });
 
+const yosysMerge = vals => vals.reduce((l, r) => Vector3vl.fromArray(l.toArray().map((v, n) => v == r.get(n) ? v : 0)));
+const yosysCandidates = sel => sel.toArray().reduce((acc, v, n) => v == 1 ? acc.map(c => c + 2 ** n) : v == 0 ? acc.flatMap(c => [c, c + 2 ** n]) : acc, [0]);
+
+describe('Pmux', () => {
+    describe.each([[1, 1], [1, 3], [4, 2], [16, 2]])('%i bits, %i-select', (bits, swidth) => {
+        new SingleCellTestFixture(engine, {type: 'Pmux', parameters: { WIDTH: bits, S_WIDTH: swidth }})
+            .testFun(s => {
+                const hot = s.S.toArray().flatMap((v, n) => v == 1 ? [n] : []);
+                return { Y: !s.S.isFullyDefined || hot.length > 1 ? Vector3vl.xes(bits)
+                    : hot.length ? s.B.slice(hot[0] * bits, (hot[0] + 1) * bits) : s.A };
+            });
+    });
+});
+
+describe('$bmux', () => {
+    describe.each([[1, 1], [1, 2], [2, 2], [16, 1]])('%i bits, %i-select', (bits, swidth) => {
+        new SingleCellTestFixture(engine, {celltype: '$bmux', parameters: { WIDTH: bits, S_WIDTH: swidth }})
+            .testFun(s => ({ Y: yosysMerge(yosysCandidates(s.S).map(idx => s.A.slice(idx * bits, (idx + 1) * bits))) }));
+    });
+});
+
+describe('$demux', () => {
+    describe.each([[1, 1], [1, 2], [2, 2], [16, 1]])('%i bits, %i-select', (bits, swidth) => {
+        new SingleCellTestFixture(engine, {celltype: '$demux', parameters: { WIDTH: bits, S_WIDTH: swidth }})
+            .testFun(s => {
+                const sel = yosysCandidates(s.S);
+                const value = sel.length == 1 ? s.A : yosysMerge([s.A, Vector3vl.zeros(bits)]);
+                return { Y: Vector3vl.concat(...Array.from({ length: 2 ** swidth }, (_, n) => sel.includes(n) ? value : Vector3vl.zeros(bits))) };
+            });
+    });
+});
+
+const yosysShiftfun = (asgn, bsgn, ybits, fillx) => s => {
+    if (!s.B.isFullyDefined) return { Y: Vector3vl.xes(ybits) };
+    const amount = parseIntSign(s.B.toBin(), bsgn);
+    const a = s.A.toArray();
+    const ext = fillx ? a : a.concat(Array(Math.max(0, ybits - a.length)).fill(asgn ? a[a.length - 1] : -1));
+    return { Y: Vector3vl.fromArray(Array.from({ length: ybits }, (_, n) => ext[n + amount] ?? (fillx ? 0 : -1))) };
+};
+
+describe.each([
+["$shift", false],
+["$shiftx", true],
+])('%s', (name, fillx) => {
+    describe.each([[false, false], [true, false], [false, true], [true, true]])('A signed %s, B signed %s', (asgn, bsgn) => {
+        describe.each([[4, 2, 6], [3, 3, 2]])('%i bits by %i bits to %i bits', (abits, bbits, ybits) => {
+            new SingleCellTestFixture(engine, {celltype: name, parameters: { A_WIDTH: abits, B_WIDTH: bbits, Y_WIDTH: ybits, A_SIGNED: asgn ? 1 : 0, B_SIGNED: bsgn ? '1' : '0' }})
+                .testFun(yosysShiftfun(asgn, bsgn, ybits, fillx));
+        });
+    });
+});
+
+describe('$lut', () => {
+    describe.each([[2, '0110'], [3, 0x96], [4, '1000000000000001']])('%i bits, table %s', (bits, lut) => {
+        const table = typeof lut == 'number' ? Vector3vl.fromNumber(lut, 2 ** bits) : Vector3vl.fromBin(lut);
+        new SingleCellTestFixture(engine, {celltype: '$lut', parameters: { WIDTH: bits, LUT: lut }})
+            .testFun(s => ({ Y: yosysMerge(yosysCandidates(s.A).map(idx => Vector3vl.fromArray([table.get(idx)]))) }));
+    });
+});
+
+describe('$sop', () => {
+    // (A[0] & !A[1]) | (!A[0] & A[1])
+    new SingleCellTestFixture(engine, {celltype: '$sop', parameters: { WIDTH: 2, DEPTH: 2, TABLE: '10010110' }})
+        .testFun(s => ({ Y: s.A.reduceXor() }));
+});
+
+describe('$bweqx', () => {
+    describe.each(testBits)('%i bits', (bits) => {
+        new SingleCellTestFixture(engine, {celltype: '$bweqx', parameters: { WIDTH: bits }})
+            .testFun(s => ({ Y: Vector3vl.fromArray(s.A.toArray().map((v, n) => v == s.B.get(n) ? 1 : -1)) }));
+    });
+});
+
+describe('$bwmux', () => {
+    describe.each(testBits)('%i bits', (bits) => {
+        new SingleCellTestFixture(engine, {celltype: '$bwmux', parameters: { WIDTH: bits }})
+            .testFun(s => ({ Y: Vector3vl.concat(...s.S.toArray().map((v, n) =>
+                v == 1 ? s.B.slice(n, n + 1) : v == -1 ? s.A.slice(n, n + 1) : yosysMerge([s.A.slice(n, n + 1), s.B.slice(n, n + 1)]))) }));
+    });
+});
+
+describe('x select', () => {
+    const bmux = new SingleCellTestFixture(engine, {celltype: '$bmux', parameters: { WIDTH: 2, S_WIDTH: 2 }});
+    test('$bmux merges the selected slices', () =>
+        bmux.expectComb({ A: Vector3vl.fromBin('00110101'), S: Vector3vl.fromBin('x0') }, s => ({ Y: Vector3vl.fromBin('x1') })));
+    const demux = new SingleCellTestFixture(engine, {celltype: '$demux', parameters: { WIDTH: 2, S_WIDTH: 1 }});
+    test('$demux merges the input with zero', () =>
+        demux.expectComb({ A: Vector3vl.fromBin('10'), S: Vector3vl.fromBin('x') }, s => ({ Y: Vector3vl.fromBin('x0x0') })));
+    const bwmux = new SingleCellTestFixture(engine, {celltype: '$bwmux', parameters: { WIDTH: 4 }});
+    test('$bwmux keeps agreeing bits', () =>
+        bwmux.expectComb({ A: Vector3vl.fromBin('0011'), B: Vector3vl.fromBin('0101'), S: Vector3vl.fromBin('xxxx') }, s => ({ Y: Vector3vl.fromBin('0xx1') })));
+    const lut = new SingleCellTestFixture(engine, {celltype: '$lut', parameters: { WIDTH: 2, LUT: '1100' }});
+    test('$lut merges the table entries', () =>
+        lut.expectComb({ A: Vector3vl.fromBin('1x') }, s => ({ Y: Vector3vl.fromBin('1') })));
+    const widelut = new SingleCellTestFixture(engine, {celltype: '$lut', parameters: { WIDTH: 16, LUT: '1'.repeat(2 ** 16) }});
+    test('$lut with a wide undefined input', () =>
+        widelut.expectComb({ A: Vector3vl.xes(16) }, s => ({ Y: Vector3vl.fromBin('1') })));
+    const widebmux = new SingleCellTestFixture(engine, {celltype: '$bmux', parameters: { WIDTH: 1, S_WIDTH: 16 }});
+    test('$bmux with a wide undefined select', () =>
+        widebmux.expectComb({ A: Vector3vl.ones(2 ** 16), S: Vector3vl.xes(16) }, s => ({ Y: Vector3vl.fromBin('1') })));
+    const pmux = new SingleCellTestFixture(engine, {type: 'Pmux', parameters: { WIDTH: 2, S_WIDTH: 2 }});
+    test('Pmux outputs x', () =>
+        pmux.expectComb({ A: Vector3vl.fromBin('11'), B: Vector3vl.fromBin('1111'), S: Vector3vl.fromBin('x0') }, s => ({ Y: Vector3vl.fromBin('xx') })));
+});
+
 // TODO: better tests for stateful cells
 
 describe('$dff', () => {
@@ -758,6 +863,61 @@ describe('$fsm', () => {
     });
 });
 
+describe('$mem', () => {
+    const bits = 16, words = 4;
+    describe.each([
+        ["bit enables", {}, en => en],
+        ["byte enables", { byte_enable: true }, en => Vector3vl.fromArray(en.toArray().flatMap(b => Array(8).fill(b)))]
+    ])('partial word writes with %s', (x, options, expand) => {
+        const mem = Array(words).fill(Vector3vl.zeros(bits));
+        new SingleCellTestFixture(engine, {
+            celltype: '$mem', bits: bits, abits: 2, words: words,
+            memdata: [words, Vector3vl.zeros(bits).toBin()],
+            rdports: [{}],
+            wrports: [_.assign({ clock_polarity: true, enable_polarity: true }, options)]
+        })
+            .testFunRandomized(s => {
+                const mask = expand(s.wr0en);
+                const addr = s.wr0addr.toNumber();
+                mem[addr] = s.wr0data.and(mask).or(mem[addr].and(mask.not()));
+                return { rd0data: mem[s.rd0addr.toNumber()] };
+            }, { no_random_x: true, clock: 'wr0clk', clock_polarity: true });
+    });
+    describe('read-only', () => {
+        const rom = Array(words).fill(0).map((x, n) => Vector3vl.fromNumber(n * 0x1111, bits));
+        new SingleCellTestFixture(engine, {
+            celltype: '$mem', bits: bits, abits: 2, words: words,
+            memdata: rom.map(v => v.toBin()),
+            rdports: [{}, {}],
+            wrports: []
+        })
+            .testFunComplete(s => ({
+                rd0data: s.rd0addr.isFullyDefined ? rom[s.rd0addr.toNumber()] : Vector3vl.xes(bits),
+                rd1data: s.rd1addr.isFullyDefined ? rom[s.rd1addr.toNumber()] : Vector3vl.xes(bits)
+            }));
+    });
+    describe('large address space with fill', () => {
+        const fill = Vector3vl.fromBin('10100101', 8);
+        const fixture = new SingleCellTestFixture(engine, {
+            celltype: '$mem', bits: 8, abits: 32,
+            memfill: fill.toBin(),
+            memdata: [2, '00000001'],
+            rdports: [{}],
+            wrports: []
+        });
+        fixture.testFunRandomized(s => ({
+            rd0data: !s.rd0addr.isFullyDefined ? Vector3vl.xes(8)
+                : parseInt(s.rd0addr.toBin(), 2) < 2 ? Vector3vl.fromNumber(1, 8) : fill
+        }));
+        test('only written words are stored', () => {
+            const dut = fixture.circuit._graph.getCell('dut');
+            expect(dut.get('words')).toBe(2 ** 32);
+            expect(dut.memdata.get(2 ** 32 - 1).toBin()).toEqual(fill.toBin());
+            expect(dut.getGateParams().memdata).toEqual(['00000001', '00000001']);
+        });
+    });
+});
+
 describe('order', () => {
     // A circuit with order parameters on the ports
     const circuit = {
@@ -794,5 +954,777 @@ describe('order', () => {
 
 });
 
+describe('WasmWorkerEngine', () => {
+    const wire = {
+        devices: {
+            i: { type: "Button", label: "i" },
+            o: { type: "Lamp", label: "o" }
+        },
+        connectors: [
+            { from: { id: "i", port: "out" }, to: { id: "o", port: "in" } }
+        ]
+    };
+
+    describe('module instances', () => {
+        const circuit = {
+            subcircuits: {
+                inv: {
+                    devices: {
+                        i: { type: "Input", net: "i", order: 0, bits: 1 },
+                        n: { type: "Not", bits: 1 },
+                        o: { type: "Output", net: "o", order: 1, bits: 1 }
+                    },
+                    connectors: [
+                        { from: { id: "i", port: "out" }, to: { id: "n", port: "in" } },
+                        { from: { id: "n", port: "out" }, to: { id: "o", port: "in" } }
+                    ]
+                }
+            },
+            devices: {
+                a: { type: "Button", label: "a" },
+                b: { type: "Button", label: "b" },
+                x: { type: "Lamp", label: "x" },
+                y: { type: "Lamp", label: "y" },
+                m1: { type: "Subcircuit", celltype: "inv" },
+                m2: { type: "Subcircuit", celltype: "inv" }
+            },
+            connectors: [
+                { from: { id: "a", port: "out" }, to: { id: "m1", port: "i" } },
+                { from: { id: "b", port: "out" }, to: { id: "m2", port: "i" } },
+                { from: { id: "m1", port: "o" }, to: { id: "x", port: "in" } },
+                { from: { id: "m2", port: "o" }, to: { id: "y", port: "in" } }
+            ]
+        };
+        const fixture = new CircuitTestFixture(circuit, [{name: 'a', bits: 1}, {name: 'b', bits: 1}], [{name: 'x', bits: 1}, {name: 'y', bits: 1}], 'WasmWorkerEngine')
+            .testFunComplete(s => ({ x: s.a.not(), y: s.b.not() }));
+        test('instances share one module definition', () => {
+            expect(Object.values(fixture.circuit._engine._modules)).toEqual(['module:inv']);
+        });
+    });
+
+    describe('hierarchical paths', () => {
+        const inv = {
+            devices: {
+                i: { type: "Input", net: "i", order: 0, bits: 1 },
+                n: { type: "Not", bits: 1 },
+                o: { type: "Output", net: "o", order: 1, bits: 1 }
+            },
+            connectors: [
+                { from: { id: "i", port: "out" }, to: { id: "n", port: "in" } },
+                { from: { id: "n", port: "out" }, to: { id: "o", port: "in" } }
+            ]
+        };
+        const circuit = {
+            subcircuits: {
+                inv,
+                wrap: {
+                    devices: {
+                        i: { type: "Input", net: "i", order: 0, bits: 1 },
+                        c: { type: "Subcircuit", celltype: "inv", label: "core" },
+                        o: { type: "Output", net: "o", order: 1, bits: 1 }
+                    },
+                    connectors: [
+                        { from: { id: "i", port: "out" }, to: { id: "c", port: "i" } },
+                        { from: { id: "c", port: "o" }, to: { id: "o", port: "in" } }
+                    ]
+                }
+            },
+            devices: {
+                a: { type: "Button", label: "a" },
+                x: { type: "Lamp", label: "x" },
+                w: { type: "Subcircuit", celltype: "wrap", label: "w" }
+            },
+            connectors: [
+                { from: { id: "a", port: "out" }, to: { id: "w", port: "i" } },
+                { from: { id: "w", port: "o" }, to: { id: "x", port: "in" } }
+            ]
+        };
+        const fixture = new CircuitTestFixture(circuit, [], [], 'WasmWorkerEngine');
+        test('nested gates are listed and resolved by dotted paths', async () => {
+            const engine = fixture.circuit._engine;
+            expect(await engine.listHierarchy()).toEqual(
+                ['a', 'w', 'w.core', 'w.core.i', 'w.core.n', 'w.core.o', 'w.i', 'w.o', 'x']);
+            const core = fixture.circuit._graph.getCell('w').get('graph').getCell('c').get('graph');
+            expect(await engine.resolvePath('w.core.n')).toEqual([core.cid, 'n', 'out']);
+            expect(await engine.resolvePath('w.core.n.in')).toEqual([core.cid, 'n', 'in']);
+            await expect(engine.resolvePath('w.core.missing')).rejects.toThrow();
+        });
+        test('inner ports are monitored and driven by path', async () => {
+            const engine = fixture.circuit._engine;
+            fixture.circuit.setInput('a', Vector3vl.zero);
+            await fixture.waitUntilStable(10);
+            expect(fixture.circuit.getOutput('x').toBin()).toEqual('1');
+            const values = [];
+            const monitorId = engine.monitorPath('w.core.n.out', (tick, sig) => { values.push(sig.toBin()); });
+            engine.changeInputPath('w.core.i', Vector3vl.one);
+            await fixture.waitUntilStable(10);
+            await engine.synchronize();
+            expect(values[values.length - 1]).toEqual('0');
+            expect(fixture.circuit.getOutput('x').toBin()).toEqual('0');
+            engine.unmonitor(monitorId);
+        });
+        test('removing a subcircuit gate removes its graphs', async () => {
+            const engine = fixture.circuit._engine;
+            const wrap = fixture.circuit._graph.getCell('w').get('graph');
+            const core = wrap.getCell('c').get('graph');
+            engine._worker.postMessage({ type: 'removeGate', args: [fixture.circuit._graph.cid, 'w'] });
+            expect(await engine.listHierarchy()).toEqual(['a', 'x']);
+            await expect(engine._query('listHierarchy', wrap.cid)).rejects.toThrow();
+            await expect(engine._query('listHierarchy', core.cid)).rejects.toThrow();
+        });
+    });
+
+    describe('ambiguous paths', () => {
+        const circuit = {
+            devices: {
+                i: { type: "Button", label: "i" },
+                o1: { type: "Lamp", label: "o" },
+                o2: { type: "Lamp", label: "o" }
+            },
+            connectors: [
+                { from: { id: "i", port: "out" }, to: { id: "o1", port: "in" } },
+                { from: { id: "i", port: "out" }, to: { id: "o2", port: "in" } }
+            ]
+        };
+        const fixture = new CircuitTestFixture(circuit, [], [], 'WasmWorkerEngine');
+        test('duplicate labels are rejected', async () => {
+            await expect(fixture.circuit._engine.resolvePath('o')).rejects.toThrow(/Ambiguous path/);
+            expect(await fixture.circuit._engine.resolvePath('o1')).toEqual([fixture.circuit._graph.cid, 'o1', 'in']);
+        });
+    });
+
+    describe('removed monitored gate', () => {
+        const circuit = {
+            devices: {
+                i: { type: "Button", label: "i" },
+                o1: { type: "Lamp", label: "o1" },
+                o2: { type: "Lamp", label: "o2" }
+            },
+            connectors: [
+                { from: { id: "i", port: "out" }, to: { id: "o1", port: "in" } },
+                { from: { id: "i", port: "out" }, to: { id: "o2", port: "in" } }
+            ]
+        };
+        const fixture = new CircuitTestFixture(circuit, [], [], 'WasmWorkerEngine');
+        test('monitor is dropped without failing the simulation', async () => {
+            const engine = fixture.circuit._engine;
+            const calls = [];
+            engine.monitor(fixture.circuit._graph.getCell('o1'), 'in', (tick, sig) => { calls.push(sig); },
+                { trigger: { type: 'rising', path: 'o2.in' } });
+            engine._worker.postMessage({ type: 'removeGate', args: [fixture.circuit._graph.cid, 'o1'] });
+            fixture.circuit.setInput('i', Vector3vl.one);
+            await fixture.waitUntilStable();
+            expect(fixture.circuit.getOutput('o2').toBin()).toEqual('1');
+            expect(calls).toEqual([]);
+        });
+    });
+
+    describe('breakpoints', () => {
+        const fixture = new CircuitTestFixture(wire, [], [], 'WasmWorkerEngine');
+        test('hitting a breakpoint stops the simulation', async () => {
+            const engine = fixture.circuit._engine;
+            const changes = [];
+            engine.on('changeRunning', () => changes.push(engine.running));
+            const hit = new Promise(resolve => engine.breakpoint('o == 1', resolve));
+            engine.start();
+            fixture.circuit.setInput('i', Vector3vl.one);
+            await hit;
+            expect(engine.running).toBeFalsy();
+            expect(changes[changes.length - 1]).toBe(false);
+        });
+    });
+
+    describe('buffered monitor', () => {
+        const fixture = new CircuitTestFixture(wire, [], [], 'WasmWorkerEngine');
+        test('stopping on trigger stops the simulation', async () => {
+            const engine = fixture.circuit._engine;
+            const monitorId = engine.monitor(fixture.circuit._graph.getCell('o'), 'in', () => {},
+                { capacity: 16, stopOnTrigger: true, trigger: { type: 'rising' } });
+            const stopped = new Promise(resolve => engine.on('changeRunning', () => { if (!engine.running) resolve(); }));
+            engine.start();
+            fixture.circuit.setInput('i', Vector3vl.one);
+            await stopped;
+            expect(engine.running).toBeFalsy();
+            const samples = await engine.drainMonitor(monitorId);
+            expect(samples.ticks.length).toBe(1);
+        });
+    });
+
+    describe('FSM coverage', () => {
+        const fixture = new SingleCellTestFixture('WasmWorkerEngine', {
+            celltype: '$fsm',
+            bits: {in: 1, out: 1},
+            init_state: 0,
+            states: 2,
+            polarity: { clock: true, arst: true },
+            trans_table: [
+                {state_in: 0, state_out: 0, ctrl_in: '0', ctrl_out: '0'},
+                {state_in: 0, state_out: 1, ctrl_in: '1', ctrl_out: '0'},
+                {state_in: 1, state_out: 1, ctrl_in: '0', ctrl_out: '1'},
+                {state_in: 1, state_out: 0, ctrl_in: '1', ctrl_out: '1'},
+            ]
+        });
+        test('a held reset enters the initial state once', async () => {
+            const circuit = fixture.circuit;
+            const dut = circuit._graph.getCell('dut');
+            circuit.setInput('in', Vector3vl.one);
+            circuit.setInput('arst', Vector3vl.one);
+            await fixture.waitUntilStable();
+            const initial = (await circuit._engine.fsmCoverage(dut)).states[0];
+            for (let n = 0; n < 3; n++)
+                await fixture.clockPulse('clk', true);
+            expect((await circuit._engine.fsmCoverage(dut)).states[0]).toBe(initial);
+
+            circuit.setInput('arst', Vector3vl.zero);
+            await fixture.clockPulse('clk', true);
+            circuit.setInput('arst', Vector3vl.one);
+            await fixture.waitUntilStable();
+            circuit.setInput('in', Vector3vl.zero);
+            await fixture.clockPulse('clk', true);
+            const coverage = await circuit._engine.fsmCoverage(dut);
+            expect(coverage.states[0]).toBe(initial + 1);
+            expect(coverage.states[1]).toBe(1);
+        });
+    });
+
+    describe('power estimate', () => {
+        const inv = {
+            devices: {
+                i: { type: "Input", net: "i", order: 0, bits: 1 },
+                n: { type: "Not", bits: 1 },
+                o: { type: "Output", net: "o", order: 1, bits: 1 }
+            },
+            connectors: [
+                { from: { id: "i", port: "out" }, to: { id: "n", port: "in" } },
+                { from: { id: "n", port: "out" }, to: { id: "o", port: "in" } }
+            ]
+        };
+        const circuit = {
+            subcircuits: {
+                inv,
+                wrap: {
+                    devices: {
+                        i: { type: "Input", net: "i", order: 0, bits: 1 },
+                        c: { type: "Subcircuit", celltype: "inv", label: "core" },
+                        o: { type: "Output", net: "o", order: 1, bits: 1 }
+                    },
+                    connectors: [
+                        { from: { id: "i", port: "out" }, to: { id: "c", port: "i" } },
+                        { from: { id: "c", port: "o" }, to: { id: "o", port: "in" } }
+                    ]
+                }
+            },
+            devices: {
+                a: { type: "Button", label: "a" },
+                x: { type: "Lamp", label: "x" },
+                w: { type: "Subcircuit", celltype: "wrap", label: "w" }
+            },
+            connectors: [
+                { from: { id: "a", port: "out" }, to: { id: "w", port: "i" } },
+                { from: { id: "w", port: "o" }, to: { id: "x", port: "in" } }
+            ]
+        };
+        const fixture = new CircuitTestFixture(circuit, [{name: 'a', bits: 1}], [{name: 'x', bits: 1}], 'WasmWorkerEngine');
+        test('subcircuit boundaries are not counted twice', async () => {
+            const engine = fixture.circuit._engine;
+            await fixture.waitUntilStable();
+            engine.startPowerEstimate();
+            for (const v of [Vector3vl.one, Vector3vl.zero]) {
+                fixture.circuit.setInput('a', v);
+                await fixture.waitUntilStable();
+            }
+            const report = await engine.powerReport();
+            expect(report.toggles).toBe(4);
+            expect(Object.keys(report.subcircuits).sort()).toEqual(['w', 'w.core']);
+            expect(report.subcircuits['w'].toggles).toBe(2);
+            expect(report.subcircuits['w.core'].toggles).toBe(2);
+        });
+    });
+
+    describe('toggle coverage', () => {
+        const circuit = {
+            devices: {
+                i: { celltype: "$input", bits: 4 },
+                o: { celltype: "$output", bits: 4 }
+            },
+            connectors: [
+                { from: { id: "i", port: "out" }, to: { id: "o", port: "in" } }
+            ]
+        };
+        const fixture = new CircuitTestFixture(circuit, [{name: 'i', bits: 4}], [{name: 'o', bits: 4}], 'WasmWorkerEngine');
+        test('toggles are counted per bit', async () => {
+            const engine = fixture.circuit._engine;
+            fixture.circuit.setInput('i', Vector3vl.zeros(4));
+            await fixture.waitUntilStable();
+            engine.startCoverage();
+            for (const v of ['0011', '0000', '0001']) {
+                fixture.circuit.setInput('i', Vector3vl.fromBin(v, 4));
+                await fixture.waitUntilStable();
+            }
+            const stats = (await engine.coverageReport(fixture.circuit._graph.getCell('i'))).i.out;
+            expect(stats.bits).toBe(4);
+            expect(stats.rise).toEqual([2, 1, 0, 0]);
+            expect(stats.fall).toEqual([1, 1, 0, 0]);
+            expect(stats.toX).toEqual([0, 0, 0, 0]);
+            expect(stats.fromX).toEqual([0, 0, 0, 0]);
+            expect(stats.toggled).toBe(2);
+            expect(100 * stats.toggled / stats.bits).toBe(50);
+
+            const report = await engine.coverageReport();
+            expect(Object.keys(report).sort()).toEqual(['i', 'o']);
+            expect(report.o).toEqual({});
+            engine.stopCoverage();
+        });
+    });
+
+    describe('inertial delay', () => {
+        const circuit = {
+            devices: {
+                a: { type: "Button", label: "a" },
+                b: { type: "Button", label: "b" },
+                g: { type: "Or", bits: 1, propagation: 3 },
+                o: { type: "Lamp", label: "o" }
+            },
+            connectors: [
+                { from: { id: "a", port: "out" }, to: { id: "g", port: "in1" } },
+                { from: { id: "b", port: "out" }, to: { id: "g", port: "in2" } },
+                { from: { id: "g", port: "out" }, to: { id: "o", port: "in" } }
+            ]
+        };
+        const fixture = new CircuitTestFixture(circuit, [], [], 'WasmWorkerEngine');
+        // Applies each step of input changes one tick apart and returns the ticks,
+        // relative to the first step, at which the gate output changed.
+        async function run(steps) {
+            const engine = fixture.circuit._engine;
+            const gate = fixture.circuit._graph.getCell('g');
+            await fixture.waitUntilStable(10);
+            await engine.synchronize();
+            const start = engine.tick;
+            const changes = [];
+            const monitorId = engine.monitor(gate, 'out', (tick, sig) => { changes.push([tick - start, sig.toBin()]); });
+            for (const step of steps) {
+                for (const [name, value] of Object.entries(step))
+                    fixture.circuit.setInput(name, value);
+                await engine.updateGates({ synchronous: true });
+            }
+            await fixture.waitUntilStable(10);
+            engine.unmonitor(monitorId);
+            fixture.circuit.setInput('a', Vector3vl.zero);
+            fixture.circuit.setInput('b', Vector3vl.zero);
+            return changes;
+        }
+        test('input changes to the same output value do not delay it', async () => {
+            fixture.circuit._engine.setDelayModel(fixture.circuit._graph.getCell('g'), 'inertial');
+            const single = await run([{ a: Vector3vl.one }]);
+            const both = await run([{ a: Vector3vl.one }, { b: Vector3vl.one }]);
+            expect(single).toHaveLength(1);
+            expect(both).toEqual(single);
+        });
+        test('pulses shorter than the propagation are dropped', async () => {
+            expect(await run([{ a: Vector3vl.one }, { a: Vector3vl.zero }])).toEqual([]);
+        });
+        test('the same pulses pass with transport delay', async () => {
+            fixture.circuit._engine.setDelayModel(fixture.circuit._graph.getCell('g'), 'transport');
+            const changes = await run([{ a: Vector3vl.one }, { a: Vector3vl.zero }]);
+            expect(changes.map(([, sig]) => sig)).toEqual(['1', '0']);
+            expect(changes[1][0] - changes[0][0]).toBe(1);
+        });
+        test('port delays apply to the rising and falling edge', async () => {
+            const engine = fixture.circuit._engine;
+            const gate = fixture.circuit._graph.getCell('g');
+            const [[before]] = await run([{ a: Vector3vl.one }]);
+            engine.setPortDelay(gate, 'out', 2, 5);
+            const [[after]] = await run([{ a: Vector3vl.one }]);
+            expect(after).toBe(before - 1);
+            const changes = await run([{ a: Vector3vl.one }, { a: Vector3vl.zero }]);
+            expect(changes).toEqual([[after, '1'], [after + 4, '0']]);
+            engine.setDelayModel(gate, 'inertial');
+            expect(await run([{ a: Vector3vl.one }, { a: Vector3vl.zero }])).toEqual([]);
+        });
+    });
+
+    describe('timed gates in series', () => {
+        const circuit = {
+            devices: {
+                i: { type: "Button", label: "i" },
+                n1: { type: "Not", bits: 1 },
+                n2: { type: "Not", bits: 1 },
+                o: { type: "Lamp", label: "o" }
+            },
+            connectors: [
+                { from: { id: "i", port: "out" }, to: { id: "n1", port: "in" } },
+                { from: { id: "n1", port: "out" }, to: { id: "n2", port: "in" } },
+                { from: { id: "n2", port: "out" }, to: { id: "o", port: "in" } }
+            ]
+        };
+        const fixture = new CircuitTestFixture(circuit, [], [], 'WasmWorkerEngine');
+        test('changes propagate through both delays', async () => {
+            const engine = fixture.circuit._engine;
+            engine.setGateDelay(fixture.circuit._graph.getCell('n1'), 2, 2);
+            engine.setGateDelay(fixture.circuit._graph.getCell('n2'), 3, 3);
+            await fixture.waitUntilStable(10);
+            for (const value of [Vector3vl.one, Vector3vl.zero]) {
+                fixture.circuit.setInput('i', value);
+                await fixture.waitUntilStable(20);
+                expect(engine.hasPendingEvents).toBeFalsy();
+                expect(fixture.circuit.getOutput('o').toBin()).toEqual(value.toBin());
+            }
+        });
+    });
+
+    describe('faults', () => {
+        const fixture = new CircuitTestFixture(wire, [], [], 'WasmWorkerEngine');
+        test('a stuck-at fault with a tick stays active from that tick', async () => {
+            const engine = fixture.circuit._engine;
+            const i = fixture.circuit._graph.getCell('i');
+            await fixture.waitUntilStable();
+            const faultId = engine.addFault(i, 'out', { type: 'stuck1', tick: engine.tick + 3 });
+            await fixture.waitUntilStable(10);
+            expect(fixture.circuit.getOutput('o').toBin()).toEqual('1');
+            fixture.circuit.setInput('i', Vector3vl.one);
+            fixture.circuit.setInput('i', Vector3vl.zero);
+            await fixture.waitUntilStable(10);
+            expect(fixture.circuit.getOutput('o').toBin()).toEqual('1');
+            engine.removeFault(faultId);
+            await fixture.waitUntilStable(10);
+            expect(fixture.circuit.getOutput('o').toBin()).toEqual('0');
+        });
+    });
+
+    describe('fault campaign', () => {
+        const circuit = {
+            devices: {
+                a: { type: "Button", label: "a" },
+                b: { type: "Button", label: "b" },
+                g: { type: "And", bits: 1 },
+                h: { type: "Or", bits: 1 },
+                o: { type: "Lamp", label: "o" }
+            },
+            connectors: [
+                { from: { id: "a", port: "out" }, to: { id: "g", port: "in1" } },
+                { from: { id: "b", port: "out" }, to: { id: "g", port: "in2" } },
+                { from: { id: "a", port: "out" }, to: { id: "h", port: "in1" } },
+                { from: { id: "g", port: "out" }, to: { id: "h", port: "in2" } },
+                { from: { id: "h", port: "out" }, to: { id: "o", port: "in" } }
+            ]
+        };
+        const fixture = new CircuitTestFixture(circuit, [], [], 'WasmWorkerEngine');
+        test('redundant faults are not detected', async () => {
+            const bools = [Vector3vl.zero, Vector3vl.one];
+            const vectors = bools.flatMap(a => bools.map(b => [a, b]));
+            const result = await fixture.circuit._engine.faultCampaign({ inputs: ['a', 'b'], vectors });
+            const find = stuckAt => result.faults.find(f => f.gate == 'g' && f.port == 'out' && f.stuckAt == stuckAt);
+            expect(find(0).detected).toBeFalsy();
+            expect(find(1).detected).toBeTruthy();
+            expect(find(1).vector).toBe(0);
+            expect(result.detected).toBeLessThan(result.total);
+        });
+    });
+
+    describe('equivalence', () => {
+        const fixture = new CircuitTestFixture(wire, [], [], 'WasmWorkerEngine');
+        test('the reference does not stay in the live engine', async () => {
+            const engine = fixture.circuit._engine;
+            const reference = fixture.circuit._makeGraph(wire, {});
+            const result = await engine.checkEquivalence(reference);
+            expect(result.equivalent).toBeTruthy();
+            expect(reference.cid in engine._graphs).toBeFalsy();
+            await expect(engine._query('resolvePath', reference.cid + '.o')).rejects.toThrow();
+            fixture.circuit.setInput('i', Vector3vl.one);
+            await fixture.waitUntilStable();
+            expect(fixture.circuit.getOutput('o').toBin()).toEqual('1');
+        });
+    });
+
+    describe('memory reads', () => {
+        const fixture = new SingleCellTestFixture('WasmWorkerEngine', {
+            celltype: '$mem', bits: 8, abits: 17, words: 1 << 17,
+            memdata: [1, '00000001'],
+            rdports: [{}],
+            wrports: []
+        });
+        test('large reads are paged', async () => {
+            const engine = fixture.circuit._engine;
+            const dut = fixture.circuit._graph.getCell('dut');
+            const first = await engine.readMemory(dut);
+            expect(first.count).toBe(1 << 16);
+            expect(first.next).toBe(1 << 16);
+            expect(first.avec[0]).toBe(1);
+            const second = await engine.readMemory(dut, first.next);
+            expect(second.count).toBe(1 << 16);
+            expect(second.next).toBeNull();
+            const last = await engine.readMemory(dut, (1 << 17) - 10, 1000);
+            expect(last.count).toBe(10);
+            expect(last.next).toBeNull();
+        });
+    });
+
+    describe('memory-mapped devices', () => {
+        const fixture = new SingleCellTestFixture('WasmWorkerEngine', {
+            celltype: '$mem', bits: 8, abits: 4, words: 16,
+            rdports: [{}],
+            wrports: [{ clock_polarity: true }]
+        });
+        const writes = [];
+        let dut, host, uart;
+        async function write(addr, value) {
+            fixture.circuit.setInput('wr0addr', Vector3vl.fromNumber(addr, 4));
+            fixture.circuit.setInput('wr0data', Vector3vl.fromNumber(value, 8));
+            await fixture.clockPulse('wr0clk', true);
+        }
+        beforeAll(() => {
+            const engine = fixture.circuit._engine;
+            dut = fixture.circuit._graph.getCell('dut');
+            host = engine.addMmioRegion(dut, 8, 2, (offset, sig) => { writes.push([offset, sig.toHex()]); });
+            uart = engine.addMmioConsole(dut, 12);
+        });
+        test('reads return the values set by the host', async () => {
+            fixture.circuit._engine.setMmioValue(dut, host, 1, Vector3vl.fromNumber(0x42, 8));
+            fixture.circuit.setInput('rd0addr', Vector3vl.fromNumber(9, 4));
+            await fixture.waitUntilStable();
+            expect(fixture.circuit.getOutput('rd0data').toHex()).toEqual('42');
+        });
+        test('writes are posted to the host', async () => {
+            await write(8, 0x17);
+            expect(writes).toEqual([[0, '17']]);
+        });
+        test('the console collects written bytes', async () => {
+            await write(12, 0x48);
+            await write(12, 0x69);
+            expect(await fixture.circuit._engine.drainMmio(dut, uart)).toEqual('Hi');
+            expect(await fixture.circuit._engine.drainMmio(dut, uart)).toEqual('');
+        });
+        test('scratch engines do not post writes', async () => {
+            const count = writes.length;
+            const bit = v => Vector3vl.fromNumber(v, 1);
+            await fixture.circuit._engine.faultCampaign({
+                inputs: ['wr0addr', 'wr0data', 'wr0clk'],
+                vectors: [
+                    [Vector3vl.fromNumber(9, 4), Vector3vl.fromNumber(0x55, 8), bit(0)],
+                    [Vector3vl.fromNumber(9, 4), Vector3vl.fromNumber(0x55, 8), bit(1)]
+                ]
+            });
+            await fixture.waitUntilStable();
+            expect(writes.length).toBe(count);
+        });
+    });
+
+    describe('read-only memories', () => {
+        const fixture = new SingleCellTestFixture('WasmWorkerEngine', {
+            celltype: '$mem', bits: 8, abits: 4, words: 16,
+            memdata: ['00000001', '00000010'],
+            rdports: [{}],
+            wrports: []
+        });
+        const read = async addr => {
+            fixture.circuit.setInput('rd0addr', Vector3vl.fromNumber(addr, 4));
+            await fixture.waitUntilStable();
+            return fixture.circuit.getOutput('rd0data').toHex();
+        };
+        test('memory images', async () => {
+            const engine = fixture.circuit._engine;
+            const dut = fixture.circuit._graph.getCell('dut');
+            expect(await engine.dumpMemoryImage(dut, 'hex')).toEqual('01\n02\n');
+            engine.loadMemoryImage(dut, 'hex', '@3\nab\n');
+            expect(await read(3)).toEqual('ab');
+            expect(await read(0)).toEqual('xx');
+            expect(await engine.dumpMemoryImage(dut, 'hex')).toEqual('@3\nab\n');
+        });
+        test('memory-mapped devices', async () => {
+            const engine = fixture.circuit._engine;
+            const dut = fixture.circuit._graph.getCell('dut');
+            const device = engine.addMmioRegion(dut, 4, 1, () => {});
+            engine.setMmioValue(dut, device, 0, Vector3vl.fromNumber(0x5a, 8));
+            expect(await read(4)).toEqual('5a');
+            expect(await read(3)).toEqual('ab');
+        });
+    });
+
+    describe('memory read latency', () => {
+        const circuit = {
+            devices: {
+                a0: { celltype: "$input", bits: 2 },
+                a1: { celltype: "$input", bits: 2 },
+                m: { celltype: "$mem", bits: 4, abits: 2, words: 4,
+                     memdata: ['0000', '0101', '1010', '1111'],
+                     rdports: [{ latency: 3 }, { latency: 1 }], wrports: [] },
+                o0: { celltype: "$output", bits: 4 },
+                o1: { celltype: "$output", bits: 4 }
+            },
+            connectors: [
+                { from: { id: "a0", port: "out" }, to: { id: "m", port: "rd0addr" } },
+                { from: { id: "a1", port: "out" }, to: { id: "m", port: "rd1addr" } },
+                { from: { id: "m", port: "rd0data" }, to: { id: "o0", port: "in" } },
+                { from: { id: "m", port: "rd1data" }, to: { id: "o1", port: "in" } }
+            ]
+        };
+        const fixture = new CircuitTestFixture(circuit, [{name: 'a0', bits: 2}, {name: 'a1', bits: 2}],
+            [{name: 'o0', bits: 4}, {name: 'o1', bits: 4}], 'WasmWorkerEngine');
+        // Changes the inputs and returns the ticks, relative to the change, at which
+        // each read port output changed.
+        async function run(inputs) {
+            const engine = fixture.circuit._engine;
+            const gate = fixture.circuit._graph.getCell('m');
+            await fixture.waitUntilStable(10);
+            await engine.synchronize();
+            const start = engine.tick;
+            const changes = { rd0data: [], rd1data: [] };
+            const monitorIds = Object.keys(changes).map(port =>
+                engine.monitor(gate, port, (tick, sig) => { changes[port].push([tick - start, sig.toBin()]); }));
+            for (const [name, value] of Object.entries(inputs))
+                fixture.circuit.setInput(name, Vector3vl.fromBin(value, 2));
+            await fixture.waitUntilStable(10);
+            for (const monitorId of monitorIds) engine.unmonitor(monitorId);
+            return changes;
+        }
+        test('data arrives after the latency of the port', async () => {
+            await run({ a0: '00', a1: '00' });
+            expect(await run({ a0: '01', a1: '11' })).toEqual({ rd0data: [[3, '0101']], rd1data: [[1, '1111']] });
+        });
+        test('an address change re-reads only its port', async () => {
+            expect(await run({ a1: '10' })).toEqual({ rd0data: [], rd1data: [[1, '1010']] });
+            expect(await run({ a0: '11' })).toEqual({ rd0data: [[3, '1111']], rd1data: [] });
+            expect(fixture.circuit.getOutput('o0').toBin()).toEqual('1111');
+            expect(fixture.circuit.getOutput('o1').toBin()).toEqual('1010');
+        });
+    });
+
+    describe('static timing', () => {
+        const circuit = {
+            devices: {
+                a: { type: "Button", label: "a" },
+                b: { type: "Button", label: "b" },
+                c: { type: "Button", label: "c" },
+                n: { type: "Not", bits: 1, propagation: 2 },
+                g: { type: "And", bits: 1, propagation: 3 },
+                m: { type: "Memory", bits: 1, abits: 1, rdports: [{}], wrports: [{ clock_polarity: true }], propagation: 4 },
+                d: { type: "Dff", bits: 1, polarity: { clock: true }, propagation: 1 },
+                o: { type: "Lamp", label: "o" }
+            },
+            connectors: [
+                { from: { id: "a", port: "out" }, to: { id: "n", port: "in" } },
+                { from: { id: "n", port: "out" }, to: { id: "g", port: "in1" } },
+                { from: { id: "b", port: "out" }, to: { id: "g", port: "in2" } },
+                { from: { id: "g", port: "out" }, to: { id: "m", port: "rd0addr" } },
+                { from: { id: "b", port: "out" }, to: { id: "m", port: "wr0addr" } },
+                { from: { id: "b", port: "out" }, to: { id: "m", port: "wr0data" } },
+                { from: { id: "c", port: "out" }, to: { id: "m", port: "wr0clk" } },
+                { from: { id: "m", port: "rd0data" }, to: { id: "d", port: "in" } },
+                { from: { id: "c", port: "out" }, to: { id: "d", port: "clk" } },
+                { from: { id: "d", port: "out" }, to: { id: "o", port: "in" } }
+            ]
+        };
+        const fixture = new CircuitTestFixture(circuit, [], [], 'WasmWorkerEngine');
+        test('the critical path goes through an asynchronous memory read', async () => {
+            const report = await fixture.circuit._engine.staticTiming();
+            expect(report.delay).toBe(9);
+            expect(report.minClockPeriod).toBe(9);
+            expect(report.path).toEqual([
+                { gate: 'a', port: 'out', arrival: 0 },
+                { gate: 'n', port: 'out', arrival: 2 },
+                { gate: 'g', port: 'out', arrival: 5 },
+                { gate: 'm', port: 'rd0data', arrival: 9 },
+                { gate: 'd', port: 'in', arrival: 9 }
+            ]);
+        });
+        test('rise/fall and port delays count with the slower edge', async () => {
+            const engine = fixture.circuit._engine;
+            engine.setGateDelay(fixture.circuit._graph.getCell('n'), 1, 4);
+            engine.setPortDelay(fixture.circuit._graph.getCell('m'), 'rd0data', 7, 6);
+            const report = await engine.staticTiming();
+            expect(report.delay).toBe(14);
+            expect(report.path.map(step => step.arrival)).toEqual([0, 4, 7, 14, 14]);
+        });
+    });
+
+    describe('instances', () => {
+        const circuit = {
+            devices: {
+                a: { type: "Button", label: "a" },
+                b: { type: "Button", label: "b" },
+                c: { type: "Button", label: "c" },
+                g: { type: "And", bits: 1 },
+                d: { type: "Dff", bits: 1, polarity: { clock: true } },
+                o: { type: "Lamp", label: "o" }
+            },
+            connectors: [
+                { from: { id: "a", port: "out" }, to: { id: "g", port: "in1" } },
+                { from: { id: "b", port: "out" }, to: { id: "g", port: "in2" } },
+                { from: { id: "g", port: "out" }, to: { id: "d", port: "in" } },
+                { from: { id: "c", port: "out" }, to: { id: "d", port: "clk" } },
+                { from: { id: "d", port: "out" }, to: { id: "o", port: "in" } }
+            ]
+        };
+        const fixture = new CircuitTestFixture(circuit, [], [], 'WasmWorkerEngine');
+        const bins = sigs => sigs.map(sig => sig.toBin());
+        test('instances of one graph are driven independently', async () => {
+            const engine = fixture.circuit._engine;
+            const ids = await engine.instantiateGraph(fixture.circuit._graph, 2);
+            expect(ids.length).toBe(2);
+            expect(ids[0]).not.toEqual(ids[1]);
+
+            engine.changeInputs(ids, 'a', [Vector3vl.one, Vector3vl.one]);
+            engine.changeInputs(ids, 'b', [Vector3vl.one, Vector3vl.zero]);
+            await fixture.waitUntilStable();
+            expect(bins(await engine.getOutputs(ids, 'd', 'in'))).toEqual(['1', '0']);
+
+            engine.changeInputs(ids, 'c', [Vector3vl.one, Vector3vl.one]);
+            await fixture.waitUntilStable();
+            expect(bins(await engine.getOutputs(ids, 'o'))).toEqual(['1', '0']);
+
+            engine.changeInputs(ids.slice(1), 'b', [Vector3vl.one]);
+            await fixture.waitUntilStable();
+            expect(bins(await engine.getOutputs(ids, 'd', 'in'))).toEqual(['1', '1']);
+            expect(bins(await engine.getOutputs(ids, 'o'))).toEqual(['1', '0']);
+
+            for (const id of ids) engine.removeInstance(id);
+        });
+    });
+
+    describe('glitch detection', () => {
+        const circuit = {
+            devices: {
+                i: { type: "Button", label: "i" },
+                n: { type: "Not", bits: 1, propagation: 2 },
+                g: { type: "And", bits: 1, propagation: 1 },
+                o: { type: "Lamp", label: "o" }
+            },
+            connectors: [
+                { from: { id: "i", port: "out" }, to: { id: "n", port: "in" } },
+                { from: { id: "n", port: "out" }, to: { id: "g", port: "in1" } },
+                { from: { id: "i", port: "out" }, to: { id: "g", port: "in2" } },
+                { from: { id: "g", port: "out" }, to: { id: "o", port: "in" } }
+            ]
+        };
+        const fixture = new CircuitTestFixture(circuit, [], [], 'WasmWorkerEngine');
+        test('a reconvergent path gives a pulse, which is drained once', async () => {
+            const engine = fixture.circuit._engine;
+            fixture.circuit.setInput('i', Vector3vl.zero);
+            await fixture.waitUntilStable(10);
+            engine.startGlitchDetection(5);
+            fixture.circuit.setInput('i', Vector3vl.one);
+            await fixture.waitUntilStable(10);
+            expect(fixture.circuit.getOutput('o').toBin()).toEqual('0');
+
+            const { glitches, dropped } = await engine.drainGlitches();
+            expect(dropped).toBe(0);
+            expect(glitches.length).toBe(1);
+            expect(glitches[0].gate).toBe(fixture.circuit._graph.getCell('g'));
+            expect(glitches[0].port).toEqual('out');
+            expect(glitches[0].width).toBe(2);
+            expect(await engine.drainGlitches()).toEqual({ glitches: [], dropped: 0 });
+            engine.stopGlitchDetection();
+        });
+    });
+
+    describe('queries', () => {
+        const fixture = new CircuitTestFixture(wire, [], [], 'WasmWorkerEngine');
+        test('failed query rejects', async () => {
+            await expect(fixture.circuit._engine.resolvePath('missing')).rejects.toThrow();
+            expect(await fixture.circuit._engine.resolvePath('o')).toHaveLength(3);
+        });
+    });
+});
+
 // TODO: tests for public circuit interface
 